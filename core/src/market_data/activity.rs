@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::processor::MarketMessageType;
+
+/// Messages of each type, including ones the book rejected, e.g. a
+/// cancel for an unknown order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MessageCounts {
+    pub adds: u64,
+    pub modifies: u64,
+    pub cancels: u64,
+    pub trades: u64,
+}
+
+impl MessageCounts {
+    /// Adds, modifies and cancels.
+    pub fn orders(&self) -> u64 {
+        self.adds + self.modifies + self.cancels
+    }
+
+    /// Order messages per trade; None without trades.
+    pub fn order_to_trade_ratio(&self) -> Option<f64> {
+        ratio(self.orders(), self.trades)
+    }
+
+    pub fn trade_to_cancel_ratio(&self) -> Option<f64> {
+        ratio(self.trades, self.cancels)
+    }
+
+    pub fn cancel_to_trade_ratio(&self) -> Option<f64> {
+        ratio(self.cancels, self.trades)
+    }
+
+    fn count_mut(&mut self, message_type: &MarketMessageType) -> &mut u64 {
+        match message_type {
+            MarketMessageType::Add => &mut self.adds,
+            MarketMessageType::Modify => &mut self.modifies,
+            MarketMessageType::Cancel => &mut self.cancels,
+            MarketMessageType::Trade => &mut self.trades,
+        }
+    }
+}
+
+fn ratio(numerator: u64, denominator: u64) -> Option<f64> {
+    if denominator == 0 {
+        return None;
+    }
+    Some(numerator as f64 / denominator as f64)
+}
+
+/// Message counts over the `window` ending at `end_ns`, the latest message
+/// timestamp of the symbol. Counts are exact, not bucketed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderActivity {
+    pub window: Duration,
+    pub end_ns: u64,
+    pub counts: MessageCounts,
+}
+
+/// Timestamps of recent messages by type, kept back as far as the longest
+/// configured window.
+#[derive(Debug)]
+pub(crate) struct ActivityTracker {
+    windows: Vec<Duration>,
+    horizon_ns: u64,
+    latest_ns: u64,
+    // Sorted; indexed in the order of `MessageCounts` fields
+    timestamps: [VecDeque<u64>; 4],
+    totals: MessageCounts,
+}
+
+impl ActivityTracker {
+    pub(crate) fn new(windows: &[Duration]) -> Self {
+        let horizon_ns = windows.iter()
+            .map(|window| u64::try_from(window.as_nanos()).unwrap_or(u64::MAX))
+            .max()
+            .unwrap_or(0);
+        ActivityTracker {
+            windows: windows.to_vec(),
+            horizon_ns,
+            latest_ns: 0,
+            timestamps: Default::default(),
+            totals: MessageCounts::default(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, message_type: &MarketMessageType, timestamp_ns: u64) {
+        *self.totals.count_mut(message_type) += 1;
+        self.latest_ns = self.latest_ns.max(timestamp_ns);
+
+        // Late messages are rare, so inserting into the deque is cheap
+        let timestamps = &mut self.timestamps[slot(message_type)];
+        let index = timestamps.partition_point(|timestamp| *timestamp <= timestamp_ns);
+        timestamps.insert(index, timestamp_ns);
+
+        if let Some(cutoff) = self.latest_ns.checked_sub(self.horizon_ns) {
+            for timestamps in &mut self.timestamps {
+                while timestamps.front().is_some_and(|timestamp| *timestamp <= cutoff) {
+                    timestamps.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Everything processed since the symbol was first seen.
+    pub(crate) fn totals(&self) -> MessageCounts {
+        self.totals
+    }
+
+    /// Counts for each configured window.
+    pub(crate) fn activity(&self) -> Vec<OrderActivity> {
+        self.windows.iter().map(|window| self.window(*window)).collect()
+    }
+
+    // Messages in `(latest - window, latest]`
+    fn window(&self, window: Duration) -> OrderActivity {
+        let window_ns = u64::try_from(window.as_nanos()).unwrap_or(u64::MAX);
+        let count = |timestamps: &VecDeque<u64>| {
+            let from = match self.latest_ns.checked_sub(window_ns) {
+                Some(start) => timestamps.partition_point(|timestamp| *timestamp <= start),
+                None => 0,
+            };
+            (timestamps.len() - from) as u64
+        };
+        OrderActivity {
+            window,
+            end_ns: self.latest_ns,
+            counts: MessageCounts {
+                adds: count(&self.timestamps[0]),
+                modifies: count(&self.timestamps[1]),
+                cancels: count(&self.timestamps[2]),
+                trades: count(&self.timestamps[3]),
+            },
+        }
+    }
+}
+
+fn slot(message_type: &MarketMessageType) -> usize {
+    match message_type {
+        MarketMessageType::Add => 0,
+        MarketMessageType::Modify => 1,
+        MarketMessageType::Cancel => 2,
+        MarketMessageType::Trade => 3,
+    }
+}
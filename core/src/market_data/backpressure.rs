@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use super::processor::ShardMessage;
+use super::symbols::SymbolId;
+
+/// What `submit_message` does when a shard's queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackpressurePolicy {
+    /// Wait until the worker frees a slot.
+    #[default]
+    Block,
+    /// Wait up to the given duration, then reject the message.
+    BlockWithTimeout(Duration),
+    /// Reject the message immediately.
+    FailFast,
+    /// Discard the message and report success to the producer.
+    DropNewest,
+    /// Park conflatable messages and keep only the newest one per symbol
+    /// until the worker has caught up with that symbol. Order and trade
+    /// events are never conflated; they are rejected as with `FailFast`.
+    ConflateLatest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BackpressureStats {
+    pub dropped: u64,
+    pub rejected: u64,
+    pub conflated: u64,
+    pub queue_high_water_mark: usize,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct BackpressureCounters {
+    dropped: AtomicU64,
+    rejected: AtomicU64,
+    conflated: AtomicU64,
+    queue_high_water_mark: AtomicUsize,
+}
+
+impl BackpressureCounters {
+    pub(crate) fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_conflated(&self) {
+        self.conflated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_queue_len(&self, len: usize) {
+        self.queue_high_water_mark.fetch_max(len, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> BackpressureStats {
+        BackpressureStats {
+            dropped: self.dropped.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            conflated: self.conflated.load(Ordering::Relaxed),
+            queue_high_water_mark: self.queue_high_water_mark.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Per-shard bookkeeping for `ConflateLatest`. A parked message may only be
+/// applied once every message queued before it for the same symbol has been
+/// processed, otherwise per-symbol ordering would break.
+#[derive(Debug, Default)]
+pub(crate) struct ConflationState {
+    queued: HashMap<SymbolId, usize>,
+    pending: HashMap<SymbolId, ShardMessage>,
+    // Parked symbols with nothing left in the queue, released on the next
+    // processed message
+    ready: Vec<SymbolId>,
+}
+
+impl ConflationState {
+    pub(crate) fn has_pending(&self, symbol: SymbolId) -> bool {
+        self.pending.contains_key(&symbol)
+    }
+
+    /// Parks a message, returning true if it replaced an older one.
+    pub(crate) fn park(&mut self, message: ShardMessage) -> bool {
+        let symbol = message.symbol;
+        let replaced = self.pending.insert(symbol, message).is_some();
+        if !replaced && !self.queued.contains_key(&symbol) {
+            self.ready.push(symbol);
+        }
+        replaced
+    }
+
+    pub(crate) fn record_queued(&mut self, symbol: SymbolId) {
+        *self.queued.entry(symbol).or_insert(0) += 1;
+    }
+
+    /// Marks a queued message as processed and returns the parked messages
+    /// that are now safe to apply.
+    pub(crate) fn record_processed(&mut self, symbol: SymbolId) -> Vec<ShardMessage> {
+        if let Some(count) = self.queued.get_mut(&symbol) {
+            *count -= 1;
+            if *count == 0 {
+                self.queued.remove(&symbol);
+                if self.pending.contains_key(&symbol) {
+                    self.ready.push(symbol);
+                }
+            }
+        }
+
+        if self.ready.is_empty() {
+            return Vec::new();
+        }
+        let ready = std::mem::take(&mut self.ready);
+        ready.iter().filter_map(|symbol| self.pending.remove(symbol)).collect()
+    }
+
+    pub(crate) fn take_all_pending(&mut self) -> Vec<ShardMessage> {
+        self.ready.clear();
+        self.pending.drain().map(|(_, message)| message).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::processor::MarketMessageType;
+    use super::super::symbols::SymbolRegistry;
+
+    fn message(symbol: SymbolId, timestamp_ns: u64) -> ShardMessage {
+        ShardMessage {
+            symbol,
+            timestamp_ns,
+            message_type: MarketMessageType::Trade,
+            order_id: None,
+            price: None,
+            quantity: None,
+            is_buy: None,
+        }
+    }
+
+    #[test]
+    fn parked_message_waits_for_its_symbol_to_drain() {
+        let symbols = SymbolRegistry::new();
+        let (a, b) = (symbols.intern("A").unwrap(), symbols.intern("B").unwrap());
+        let mut state = ConflationState::default();
+        state.record_queued(a);
+        state.record_queued(a);
+        state.record_queued(b);
+
+        assert!(!state.park(message(a, 3)));
+        assert!(state.park(message(a, 4)));
+        assert!(state.record_processed(b).is_empty());
+        assert!(state.record_processed(a).is_empty());
+
+        let ready = state.record_processed(a);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].timestamp_ns, 4);
+        assert!(!state.has_pending(a));
+    }
+
+    #[test]
+    fn message_parked_with_nothing_queued_is_released_by_any_symbol() {
+        let symbols = SymbolRegistry::new();
+        let (a, b) = (symbols.intern("A").unwrap(), symbols.intern("B").unwrap());
+        let mut state = ConflationState::default();
+        state.record_queued(b);
+        state.park(message(a, 1));
+
+        let ready = state.record_processed(b);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].symbol, a);
+        assert!(state.take_all_pending().is_empty());
+    }
+}
@@ -0,0 +1,493 @@
+use std::time::Duration;
+
+use super::calendar::TradingCalendar;
+use super::chunked::ChunkedVec;
+use super::fixed_point::{Precision, Price, Quantity, Total};
+
+/// How a bar series is cut. All but `Time` close on the trade that reaches
+/// the threshold, which is included whole, and carry over session boundaries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarSpec {
+    /// Fixed intervals aligned to the start of the session segment (pre-open,
+    /// regular hours or post-close) they fall in; a bar never spans a segment
+    /// boundary, so the last bar of a segment may be short. Bars close on the
+    /// symbol's own event time, when its first message at or after the bar's
+    /// end arrives, so an illiquid symbol's last bar stays open until then
+    /// regardless of other symbols or the wall clock.
+    Time(Duration),
+    /// A fixed number of trades.
+    Ticks(u64),
+    /// A fixed traded quantity.
+    Volume(f64),
+    /// A fixed traded notional.
+    Dollar(f64),
+    /// Closes once the absolute sum of trade signs reaches the expected bar
+    /// length times the expected absolute sign, both EWMAs over the last
+    /// `span` bars. The first bar is cut after `initial_ticks` trades to seed
+    /// the expectations. The threshold is floored at the square root of the
+    /// expected bar length, what balanced flow drifts to over a bar, so
+    /// balanced bars don't shrink the next ones to a single trade.
+    TickImbalance { initial_ticks: u64, span: usize },
+    /// As `TickImbalance`, with signs weighted by trade quantity.
+    VolumeImbalance { initial_ticks: u64, span: usize },
+}
+
+/// What to do with time intervals that have no trades.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyBarPolicy {
+    /// Emit nothing; the series has holes.
+    #[default]
+    Skip,
+    /// Emit flat zero-volume bars at the previous close, up to the end of the
+    /// session segment the previous bar belonged to.
+    CarryForward,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bar {
+    pub start_ns: u64,
+    /// Exclusive; one past the last trade for bars not cut by time.
+    pub end_ns: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub vwap: f64,
+    pub trade_count: u64,
+    /// Volume by aggressor side; unclassified trades are split evenly.
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+}
+
+/// Completed bars of one series plus the bar still being built.
+#[derive(Debug, Clone)]
+pub struct BarSeries {
+    pub spec: BarSpec,
+    pub completed: ChunkedVec<Bar>,
+    pub current: Option<Bar>,
+}
+
+impl BarSeries {
+    /// Completed bars starting within `[start_ns, end_ns]`.
+    pub fn range(&self, start_ns: u64, end_ns: u64) -> Vec<Bar> {
+        let from = self.completed.partition_point(|bar| bar.start_ns < start_ns);
+        let to = self.completed.partition_point(|bar| bar.start_ns <= end_ns);
+        self.completed.range(from, to).copied().collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OpenBar {
+    start_ns: u64,
+    end_ns: u64,
+    segment_end_ns: u64,
+    open: Price,
+    high: Price,
+    low: Price,
+    close: Price,
+    volume: Total,
+    notional: Total,
+    trade_count: u64,
+    buy_volume: f64,
+    sell_volume: f64,
+}
+
+// Where the last emitted bar ended, for filling empty intervals after it
+#[derive(Debug, Clone, Copy)]
+struct LastBar {
+    end_ns: u64,
+    segment_end_ns: u64,
+    close: Price,
+}
+
+// Running imbalance of the open bar and the expectations its threshold is
+// derived from
+#[derive(Debug, Clone, Copy)]
+struct ImbalanceState {
+    alpha: f64,
+    expected_ticks: f64,
+    // Expected signed imbalance per trade; None until the first bar closes
+    expected_imbalance: Option<f64>,
+    // Expected absolute weight per trade, 1 for tick imbalance
+    expected_weight: f64,
+    imbalance: f64,
+    weight: f64,
+}
+
+impl ImbalanceState {
+    fn new(initial_ticks: u64, span: usize) -> Self {
+        ImbalanceState {
+            alpha: 2.0 / (span.max(1) as f64 + 1.0),
+            expected_ticks: initial_ticks.max(1) as f64,
+            expected_imbalance: None,
+            expected_weight: 1.0,
+            imbalance: 0.0,
+            weight: 0.0,
+        }
+    }
+
+    fn add(&mut self, sign: f64, weight: f64) {
+        self.imbalance += sign * weight;
+        self.weight += weight;
+    }
+
+    fn is_reached(&self, trade_count: u64) -> bool {
+        match self.expected_imbalance {
+            Some(per_trade) => {
+                // A random walk of n steps drifts about sqrt(n) steps from zero
+                let floor = self.expected_weight / self.expected_ticks.sqrt();
+                self.imbalance.abs() >= self.expected_ticks * per_trade.abs().max(floor)
+            },
+            None => trade_count as f64 >= self.expected_ticks,
+        }
+    }
+
+    fn close(&mut self, trade_count: u64) {
+        let ticks = trade_count.max(1) as f64;
+        let per_trade = self.imbalance / ticks;
+        let weight = self.weight / ticks;
+        self.expected_ticks += self.alpha * (ticks - self.expected_ticks);
+        match self.expected_imbalance {
+            Some(expected) => {
+                self.expected_imbalance = Some(expected + self.alpha * (per_trade - expected));
+                self.expected_weight += self.alpha * (weight - self.expected_weight);
+            },
+            None => {
+                self.expected_imbalance = Some(per_trade);
+                self.expected_weight = weight;
+            },
+        }
+        self.imbalance = 0.0;
+        self.weight = 0.0;
+    }
+}
+
+/// Builds one bar series for one symbol. A time bar closes when the first
+/// message at or after its end is applied, so closing depends on the
+/// symbol's own activity rather than wall time.
+#[derive(Debug)]
+pub(crate) struct BarBuilder {
+    spec: BarSpec,
+    precision: Precision,
+    empty_bars: EmptyBarPolicy,
+    max_bars: usize,
+    current: Option<OpenBar>,
+    last: Option<LastBar>,
+    imbalance: Option<ImbalanceState>,
+    completed: ChunkedVec<Bar>,
+}
+
+impl BarBuilder {
+    pub(crate) fn new(spec: BarSpec, precision: Precision, empty_bars: EmptyBarPolicy, max_bars: usize) -> Self {
+        BarBuilder {
+            spec,
+            precision,
+            empty_bars,
+            max_bars,
+            current: None,
+            last: None,
+            imbalance: match spec {
+                BarSpec::TickImbalance { initial_ticks, span } | BarSpec::VolumeImbalance { initial_ticks, span } => {
+                    Some(ImbalanceState::new(initial_ticks, span))
+                },
+                _ => None,
+            },
+            completed: ChunkedVec::new(),
+        }
+    }
+
+    /// Closes time bars that ended at or before `timestamp_ns`.
+    pub(crate) fn advance(&mut self, timestamp_ns: u64, calendar: &TradingCalendar, closed: &mut Vec<(BarSpec, Bar)>) {
+        if !matches!(self.spec, BarSpec::Time(_)) {
+            return;
+        }
+        if let Some(bar) = self.current.filter(|bar| timestamp_ns >= bar.end_ns) {
+            self.current = None;
+            self.finish(bar, closed);
+        }
+        if self.empty_bars == EmptyBarPolicy::CarryForward && self.current.is_none() {
+            self.fill_until(timestamp_ns, calendar, closed);
+        }
+    }
+
+    /// Adds a trade. Trades older than the time bar being built are ignored,
+    /// since the bars they belong to have already been emitted; bars cut by
+    /// activity take trades in the order they are applied.
+    pub(crate) fn add_trade(&mut self, timestamp_ns: u64, price: Price, quantity: Quantity, buy_fraction: f64, calendar: &TradingCalendar, closed: &mut Vec<(BarSpec, Bar)>) {
+        self.advance(timestamp_ns, calendar, closed);
+        let is_time = matches!(self.spec, BarSpec::Time(_));
+        let window = match self.spec {
+            BarSpec::Time(interval) if self.current.is_none() => self.window(interval, timestamp_ns, calendar),
+            // The bar starts with its first trade
+            _ => (timestamp_ns, timestamp_ns.saturating_add(1), u64::MAX),
+        };
+
+        let bar = match &mut self.current {
+            Some(bar) if is_time && timestamp_ns < bar.start_ns => return,
+            Some(bar) => bar,
+            None => {
+                if is_time && self.last.is_some_and(|last| timestamp_ns < last.end_ns) {
+                    return;
+                }
+                let (start_ns, end_ns, segment_end_ns) = window;
+                self.current.insert(OpenBar {
+                    start_ns,
+                    end_ns,
+                    segment_end_ns,
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    volume: Total::ZERO,
+                    notional: Total::ZERO,
+                    trade_count: 0,
+                    buy_volume: 0.0,
+                    sell_volume: 0.0,
+                })
+            },
+        };
+
+        bar.high = bar.high.max(price);
+        bar.low = bar.low.min(price);
+        bar.close = price;
+        bar.volume += self.precision.quantity_total(quantity);
+        bar.notional += self.precision.notional(price, quantity);
+        bar.trade_count += 1;
+        let size = self.precision.quantity_to_f64(quantity);
+        bar.buy_volume += size * buy_fraction;
+        bar.sell_volume += size * (1.0 - buy_fraction);
+        if !is_time {
+            bar.end_ns = bar.end_ns.max(timestamp_ns.saturating_add(1));
+        }
+
+        // Signed in [-1, 1]; fractional under bulk volume classification
+        let sign = 2.0 * buy_fraction - 1.0;
+        let full = match self.spec {
+            BarSpec::Time(_) => false,
+            BarSpec::Ticks(ticks) => bar.trade_count >= ticks.max(1),
+            BarSpec::Volume(volume) => self.precision.quantity_total_to_f64(bar.volume) >= volume,
+            BarSpec::Dollar(notional) => self.precision.notional_to_f64(bar.notional) >= notional,
+            BarSpec::TickImbalance { .. } | BarSpec::VolumeImbalance { .. } => {
+                let weight = if matches!(self.spec, BarSpec::VolumeImbalance { .. }) { size } else { 1.0 };
+                self.imbalance.as_mut().is_some_and(|state| {
+                    state.add(sign, weight);
+                    state.is_reached(bar.trade_count)
+                })
+            },
+        };
+
+        if full {
+            let bar = *bar;
+            self.current = None;
+            if let Some(state) = &mut self.imbalance {
+                state.close(bar.trade_count);
+            }
+            self.finish(bar, closed);
+        }
+    }
+
+    pub(crate) fn series(&self) -> BarSeries {
+        BarSeries {
+            spec: self.spec,
+            completed: self.completed.clone(),
+            current: self.current.map(|bar| self.to_bar(&bar)),
+        }
+    }
+
+    // Time bar window containing a timestamp, and the end of its session segment
+    fn window(&self, interval: Duration, timestamp_ns: u64, calendar: &TradingCalendar) -> (u64, u64, u64) {
+        let interval_ns = (interval.as_nanos() as u64).max(1);
+
+        let day = calendar.trading_day(timestamp_ns);
+        let (segment_start_ns, segment_end_ns) = match calendar.session_hours(day.date) {
+            Some((open, close)) if timestamp_ns >= open && timestamp_ns < close => (open, close),
+            Some((open, _)) if timestamp_ns < open => (day.start_ns, open),
+            Some((_, close)) => (close.max(day.start_ns), day.end_ns),
+            None => (day.start_ns, day.end_ns),
+        };
+
+        let start_ns = segment_start_ns + (timestamp_ns - segment_start_ns) / interval_ns * interval_ns;
+        (start_ns, (start_ns + interval_ns).min(segment_end_ns), segment_end_ns)
+    }
+
+    fn fill_until(&mut self, timestamp_ns: u64, calendar: &TradingCalendar, closed: &mut Vec<(BarSpec, Bar)>) {
+        let (mut last, interval) = match (self.last, self.spec) {
+            (Some(last), BarSpec::Time(interval)) => (last, interval),
+            _ => return,
+        };
+
+        // Bounded by max_bars since anything beyond that would be evicted anyway
+        for _ in 0..self.max_bars {
+            if last.end_ns >= last.segment_end_ns {
+                break;
+            }
+            let (start_ns, end_ns, _) = self.window(interval, last.end_ns, calendar);
+            if end_ns > timestamp_ns {
+                break;
+            }
+            let flat = OpenBar {
+                start_ns,
+                end_ns,
+                segment_end_ns: last.segment_end_ns,
+                open: last.close,
+                high: last.close,
+                low: last.close,
+                close: last.close,
+                volume: Total::ZERO,
+                notional: Total::ZERO,
+                trade_count: 0,
+                buy_volume: 0.0,
+                sell_volume: 0.0,
+            };
+            self.finish(flat, closed);
+            last = LastBar { end_ns, ..last };
+        }
+    }
+
+    fn finish(&mut self, bar: OpenBar, closed: &mut Vec<(BarSpec, Bar)>) {
+        let finished = self.to_bar(&bar);
+        self.completed.push(finished);
+        self.completed.remove_front(self.completed.len().saturating_sub(self.max_bars));
+
+        self.last = Some(LastBar {
+            end_ns: bar.end_ns,
+            segment_end_ns: bar.segment_end_ns,
+            close: bar.close,
+        });
+        closed.push((self.spec, finished));
+    }
+
+    fn to_bar(&self, bar: &OpenBar) -> Bar {
+        let precision = &self.precision;
+        let close = precision.price_to_f64(bar.close);
+        Bar {
+            start_ns: bar.start_ns,
+            end_ns: bar.end_ns,
+            open: precision.price_to_f64(bar.open),
+            high: precision.price_to_f64(bar.high),
+            low: precision.price_to_f64(bar.low),
+            close,
+            volume: precision.quantity_total_to_f64(bar.volume),
+            vwap: precision.average_price(bar.notional, bar.volume).unwrap_or(close),
+            trade_count: bar.trade_count,
+            buy_volume: bar.buy_volume,
+            sell_volume: bar.sell_volume,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixed_point::FixedScale;
+
+    fn builder(spec: BarSpec) -> BarBuilder {
+        let precision = Precision::new(FixedScale::decimals(2), FixedScale::decimals(0));
+        BarBuilder::new(spec, precision, EmptyBarPolicy::Skip, 100)
+    }
+
+    // Feeds (timestamp, price, quantity, buy fraction) trades and returns the closed bars
+    fn run(builder: &mut BarBuilder, trades: &[(u64, f64, f64, f64)]) -> Vec<Bar> {
+        let calendar = TradingCalendar::always_open();
+        let mut closed = Vec::new();
+        for &(timestamp_ns, price, quantity, buy_fraction) in trades {
+            let price = builder.precision.price(price).unwrap();
+            let quantity = builder.precision.quantity(quantity).unwrap();
+            builder.add_trade(timestamp_ns, price, quantity, buy_fraction, &calendar, &mut closed);
+        }
+        closed.into_iter().map(|(_, bar)| bar).collect()
+    }
+
+    #[test]
+    fn tick_bars_close_on_the_nth_trade() {
+        let mut builder = builder(BarSpec::Ticks(3));
+        let trades = [(10, 100.0, 1.0, 1.0), (20, 101.0, 2.0, 0.0), (30, 99.0, 1.0, 0.5), (40, 100.0, 1.0, 1.0)];
+        let bars = run(&mut builder, &trades);
+
+        assert_eq!(bars, vec![Bar {
+            start_ns: 10,
+            end_ns: 31,
+            open: 100.0,
+            high: 101.0,
+            low: 99.0,
+            close: 99.0,
+            volume: 4.0,
+            vwap: 100.25,
+            trade_count: 3,
+            buy_volume: 1.5,
+            sell_volume: 2.5,
+        }]);
+        let series = builder.series();
+        assert_eq!(series.completed.len(), 1);
+        assert_eq!(series.current.map(|bar| (bar.start_ns, bar.trade_count)), Some((40, 1)));
+    }
+
+    #[test]
+    fn volume_bars_include_the_crossing_trade_whole() {
+        let mut builder = builder(BarSpec::Volume(10.0));
+        let trades = [(1, 100.0, 4.0, 1.0), (2, 100.0, 4.0, 1.0), (3, 100.0, 4.0, 1.0), (4, 100.0, 9.0, 1.0), (5, 100.0, 1.0, 1.0)];
+        let bars = run(&mut builder, &trades);
+
+        let cuts: Vec<_> = bars.iter().map(|bar| (bar.start_ns, bar.trade_count, bar.volume)).collect();
+        assert_eq!(cuts, vec![(1, 3, 12.0), (4, 2, 10.0)]);
+    }
+
+    #[test]
+    fn dollar_bars_close_on_traded_notional() {
+        let mut builder = builder(BarSpec::Dollar(1000.0));
+        let trades = [(1, 100.0, 4.0, 1.0), (2, 200.0, 2.0, 1.0), (3, 50.0, 4.0, 1.0), (4, 500.0, 2.0, 1.0)];
+        let bars = run(&mut builder, &trades);
+
+        let cuts: Vec<_> = bars.iter().map(|bar| (bar.start_ns, bar.trade_count, bar.vwap)).collect();
+        assert_eq!(cuts, vec![(1, 3, 100.0), (4, 1, 500.0)]);
+    }
+
+    #[test]
+    fn tick_imbalance_bars_close_on_the_expected_imbalance() {
+        // With a span of 1 each bar's expectations are exactly the last bar's
+        let mut builder = builder(BarSpec::TickImbalance { initial_ticks: 4, span: 1 });
+        let trades = [
+            // Seed bar of 4 trades, imbalance 2, so the threshold becomes 4 * 0.5
+            (1, 100.0, 1.0, 1.0), (2, 100.0, 1.0, 1.0), (3, 100.0, 1.0, 1.0), (4, 100.0, 1.0, 0.0),
+            // Reaches |2| after two buys; threshold stays 2 * 1
+            (5, 100.0, 1.0, 1.0), (6, 100.0, 1.0, 1.0),
+            // Sells count towards the absolute imbalance too
+            (7, 100.0, 1.0, 1.0), (8, 100.0, 1.0, 0.0), (9, 100.0, 1.0, 0.0), (10, 100.0, 1.0, 0.0),
+        ];
+        let bars = run(&mut builder, &trades);
+
+        let counts: Vec<_> = bars.iter().map(|bar| bar.trade_count).collect();
+        assert_eq!(counts, vec![4, 2, 4]);
+    }
+
+    #[test]
+    fn volume_imbalance_bars_weight_signs_by_quantity() {
+        let mut builder = builder(BarSpec::VolumeImbalance { initial_ticks: 2, span: 1 });
+        let trades = [
+            // Seed imbalance 6 over 2 trades: threshold 2 * 3
+            (1, 100.0, 5.0, 1.0), (2, 100.0, 1.0, 1.0),
+            // A single large sell is enough
+            (3, 100.0, 1.0, 1.0), (4, 100.0, 8.0, 0.0),
+        ];
+        let bars = run(&mut builder, &trades);
+
+        let cuts: Vec<_> = bars.iter().map(|bar| (bar.trade_count, bar.buy_volume, bar.sell_volume)).collect();
+        assert_eq!(cuts, vec![(2, 6.0, 0.0), (2, 1.0, 8.0)]);
+    }
+
+    #[test]
+    fn balanced_imbalance_bars_do_not_collapse_to_single_trades() {
+        let mut builder = builder(BarSpec::TickImbalance { initial_ticks: 4, span: 1 });
+        // A balanced seed bar expects no imbalance; the floor keeps the
+        // threshold at sqrt(4) = 2 instead of 0
+        let mut trades = vec![(1, 100.0, 1.0, 1.0), (2, 100.0, 1.0, 0.0), (3, 100.0, 1.0, 1.0), (4, 100.0, 1.0, 0.0)];
+        trades.extend((5..10).map(|timestamp_ns| (timestamp_ns, 100.0, 1.0, if timestamp_ns % 2 == 0 { 0.0 } else { 1.0 })));
+        trades.push((10, 100.0, 1.0, 1.0));
+        let bars = run(&mut builder, &trades);
+
+        let counts: Vec<_> = bars.iter().map(|bar| bar.trade_count).collect();
+        assert_eq!(counts, vec![4, 6]);
+        assert_eq!(builder.series().current, None);
+    }
+}
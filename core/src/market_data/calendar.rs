@@ -0,0 +1,542 @@
+use std::collections::BTreeSet;
+
+use super::fixed_point::{Precision, Price, Quantity, Total};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CalendarDate {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl CalendarDate {
+    pub fn new(year: i32, month: u32, day: u32) -> Self {
+        CalendarDate { year, month, day }
+    }
+
+    // Howard Hinnant's days_from_civil
+    fn to_days(self) -> i64 {
+        let year = if self.month <= 2 { self.year as i64 - 1 } else { self.year as i64 };
+        let era = if year >= 0 { year } else { year - 399 } / 400;
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    fn from_days(days: i64) -> Self {
+        let z = days + 719_468;
+        let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+        let day_of_era = z - era * 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+        CalendarDate { year, month, day }
+    }
+
+    /// 0 = Monday .. 6 = Sunday
+    pub fn weekday(self) -> u32 {
+        (self.to_days() + 3).rem_euclid(7) as u32
+    }
+
+    pub fn is_weekend(self) -> bool {
+        self.weekday() >= 5
+    }
+
+    pub fn succ(self) -> Self {
+        Self::from_days(self.to_days() + 1)
+    }
+
+    pub fn pred(self) -> Self {
+        Self::from_days(self.to_days() - 1)
+    }
+}
+
+/// Daylight saving rules supported by `ExchangeTimeZone`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DstRule {
+    /// Second Sunday of March to first Sunday of November, 02:00 local.
+    UnitedStates,
+    /// Last Sunday of March to last Sunday of October, 01:00 UTC.
+    EuropeanUnion,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExchangeTimeZone {
+    pub standard_offset_secs: i64,
+    pub dst: Option<DstRule>,
+}
+
+impl ExchangeTimeZone {
+    pub const UTC: ExchangeTimeZone = ExchangeTimeZone { standard_offset_secs: 0, dst: None };
+    pub const NEW_YORK: ExchangeTimeZone = ExchangeTimeZone { standard_offset_secs: -5 * 3600, dst: Some(DstRule::UnitedStates) };
+    pub const CHICAGO: ExchangeTimeZone = ExchangeTimeZone { standard_offset_secs: -6 * 3600, dst: Some(DstRule::UnitedStates) };
+    pub const LONDON: ExchangeTimeZone = ExchangeTimeZone { standard_offset_secs: 0, dst: Some(DstRule::EuropeanUnion) };
+    pub const FRANKFURT: ExchangeTimeZone = ExchangeTimeZone { standard_offset_secs: 3600, dst: Some(DstRule::EuropeanUnion) };
+
+    pub fn fixed(offset_secs: i64) -> Self {
+        ExchangeTimeZone { standard_offset_secs: offset_secs, dst: None }
+    }
+
+    /// UTC offset in effect at a UTC instant given in seconds.
+    pub fn offset_at(&self, utc_secs: i64) -> i64 {
+        let rule = match self.dst {
+            Some(rule) => rule,
+            None => return self.standard_offset_secs,
+        };
+        let year = CalendarDate::from_days(utc_secs.div_euclid(SECONDS_PER_DAY)).year;
+
+        let (start, end) = match rule {
+            DstRule::UnitedStates => {
+                let start_day = nth_sunday(year, 3, 2);
+                let end_day = nth_sunday(year, 11, 1);
+                (
+                    start_day.to_days() * SECONDS_PER_DAY + 2 * 3600 - self.standard_offset_secs,
+                    end_day.to_days() * SECONDS_PER_DAY + 2 * 3600 - self.standard_offset_secs - 3600,
+                )
+            },
+            DstRule::EuropeanUnion => (
+                last_sunday(year, 3).to_days() * SECONDS_PER_DAY + 3600,
+                last_sunday(year, 10).to_days() * SECONDS_PER_DAY + 3600,
+            ),
+        };
+
+        if utc_secs >= start && utc_secs < end {
+            self.standard_offset_secs + 3600
+        } else {
+            self.standard_offset_secs
+        }
+    }
+
+    /// UTC instant in seconds of a local wall-clock time. Times that fall in
+    /// a DST transition resolve to the standard-time reading.
+    pub fn local_to_utc(&self, date: CalendarDate, local_secs_of_day: i64) -> i64 {
+        let local = date.to_days() * SECONDS_PER_DAY + local_secs_of_day;
+        let standard = local - self.standard_offset_secs;
+        if self.offset_at(standard - 3600) != self.standard_offset_secs {
+            standard - 3600
+        } else {
+            standard
+        }
+    }
+
+    fn local_date(&self, utc_secs: i64) -> (CalendarDate, i64) {
+        let local = utc_secs + self.offset_at(utc_secs);
+        (CalendarDate::from_days(local.div_euclid(SECONDS_PER_DAY)), local.rem_euclid(SECONDS_PER_DAY))
+    }
+}
+
+fn nth_sunday(year: i32, month: u32, n: u32) -> CalendarDate {
+    let first = CalendarDate::new(year, month, 1);
+    let to_sunday = (6 - first.weekday()) % 7;
+    CalendarDate::new(year, month, 1 + to_sunday + 7 * (n - 1))
+}
+
+fn last_sunday(year: i32, month: u32) -> CalendarDate {
+    let next_month = if month == 12 { CalendarDate::new(year + 1, 1, 1) } else { CalendarDate::new(year, month + 1, 1) };
+    let last = next_month.pred();
+    CalendarDate::from_days(last.to_days() - ((last.weekday() + 1) % 7) as i64)
+}
+
+/// A trading date and the UTC nanosecond range `[start_ns, end_ns)` of
+/// timestamps that belong to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TradingDay {
+    pub date: CalendarDate,
+    pub start_ns: u64,
+    pub end_ns: u64,
+}
+
+impl TradingDay {
+    pub fn contains(&self, timestamp_ns: u64) -> bool {
+        timestamp_ns >= self.start_ns && timestamp_ns < self.end_ns
+    }
+}
+
+/// Exchange calendar: time zone, regular session hours, half days and
+/// holidays. Activity on a non-trading day is attributed to the next
+/// trading day.
+#[derive(Debug, Clone)]
+pub struct TradingCalendar {
+    pub time_zone: ExchangeTimeZone,
+    pub session_open_secs: i64,
+    pub session_close_secs: i64,
+    pub half_day_close_secs: i64,
+    /// Shifts the start of a trading date relative to local midnight, e.g.
+    /// -7h for a date that begins at 17:00 the evening before.
+    pub trading_day_offset_secs: i64,
+    pub trades_weekends: bool,
+    holidays: BTreeSet<CalendarDate>,
+    half_days: BTreeSet<CalendarDate>,
+}
+
+impl Default for TradingCalendar {
+    fn default() -> Self {
+        Self::always_open()
+    }
+}
+
+impl TradingCalendar {
+    pub fn new(time_zone: ExchangeTimeZone, session_open_secs: i64, session_close_secs: i64) -> Self {
+        TradingCalendar {
+            time_zone,
+            session_open_secs,
+            session_close_secs,
+            half_day_close_secs: session_close_secs,
+            trading_day_offset_secs: 0,
+            trades_weekends: false,
+            holidays: BTreeSet::new(),
+            half_days: BTreeSet::new(),
+        }
+    }
+
+    /// UTC calendar with a 24 hour session every day, rolling at midnight.
+    pub fn always_open() -> Self {
+        TradingCalendar {
+            trades_weekends: true,
+            ..Self::new(ExchangeTimeZone::UTC, 0, SECONDS_PER_DAY)
+        }
+    }
+
+    /// 09:30-16:00 New York, closing at 13:00 on half days.
+    pub fn us_equities() -> Self {
+        TradingCalendar {
+            half_day_close_secs: 13 * 3600,
+            ..Self::new(ExchangeTimeZone::NEW_YORK, 9 * 3600 + 30 * 60, 16 * 3600)
+        }
+    }
+
+    pub fn with_holiday(mut self, date: CalendarDate) -> Self {
+        self.holidays.insert(date);
+        self
+    }
+
+    pub fn with_half_day(mut self, date: CalendarDate) -> Self {
+        self.half_days.insert(date);
+        self
+    }
+
+    pub fn with_trading_day_offset(mut self, offset_secs: i64) -> Self {
+        self.trading_day_offset_secs = offset_secs;
+        self
+    }
+
+    pub fn is_trading_day(&self, date: CalendarDate) -> bool {
+        (self.trades_weekends || !date.is_weekend()) && !self.holidays.contains(&date)
+    }
+
+    pub fn is_half_day(&self, date: CalendarDate) -> bool {
+        self.half_days.contains(&date)
+    }
+
+    pub fn next_trading_day(&self, date: CalendarDate) -> CalendarDate {
+        let mut next = date.succ();
+        // Bounded so a calendar with every day marked as a holiday cannot spin forever
+        for _ in 0..366 {
+            if self.is_trading_day(next) {
+                break;
+            }
+            next = next.succ();
+        }
+        next
+    }
+
+    pub fn previous_trading_day(&self, date: CalendarDate) -> CalendarDate {
+        let mut previous = date.pred();
+        for _ in 0..366 {
+            if self.is_trading_day(previous) {
+                break;
+            }
+            previous = previous.pred();
+        }
+        previous
+    }
+
+    /// Regular session hours of a trading date as UTC nanoseconds.
+    pub fn session_hours(&self, date: CalendarDate) -> Option<(u64, u64)> {
+        if !self.is_trading_day(date) {
+            return None;
+        }
+        let close = if self.is_half_day(date) { self.half_day_close_secs } else { self.session_close_secs };
+        Some((
+            to_ns(self.time_zone.local_to_utc(date, self.session_open_secs)),
+            to_ns(self.time_zone.local_to_utc(date, close)),
+        ))
+    }
+
+    pub fn is_in_session(&self, timestamp_ns: u64) -> bool {
+        let day = self.trading_day(timestamp_ns);
+        self.session_hours(day.date)
+            .is_some_and(|(open, close)| timestamp_ns >= open && timestamp_ns < close)
+    }
+
+    /// The trading date a timestamp belongs to, with the full range of
+    /// timestamps attributed to that date.
+    pub fn trading_day(&self, timestamp_ns: u64) -> TradingDay {
+        let utc_secs = (timestamp_ns / NANOS_PER_SECOND as u64) as i64;
+        let (mut date, secs_of_day) = self.time_zone.local_date(utc_secs);
+
+        // Which local date's window the instant falls into once the offset is applied
+        if secs_of_day < self.trading_day_offset_secs {
+            date = date.pred();
+        } else if secs_of_day >= SECONDS_PER_DAY + self.trading_day_offset_secs {
+            date = date.succ();
+        }
+
+        if !self.is_trading_day(date) {
+            date = self.next_trading_day(date);
+        }
+        let previous = self.previous_trading_day(date);
+
+        TradingDay {
+            date,
+            start_ns: to_ns(self.date_boundary(previous.succ())),
+            end_ns: to_ns(self.date_boundary(date.succ())),
+        }
+    }
+
+    fn date_boundary(&self, date: CalendarDate) -> i64 {
+        self.time_zone.local_to_utc(date, self.trading_day_offset_secs)
+    }
+}
+
+fn to_ns(secs: i64) -> u64 {
+    secs.max(0) as u64 * NANOS_PER_SECOND as u64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionSummary {
+    pub date: CalendarDate,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub vwap: f64,
+    pub trade_count: u64,
+}
+
+/// Running statistics for the trading date currently being processed, kept
+/// exact on the symbol's grid and converted on output.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SessionAccumulator {
+    precision: Precision,
+    day: Option<TradingDay>,
+    open: Price,
+    high: Price,
+    low: Price,
+    close: Price,
+    volume: Total,
+    notional: Total,
+    trade_count: u64,
+}
+
+impl SessionAccumulator {
+    pub(crate) fn new(precision: Precision) -> Self {
+        SessionAccumulator {
+            precision,
+            ..SessionAccumulator::default()
+        }
+    }
+
+    /// Moves to the trading date of `timestamp_ns` if it lies past the current
+    /// one, returning the summary of the session that just ended.
+    pub(crate) fn roll(&mut self, timestamp_ns: u64, calendar: &TradingCalendar) -> Option<SessionSummary> {
+        if self.day.is_some_and(|day| timestamp_ns < day.end_ns) {
+            return None;
+        }
+        let finished = self.summary();
+        *self = SessionAccumulator {
+            day: Some(calendar.trading_day(timestamp_ns)),
+            ..SessionAccumulator::new(self.precision)
+        };
+        finished
+    }
+
+    /// Adds a trade to the current session. Trades from an earlier session,
+    /// e.g. late arrivals after a rollover, are not counted.
+    pub(crate) fn add_trade(&mut self, timestamp_ns: u64, price: Price, quantity: Quantity) -> bool {
+        if !self.day.is_some_and(|day| day.contains(timestamp_ns)) {
+            return false;
+        }
+        if self.trade_count == 0 {
+            self.open = price;
+            self.high = price;
+            self.low = price;
+        }
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += self.precision.quantity_total(quantity);
+        self.notional += self.precision.notional(price, quantity);
+        self.trade_count += 1;
+        true
+    }
+
+    pub(crate) fn volume(&self) -> f64 {
+        self.precision.quantity_total_to_f64(self.volume)
+    }
+
+    pub(crate) fn summary(&self) -> Option<SessionSummary> {
+        let day = self.day?;
+        if self.trade_count == 0 {
+            return None;
+        }
+        let precision = &self.precision;
+        Some(SessionSummary {
+            date: day.date,
+            open: precision.price_to_f64(self.open),
+            high: precision.price_to_f64(self.high),
+            low: precision.price_to_f64(self.low),
+            close: precision.price_to_f64(self.close),
+            volume: precision.quantity_total_to_f64(self.volume),
+            vwap: precision.average_price(self.notional, self.volume).unwrap_or(precision.price_to_f64(self.close)),
+            trade_count: self.trade_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixed_point::FixedScale;
+
+    const HOUR: i64 = 3600;
+
+    fn utc(year: i32, month: u32, day: u32, hour: i64, minute: i64) -> i64 {
+        CalendarDate::new(year, month, day).to_days() * SECONDS_PER_DAY + hour * HOUR + minute * 60
+    }
+
+    fn utc_ns(year: i32, month: u32, day: u32, hour: i64, minute: i64) -> u64 {
+        to_ns(utc(year, month, day, hour, minute))
+    }
+
+    #[test]
+    fn date_arithmetic() {
+        assert_eq!(CalendarDate::new(1970, 1, 1).to_days(), 0);
+        assert_eq!(CalendarDate::new(2024, 1, 1).weekday(), 0);
+        assert!(CalendarDate::new(2024, 7, 6).is_weekend());
+        assert_eq!(CalendarDate::new(2024, 2, 28).succ(), CalendarDate::new(2024, 2, 29));
+        assert_eq!(CalendarDate::new(2023, 3, 1).pred(), CalendarDate::new(2023, 2, 28));
+        assert_eq!(CalendarDate::new(2024, 12, 31).succ(), CalendarDate::new(2025, 1, 1));
+        for days in [-719_468, -1, 0, 11_016, 19_723, 60_000] {
+            assert_eq!(CalendarDate::from_days(days).to_days(), days);
+        }
+    }
+
+    #[test]
+    fn united_states_dst_boundaries() {
+        let new_york = ExchangeTimeZone::NEW_YORK;
+        // 02:00 EST on 10 March and 02:00 EDT on 3 November 2024
+        let start = utc(2024, 3, 10, 7, 0);
+        let end = utc(2024, 11, 3, 6, 0);
+        assert_eq!(new_york.offset_at(start - 1), -5 * HOUR);
+        assert_eq!(new_york.offset_at(start), -4 * HOUR);
+        assert_eq!(new_york.offset_at(end - 1), -4 * HOUR);
+        assert_eq!(new_york.offset_at(end), -5 * HOUR);
+
+        let chicago = ExchangeTimeZone::CHICAGO;
+        assert_eq!(chicago.offset_at(utc(2024, 3, 10, 8, 0) - 1), -6 * HOUR);
+        assert_eq!(chicago.offset_at(utc(2024, 3, 10, 8, 0)), -5 * HOUR);
+    }
+
+    #[test]
+    fn european_dst_boundaries() {
+        // 01:00 UTC on the last Sundays of March and October 2024
+        let start = utc(2024, 3, 31, 1, 0);
+        let end = utc(2024, 10, 27, 1, 0);
+        for (zone, standard) in [(ExchangeTimeZone::LONDON, 0), (ExchangeTimeZone::FRANKFURT, HOUR)] {
+            assert_eq!(zone.offset_at(start - 1), standard);
+            assert_eq!(zone.offset_at(start), standard + HOUR);
+            assert_eq!(zone.offset_at(end - 1), standard + HOUR);
+            assert_eq!(zone.offset_at(end), standard);
+        }
+        assert_eq!(ExchangeTimeZone::fixed(9 * HOUR).offset_at(start), 9 * HOUR);
+    }
+
+    #[test]
+    fn local_times_across_dst() {
+        let new_york = ExchangeTimeZone::NEW_YORK;
+        assert_eq!(new_york.local_to_utc(CalendarDate::new(2024, 1, 8), 9 * HOUR + 1800), utc(2024, 1, 8, 14, 30));
+        assert_eq!(new_york.local_to_utc(CalendarDate::new(2024, 7, 8), 9 * HOUR + 1800), utc(2024, 7, 8, 13, 30));
+        // 02:30 does not exist on 10 March and reads as standard time
+        assert_eq!(new_york.local_to_utc(CalendarDate::new(2024, 3, 10), 2 * HOUR + 1800), utc(2024, 3, 10, 7, 30));
+
+        let calendar = TradingCalendar::us_equities();
+        assert_eq!(calendar.session_hours(CalendarDate::new(2024, 3, 8)), Some((utc_ns(2024, 3, 8, 14, 30), utc_ns(2024, 3, 8, 21, 0))));
+        assert_eq!(calendar.session_hours(CalendarDate::new(2024, 3, 11)), Some((utc_ns(2024, 3, 11, 13, 30), utc_ns(2024, 3, 11, 20, 0))));
+    }
+
+    #[test]
+    fn holidays_half_days_and_weekends() {
+        let calendar = TradingCalendar::us_equities()
+            .with_holiday(CalendarDate::new(2024, 7, 4))
+            .with_half_day(CalendarDate::new(2024, 7, 3));
+        assert!(!calendar.is_trading_day(CalendarDate::new(2024, 7, 4)));
+        assert_eq!(calendar.next_trading_day(CalendarDate::new(2024, 7, 3)), CalendarDate::new(2024, 7, 5));
+        assert_eq!(calendar.next_trading_day(CalendarDate::new(2024, 7, 5)), CalendarDate::new(2024, 7, 8));
+        assert_eq!(calendar.previous_trading_day(CalendarDate::new(2024, 7, 5)), CalendarDate::new(2024, 7, 3));
+        assert_eq!(calendar.session_hours(CalendarDate::new(2024, 7, 4)), None);
+        assert_eq!(calendar.session_hours(CalendarDate::new(2024, 7, 3)).map(|(_, close)| close), Some(utc_ns(2024, 7, 3, 17, 0)));
+
+        assert!(!calendar.is_in_session(utc_ns(2024, 7, 8, 13, 29)));
+        assert!(calendar.is_in_session(utc_ns(2024, 7, 8, 13, 30)));
+        assert!(calendar.is_in_session(utc_ns(2024, 7, 8, 19, 59)));
+        assert!(!calendar.is_in_session(utc_ns(2024, 7, 8, 20, 0)));
+        assert!(!calendar.is_in_session(utc_ns(2024, 7, 4, 15, 0)));
+    }
+
+    #[test]
+    fn activity_on_closed_days_belongs_to_the_next_trading_day() {
+        let calendar = TradingCalendar::us_equities().with_holiday(CalendarDate::new(2024, 7, 4));
+        let holiday = calendar.trading_day(utc_ns(2024, 7, 4, 15, 0));
+        assert_eq!(holiday.date, CalendarDate::new(2024, 7, 5));
+        assert_eq!((holiday.start_ns, holiday.end_ns), (utc_ns(2024, 7, 4, 4, 0), utc_ns(2024, 7, 6, 4, 0)));
+
+        let weekend = calendar.trading_day(utc_ns(2024, 7, 6, 12, 0));
+        assert_eq!(weekend.date, CalendarDate::new(2024, 7, 8));
+        assert!(weekend.contains(utc_ns(2024, 7, 6, 4, 0)));
+        assert!(!weekend.contains(utc_ns(2024, 7, 9, 4, 0)));
+    }
+
+    #[test]
+    fn trading_day_offset_starts_the_date_the_evening_before() {
+        let calendar = TradingCalendar::new(ExchangeTimeZone::CHICAGO, 8 * HOUR + 1800, 15 * HOUR)
+            .with_trading_day_offset(-7 * HOUR);
+        // 18:00 CDT belongs to the next date
+        assert_eq!(calendar.trading_day(utc_ns(2024, 7, 8, 23, 0)).date, CalendarDate::new(2024, 7, 9));
+        assert_eq!(calendar.trading_day(utc_ns(2024, 7, 8, 21, 59)).date, CalendarDate::new(2024, 7, 8));
+        // Sunday evening opens Monday
+        let monday = calendar.trading_day(utc_ns(2024, 7, 7, 23, 0));
+        assert_eq!(monday.date, CalendarDate::new(2024, 7, 8));
+        assert_eq!(monday.end_ns, utc_ns(2024, 7, 8, 22, 0));
+    }
+
+    #[test]
+    fn session_accumulator_rolls_at_the_date_boundary() {
+        let calendar = TradingCalendar::always_open();
+        let mut session = SessionAccumulator::new(Precision::new(FixedScale::decimals(2), FixedScale::decimals(0)));
+        assert_eq!(session.roll(utc_ns(2024, 1, 2, 10, 0), &calendar), None);
+        for (minute, price, quantity) in [(0, 10_000, 1), (1, 10_200, 1), (2, 9_900, 2), (3, 10_100, 4)] {
+            assert!(session.add_trade(utc_ns(2024, 1, 2, 10, minute), Price(price), Quantity(quantity)));
+        }
+        assert_eq!(session.roll(utc_ns(2024, 1, 2, 23, 59), &calendar), None);
+
+        let summary = session.roll(utc_ns(2024, 1, 3, 0, 0), &calendar).unwrap();
+        assert_eq!(summary.date, CalendarDate::new(2024, 1, 2));
+        assert_eq!((summary.open, summary.high, summary.low, summary.close), (100.0, 102.0, 99.0, 101.0));
+        assert_eq!((summary.volume, summary.trade_count), (8.0, 4));
+        assert_eq!(summary.vwap, 100.5);
+
+        // A late trade from the finished date is not counted
+        assert!(!session.add_trade(utc_ns(2024, 1, 2, 23, 0), Price(10_000), Quantity(1)));
+        assert_eq!(session.summary(), None);
+    }
+}
@@ -0,0 +1,258 @@
+use std::sync::Arc;
+
+// Items per chunk. Sealed chunks are shared between clones, so publishing
+// only copies the chunk still being written.
+const CHUNK_SIZE: usize = 1024;
+
+/// Ordered sequence kept in `Arc` chunks, so that a clone taken for a
+/// snapshot shares everything but the open tail. Appends and removals from
+/// the front are cheap and lookups by position are logarithmic; edits
+/// elsewhere copy the chunks they touch.
+#[derive(Debug, Clone)]
+pub struct ChunkedVec<T> {
+    chunks: Vec<Arc<Vec<T>>>,
+    // Position one past the last item of each chunk. Positions count from
+    // the first item ever held, so removing from the front only moves `start`
+    ends: Vec<usize>,
+    // Position of the first item still held; earlier items of the first
+    // chunk stay allocated until the whole chunk is dropped
+    start: usize,
+}
+
+impl<T> Default for ChunkedVec<T> {
+    fn default() -> Self {
+        ChunkedVec {
+            chunks: Vec::new(),
+            ends: Vec::new(),
+            start: 0,
+        }
+    }
+}
+
+impl<T: Clone> ChunkedVec<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.last().map_or(0, |end| end - self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.chunks.last().and_then(|chunk| chunk.last())
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len() {
+            return None;
+        }
+        let (chunk_index, offset) = self.locate(index);
+        self.chunks[chunk_index].get(offset)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.range(0, self.len())
+    }
+
+    /// Items in `[from, to)`.
+    pub fn range(&self, from: usize, to: usize) -> impl Iterator<Item = &T> + '_ {
+        let to = to.min(self.len());
+        let (chunk_index, offset) = self.locate(from.min(to));
+        self.chunks[chunk_index..].iter()
+            .enumerate()
+            .flat_map(move |(index, chunk)| chunk[if index == 0 { offset } else { 0 }..].iter())
+            .take(to.saturating_sub(from))
+    }
+
+    /// Index of the first item for which `pred` is false, for items ordered
+    /// so that it holds for a prefix.
+    pub fn partition_point(&self, pred: impl Fn(&T) -> bool) -> usize {
+        let chunk_index = self.chunks.partition_point(|chunk| chunk.last().is_some_and(&pred));
+        let chunk = match self.chunks.get(chunk_index) {
+            Some(chunk) => chunk,
+            None => return self.len(),
+        };
+        let first = self.chunk_start(chunk_index).max(self.start);
+        let live = &chunk[first - self.chunk_start(chunk_index)..];
+        first + live.partition_point(&pred) - self.start
+    }
+
+    pub(crate) fn push(&mut self, value: T) {
+        match (self.chunks.last_mut(), self.ends.last_mut()) {
+            (Some(chunk), Some(end)) if chunk.len() < CHUNK_SIZE => {
+                Arc::make_mut(chunk).push(value);
+                *end += 1;
+            },
+            _ => {
+                let end = self.ends.last().copied().unwrap_or(self.start) + 1;
+                self.chunks.push(Arc::new(vec![value]));
+                self.ends.push(end);
+            },
+        }
+    }
+
+    pub(crate) fn last_mut(&mut self) -> Option<&mut T> {
+        self.chunks.last_mut().and_then(|chunk| Arc::make_mut(chunk).last_mut())
+    }
+
+    pub(crate) fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len() {
+            return None;
+        }
+        let (chunk_index, offset) = self.locate(index);
+        Arc::make_mut(&mut self.chunks[chunk_index]).get_mut(offset)
+    }
+
+    /// Removes the first `count` items without copying any chunk.
+    pub(crate) fn remove_front(&mut self, count: usize) {
+        self.start += count.min(self.len());
+        let emptied = self.ends.partition_point(|end| *end <= self.start);
+        self.chunks.drain(..emptied);
+        self.ends.drain(..emptied);
+    }
+
+    /// Replaces the items in `[from, to)` with `items`, copying only the
+    /// chunks the range touches.
+    pub(crate) fn splice(&mut self, from: usize, to: usize, items: Vec<T>) {
+        let from = from.min(self.len());
+        let to = to.min(self.len()).max(from);
+        let (first, from_offset) = self.locate(from);
+        let (last, to_offset) = self.locate(to);
+
+        let base = if first == 0 { self.start } else { self.ends[first - 1] };
+        let mut rebuilt: Vec<T> = match self.chunks.get(first) {
+            Some(chunk) => chunk[base - self.chunk_start(first)..from_offset].to_vec(),
+            None => Vec::new(),
+        };
+        rebuilt.extend(items);
+        let replaced_end = match self.chunks.get(last) {
+            Some(chunk) => {
+                rebuilt.extend_from_slice(&chunk[to_offset..]);
+                last + 1
+            },
+            None => self.chunks.len(),
+        };
+
+        let replacement: Vec<Arc<Vec<T>>> = rebuilt.chunks(CHUNK_SIZE).map(|chunk| Arc::new(chunk.to_vec())).collect();
+        let replaced = replacement.len();
+        self.chunks.splice(first..replaced_end, replacement);
+        self.ends.splice(first..replaced_end, std::iter::repeat_n(0, replaced));
+        let mut end = base;
+        for (chunk, chunk_end) in self.chunks[first..].iter().zip(&mut self.ends[first..]) {
+            end += chunk.len();
+            *chunk_end = end;
+        }
+    }
+
+    /// Heap held by the items, leaving out spare capacity.
+    pub(crate) fn approximate_bytes(&self) -> usize {
+        self.len() * std::mem::size_of::<T>()
+    }
+
+    fn chunk_start(&self, chunk_index: usize) -> usize {
+        self.ends[chunk_index] - self.chunks[chunk_index].len()
+    }
+
+    // Chunk and offset within it of the item at `index`; one past the last
+    // chunk when `index` is the length
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let position = self.start + index;
+        let chunk_index = self.ends.partition_point(|end| *end <= position);
+        if chunk_index == self.chunks.len() {
+            return (chunk_index, 0);
+        }
+        (chunk_index, position - self.chunk_start(chunk_index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(count: usize) -> ChunkedVec<usize> {
+        let mut items = ChunkedVec::new();
+        for item in 0..count {
+            items.push(item);
+        }
+        items
+    }
+
+    #[test]
+    fn lookups_across_chunks() {
+        let items = filled(3 * CHUNK_SIZE + 5);
+        assert_eq!(items.len(), 3 * CHUNK_SIZE + 5);
+        assert_eq!(items.get(CHUNK_SIZE), Some(&CHUNK_SIZE));
+        assert_eq!(items.get(items.len()), None);
+        assert_eq!(items.range(CHUNK_SIZE - 2, CHUNK_SIZE + 2).copied().collect::<Vec<_>>(), [1022, 1023, 1024, 1025]);
+        assert_eq!(items.range(10, 5).count(), 0);
+        assert_eq!(items.partition_point(|item| *item < 2500), 2500);
+        assert_eq!(items.partition_point(|_| true), items.len());
+    }
+
+    #[test]
+    fn clones_share_sealed_chunks() {
+        let mut items = filled(2 * CHUNK_SIZE + 1);
+        let snapshot = items.clone();
+        items.push(0);
+        *items.last_mut().unwrap() = 7;
+        assert!(Arc::ptr_eq(&items.chunks[0], &snapshot.chunks[0]));
+        assert!(!Arc::ptr_eq(&items.chunks[2], &snapshot.chunks[2]));
+        assert_eq!((items.len(), snapshot.len()), (2 * CHUNK_SIZE + 2, 2 * CHUNK_SIZE + 1));
+        assert_eq!(snapshot.last(), Some(&(2 * CHUNK_SIZE)));
+    }
+
+    #[test]
+    fn remove_front_keeps_positions_consistent() {
+        let mut items = filled(3 * CHUNK_SIZE);
+        items.remove_front(CHUNK_SIZE + 10);
+        assert_eq!(items.chunks.len(), 2);
+        assert_eq!(items.first(), Some(&(CHUNK_SIZE + 10)));
+        assert_eq!(items.partition_point(|item| *item < 2 * CHUNK_SIZE), CHUNK_SIZE - 10);
+        assert_eq!(items.range(0, 3).copied().collect::<Vec<_>>(), [1034, 1035, 1036]);
+        items.remove_front(items.len() + 1);
+        assert!(items.is_empty());
+        items.push(1);
+        assert_eq!(items.iter().copied().collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn splice_replaces_a_range() {
+        let mut items = filled(4 * CHUNK_SIZE);
+        items.remove_front(5);
+        let snapshot = items.clone();
+        // Collapse most of the first two chunks into one item
+        items.splice(10, 2 * CHUNK_SIZE, vec![usize::MAX]);
+        assert_eq!(items.len(), 4 * CHUNK_SIZE - 5 - (2 * CHUNK_SIZE - 10) + 1);
+        assert_eq!(items.range(8, 12).copied().collect::<Vec<_>>(), [13, 14, usize::MAX, 2 * CHUNK_SIZE + 5]);
+        assert_eq!(items.partition_point(|item| *item < 15), 10);
+        // The untouched last chunk is still shared
+        assert!(Arc::ptr_eq(items.chunks.last().unwrap(), snapshot.chunks.last().unwrap()));
+
+        // Insertion, and a range past the end
+        items.splice(0, 0, vec![1, 2]);
+        items.splice(items.len(), items.len() + 10, vec![3]);
+        assert_eq!(items.first(), Some(&1));
+        assert_eq!(items.last(), Some(&3));
+        assert_eq!(snapshot.len(), 4 * CHUNK_SIZE - 5);
+    }
+
+    #[test]
+    fn get_mut_copies_only_its_chunk() {
+        let mut items = filled(CHUNK_SIZE + 2);
+        let snapshot = items.clone();
+        *items.get_mut(CHUNK_SIZE + 1).unwrap() = 0;
+        *items.last_mut().unwrap() += 1;
+        assert_eq!(items.range(CHUNK_SIZE - 1, CHUNK_SIZE + 2).copied().collect::<Vec<_>>(), [1023, 1024, 1]);
+        assert_eq!(snapshot.last(), Some(&(CHUNK_SIZE + 1)));
+        assert!(Arc::ptr_eq(&items.chunks[0], &snapshot.chunks[0]));
+        assert_eq!(items.get_mut(CHUNK_SIZE + 2), None);
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::VecDeque;
+
+use std::cmp::Ordering;
+
+use super::fixed_point::{Precision, Price};
+
+/// Algorithm used to sign trades whose aggressor side is not reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TradeClassifier {
+    /// Up from the previous trade price is a buy, down is a sell; unchanged
+    /// prices take the direction of the last change.
+    TickRule,
+    /// Above the prevailing mid is a buy, below is a sell.
+    QuoteRule,
+    /// Quote rule, falling back to the tick rule for trades at the mid. Uses
+    /// the quotes prevailing just before the trade rather than lagged ones.
+    #[default]
+    LeeReady,
+    /// Splits each trade's volume by the normal CDF of its standardized price
+    /// change, with the deviation estimated over the last `window` changes.
+    BulkVolume { window: usize },
+}
+
+/// What actually determined a trade's sign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClassificationMethod {
+    /// `is_buy` was present on the message.
+    Reported,
+    /// The trade executed a resting order, so the aggressor took the other side.
+    RestingOrder,
+    TickRule,
+    QuoteRule,
+    BulkVolume,
+    /// Nothing to go on, e.g. the first trade with no quotes.
+    Unclassified,
+}
+
+/// Aggressor side of a trade as the fraction of its volume attributed to
+/// buyers: 1 or 0 for discrete methods, anywhere in between for bulk volume
+/// classification, 0.5 when unclassified.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TradeSign {
+    pub buy_fraction: f64,
+    pub method: ClassificationMethod,
+}
+
+impl TradeSign {
+    pub(crate) fn discrete(is_buy: bool, method: ClassificationMethod) -> Self {
+        TradeSign {
+            buy_fraction: if is_buy { 1.0 } else { 0.0 },
+            method,
+        }
+    }
+
+    pub fn unclassified() -> Self {
+        TradeSign {
+            buy_fraction: 0.5,
+            method: ClassificationMethod::Unclassified,
+        }
+    }
+
+    /// Majority side, or None if volume is split evenly.
+    pub fn is_buy(&self) -> Option<bool> {
+        if self.buy_fraction > 0.5 {
+            Some(true)
+        } else if self.buy_fraction < 0.5 {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+/// Number of trades signed by each method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClassificationCounts {
+    pub reported: u64,
+    pub resting_order: u64,
+    pub tick_rule: u64,
+    pub quote_rule: u64,
+    pub bulk_volume: u64,
+    pub unclassified: u64,
+}
+
+impl ClassificationCounts {
+    pub(crate) fn record(&mut self, method: ClassificationMethod) {
+        let count = match method {
+            ClassificationMethod::Reported => &mut self.reported,
+            ClassificationMethod::RestingOrder => &mut self.resting_order,
+            ClassificationMethod::TickRule => &mut self.tick_rule,
+            ClassificationMethod::QuoteRule => &mut self.quote_rule,
+            ClassificationMethod::BulkVolume => &mut self.bulk_volume,
+            ClassificationMethod::Unclassified => &mut self.unclassified,
+        };
+        *count += 1;
+    }
+}
+
+/// Per-symbol trade history the classifiers need.
+#[derive(Debug)]
+pub(crate) struct TradeClassifierState {
+    precision: Precision,
+    last_price: Option<Price>,
+    // Direction of the last non-zero price change, true for an uptick
+    last_direction: Option<bool>,
+    changes: VecDeque<f64>,
+    sum: f64,
+    sum_sq: f64,
+}
+
+impl TradeClassifierState {
+    pub(crate) fn new(precision: Precision) -> Self {
+        TradeClassifierState {
+            precision,
+            last_price: None,
+            last_direction: None,
+            changes: VecDeque::new(),
+            sum: 0.0,
+            sum_sq: 0.0,
+        }
+    }
+
+    /// Signs a trade using the quotes prevailing before it, then records its
+    /// price. The trade must be applied in time order.
+    pub(crate) fn classify(&mut self, classifier: TradeClassifier, price: Price, bid: Option<Price>, ask: Option<Price>) -> TradeSign {
+        let sign = match classifier {
+            TradeClassifier::TickRule => self.tick_rule(price),
+            TradeClassifier::QuoteRule => self.quote_rule(price, bid, ask),
+            TradeClassifier::LeeReady => self.quote_rule(price, bid, ask)
+                .or_else(|| self.tick_rule(price)),
+            TradeClassifier::BulkVolume { .. } => self.bulk_volume(price),
+        };
+        self.observe(price, classifier);
+        sign.unwrap_or_else(TradeSign::unclassified)
+    }
+
+    /// Records the price of a trade whose sign came from elsewhere.
+    pub(crate) fn observe(&mut self, price: Price, classifier: TradeClassifier) {
+        if let Some(last_price) = self.last_price {
+            if price != last_price {
+                self.last_direction = Some(price > last_price);
+            }
+            if let TradeClassifier::BulkVolume { window } = classifier {
+                let change = self.price_change(last_price, price);
+                self.changes.push_back(change);
+                self.sum += change;
+                self.sum_sq += change * change;
+                while self.changes.len() > window.max(1) {
+                    if let Some(old) = self.changes.pop_front() {
+                        self.sum -= old;
+                        self.sum_sq -= old * old;
+                    }
+                }
+            }
+        }
+        self.last_price = Some(price);
+    }
+
+    fn tick_rule(&self, price: Price) -> Option<TradeSign> {
+        let last_price = self.last_price?;
+        let is_buy = if price == last_price { self.last_direction? } else { price > last_price };
+        Some(TradeSign::discrete(is_buy, ClassificationMethod::TickRule))
+    }
+
+    fn bulk_volume(&self, price: Price) -> Option<TradeSign> {
+        let change = self.price_change(self.last_price?, price);
+        let n = self.changes.len() as f64;
+        let variance = if n > 1.0 { (self.sum_sq - self.sum * self.sum / n) / (n - 1.0) } else { 0.0 };
+
+        let buy_fraction = if variance > 0.0 {
+            normal_cdf(change / variance.sqrt())
+        } else if change == 0.0 {
+            0.5
+        } else if change > 0.0 {
+            1.0
+        } else {
+            0.0
+        };
+        Some(TradeSign { buy_fraction, method: ClassificationMethod::BulkVolume })
+    }
+
+    fn quote_rule(&self, price: Price, bid: Option<Price>, ask: Option<Price>) -> Option<TradeSign> {
+        match self.precision.cmp_to_midpoint(price, bid?, ask?) {
+            Ordering::Equal => None,
+            ordering => Some(TradeSign::discrete(ordering == Ordering::Greater, ClassificationMethod::QuoteRule)),
+        }
+    }
+
+    fn price_change(&self, from: Price, to: Price) -> f64 {
+        self.precision.price_to_f64(to) - self.precision.price_to_f64(from)
+    }
+}
+
+/// Standard normal CDF, accurate to about 1e-7.
+pub(crate) fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+// Abramowitz and Stegun 7.1.26
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t * (0.254_829_592 + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let value = 1.0 - poly * (-x * x).exp();
+    if x < 0.0 { -value } else { value }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixed_point::FixedScale;
+
+    fn state() -> TradeClassifierState {
+        TradeClassifierState::new(Precision::new(FixedScale::decimals(2), FixedScale::decimals(0)))
+    }
+
+    fn price(value: f64) -> Price {
+        Price((value * 100.0).round() as i64)
+    }
+
+    fn signs(classifier: TradeClassifier, prices: &[f64], bid: Option<f64>, ask: Option<f64>) -> Vec<Option<bool>> {
+        let mut state = state();
+        prices.iter()
+            .map(|value| state.classify(classifier, price(*value), bid.map(price), ask.map(price)).is_buy())
+            .collect()
+    }
+
+    #[test]
+    fn tick_rule_carries_the_last_direction_through_zero_ticks() {
+        let prices = [100.0, 100.01, 100.01, 100.0, 100.0, 100.02];
+        assert_eq!(signs(TradeClassifier::TickRule, &prices, None, None), vec![None, Some(true), Some(true), Some(false), Some(false), Some(true)]);
+
+        let mut state = state();
+        let sign = state.classify(TradeClassifier::TickRule, price(100.0), None, None);
+        assert_eq!(sign, TradeSign::unclassified());
+        let sign = state.classify(TradeClassifier::TickRule, price(100.0), None, None);
+        assert_eq!(sign.method, ClassificationMethod::Unclassified);
+    }
+
+    #[test]
+    fn quote_rule_compares_against_the_mid() {
+        let prices = [100.5, 99.5, 100.0];
+        assert_eq!(signs(TradeClassifier::QuoteRule, &prices, Some(99.0), Some(101.0)), vec![Some(true), Some(false), None]);
+        assert_eq!(signs(TradeClassifier::QuoteRule, &prices, None, Some(101.0)), vec![None, None, None]);
+
+        let mut state = state();
+        let sign = state.classify(TradeClassifier::QuoteRule, price(100.01), Some(price(100.0)), Some(price(100.01)));
+        assert_eq!(sign, TradeSign::discrete(true, ClassificationMethod::QuoteRule));
+    }
+
+    #[test]
+    fn lee_ready_falls_back_to_the_tick_rule_at_the_mid() {
+        let mut state = state();
+        let (bid, ask) = (Some(price(99.0)), Some(price(101.0)));
+
+        let sign = state.classify(TradeClassifier::LeeReady, price(99.5), bid, ask);
+        assert_eq!(sign, TradeSign::discrete(false, ClassificationMethod::QuoteRule));
+        let sign = state.classify(TradeClassifier::LeeReady, price(100.0), bid, ask);
+        assert_eq!(sign, TradeSign::discrete(true, ClassificationMethod::TickRule));
+        let sign = state.classify(TradeClassifier::LeeReady, price(100.0), None, None);
+        assert_eq!(sign, TradeSign::discrete(true, ClassificationMethod::TickRule));
+    }
+
+    #[test]
+    fn bulk_volume_splits_by_the_standardized_price_change() {
+        let classifier = TradeClassifier::BulkVolume { window: 3 };
+        let mut state = state();
+
+        assert_eq!(state.classify(classifier, price(100.0), None, None), TradeSign::unclassified());
+        // Too few changes for a deviation, so the direction decides
+        assert_eq!(state.classify(classifier, price(101.0), None, None).buy_fraction, 1.0);
+        assert_eq!(state.classify(classifier, price(99.0), None, None).buy_fraction, 0.0);
+        state.classify(classifier, price(100.0), None, None);
+
+        // Changes in the window are +1, -2, +1 with a standard deviation of sqrt(3)
+        let sign = state.classify(classifier, price(101.0), None, None);
+        assert_eq!(sign.method, ClassificationMethod::BulkVolume);
+        assert!((sign.buy_fraction - 0.718_148_569).abs() < 1e-6);
+
+        // The window now holds -2, +1, +1, so an unchanged price splits evenly
+        let sign = state.classify(classifier, price(101.0), None, None);
+        assert!((sign.buy_fraction - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn normal_cdf_matches_reference_values() {
+        assert!((normal_cdf(0.0) - 0.5).abs() < 1e-7);
+        assert!((normal_cdf(1.0) - 0.841_344_746).abs() < 1e-6);
+        assert!((normal_cdf(-1.959_963_985) - 0.025).abs() < 1e-6);
+    }
+}
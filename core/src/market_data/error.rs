@@ -0,0 +1,68 @@
+use std::fmt;
+use std::sync::PoisonError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarketDataError {
+    /// Intake is closed or the worker side of the channel has gone away.
+    Disconnected,
+    /// The queue was full and the backpressure policy rejected the message.
+    QueueFull,
+    /// A message or request failed validation.
+    Validation(String),
+    UnknownSymbol(String),
+    UnknownOrder(String),
+    DuplicateOrder(String),
+    /// A lock was poisoned by a panicking thread.
+    PoisonedState,
+    AlreadyRunning,
+    WorkerPanicked,
+    Spawn(String),
+    Clock(String),
+}
+
+pub type MarketDataResult<T> = Result<T, MarketDataError>;
+
+impl fmt::Display for MarketDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketDataError::Disconnected => write!(f, "processor is not accepting messages"),
+            MarketDataError::QueueFull => write!(f, "queue full"),
+            MarketDataError::Validation(reason) => write!(f, "invalid message: {}", reason),
+            MarketDataError::UnknownSymbol(symbol) => write!(f, "unknown symbol {}", symbol),
+            MarketDataError::UnknownOrder(order_id) => write!(f, "unknown order id {}", order_id),
+            MarketDataError::DuplicateOrder(order_id) => write!(f, "duplicate order id {}", order_id),
+            MarketDataError::PoisonedState => write!(f, "processor state poisoned by a panicked thread"),
+            MarketDataError::AlreadyRunning => write!(f, "processing already started"),
+            MarketDataError::WorkerPanicked => write!(f, "processing thread panicked"),
+            MarketDataError::Spawn(reason) => write!(f, "failed to spawn processing thread: {}", reason),
+            MarketDataError::Clock(reason) => write!(f, "system clock error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MarketDataError {}
+
+impl<T> From<PoisonError<T>> for MarketDataError {
+    fn from(_: PoisonError<T>) -> Self {
+        MarketDataError::PoisonedState
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn poisoned_locks_become_an_error() {
+        let lock = Arc::new(Mutex::new(0));
+        let poisoner = Arc::clone(&lock);
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("poison the lock");
+        }).join();
+
+        let locked = || -> MarketDataResult<i32> { Ok(*lock.lock()?) };
+        assert_eq!(locked(), Err(MarketDataError::PoisonedState));
+    }
+}
@@ -0,0 +1,377 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender, TryRecvError, TrySendError};
+
+use super::bars::{Bar, BarSpec};
+use super::classification::TradeSign;
+use super::error::{MarketDataError, MarketDataResult};
+use super::fair_value::FairValue;
+use super::order_book::Side;
+use super::sequencing::SequenceAnomaly;
+use super::spreads::TradeSpread;
+use super::toxicity::ToxicityScore;
+use super::vpin::VpinPoint;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventTopic {
+    Trade,
+    BookUpdate,
+    BboChange,
+    Metrics,
+    Sequence,
+    Bar,
+    Toxicity,
+    Vpin,
+    TradeSpread,
+    FairValue,
+}
+
+impl EventTopic {
+    const ALL: [EventTopic; 10] = [
+        EventTopic::Trade,
+        EventTopic::BookUpdate,
+        EventTopic::BboChange,
+        EventTopic::Metrics,
+        EventTopic::Sequence,
+        EventTopic::Bar,
+        EventTopic::Toxicity,
+        EventTopic::Vpin,
+        EventTopic::TradeSpread,
+        EventTopic::FairValue,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolMetrics {
+    pub last_price: f64,
+    pub daily_volume: f64,
+    pub mid_price: Option<f64>,
+    pub spread: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketEvent {
+    Trade {
+        symbol: String,
+        timestamp_ns: u64,
+        price: f64,
+        quantity: f64,
+        /// Reported or inferred aggressor side.
+        aggressor: TradeSign,
+    },
+    /// Aggregate size at a price level after a change; zero means the level
+    /// was removed.
+    BookUpdate {
+        symbol: String,
+        timestamp_ns: u64,
+        side: Side,
+        price: f64,
+        quantity: f64,
+    },
+    BboChange {
+        symbol: String,
+        timestamp_ns: u64,
+        best_bid: Option<f64>,
+        best_ask: Option<f64>,
+    },
+    Metrics {
+        symbol: String,
+        timestamp_ns: u64,
+        metrics: SymbolMetrics,
+    },
+    /// Sequence anomaly on a feed channel, reported against the symbol of the
+    /// message that revealed it.
+    Sequence {
+        symbol: String,
+        timestamp_ns: u64,
+        channel_id: u32,
+        anomaly: SequenceAnomaly,
+        expected: u64,
+        received: u64,
+    },
+    /// A completed bar; `timestamp_ns` is the message that closed it.
+    Bar {
+        symbol: String,
+        timestamp_ns: u64,
+        spec: BarSpec,
+        bar: Bar,
+    },
+    /// A rescoring of the symbol's flow toxicity.
+    Toxicity {
+        symbol: String,
+        timestamp_ns: u64,
+        score: ToxicityScore,
+    },
+    /// VPIN after a volume bucket completed.
+    Vpin {
+        symbol: String,
+        timestamp_ns: u64,
+        point: VpinPoint,
+    },
+    /// Spread decomposition of a trade, once its last horizon has passed.
+    TradeSpread {
+        symbol: String,
+        timestamp_ns: u64,
+        spread: TradeSpread,
+    },
+    /// Microprice and weighted mids after a message that changed them.
+    FairValue {
+        symbol: String,
+        timestamp_ns: u64,
+        value: FairValue,
+    },
+}
+
+impl MarketEvent {
+    pub fn topic(&self) -> EventTopic {
+        match self {
+            MarketEvent::Trade { .. } => EventTopic::Trade,
+            MarketEvent::BookUpdate { .. } => EventTopic::BookUpdate,
+            MarketEvent::BboChange { .. } => EventTopic::BboChange,
+            MarketEvent::Metrics { .. } => EventTopic::Metrics,
+            MarketEvent::Sequence { .. } => EventTopic::Sequence,
+            MarketEvent::Bar { .. } => EventTopic::Bar,
+            MarketEvent::Toxicity { .. } => EventTopic::Toxicity,
+            MarketEvent::Vpin { .. } => EventTopic::Vpin,
+            MarketEvent::TradeSpread { .. } => EventTopic::TradeSpread,
+            MarketEvent::FairValue { .. } => EventTopic::FairValue,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        match self {
+            MarketEvent::Trade { symbol, .. }
+            | MarketEvent::BookUpdate { symbol, .. }
+            | MarketEvent::BboChange { symbol, .. }
+            | MarketEvent::Metrics { symbol, .. }
+            | MarketEvent::Sequence { symbol, .. }
+            | MarketEvent::Bar { symbol, .. }
+            | MarketEvent::Toxicity { symbol, .. }
+            | MarketEvent::Vpin { symbol, .. }
+            | MarketEvent::TradeSpread { symbol, .. }
+            | MarketEvent::FairValue { symbol, .. } => symbol,
+        }
+    }
+}
+
+struct Subscriber {
+    id: u64,
+    topics: Vec<EventTopic>,
+    symbol: Option<String>,
+    sender: Sender<MarketEvent>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Subscriber {
+    fn wants(&self, event: &MarketEvent) -> bool {
+        self.topics.contains(&event.topic())
+            && self.symbol.as_deref().is_none_or(|symbol| symbol == event.symbol())
+    }
+}
+
+/// Receiving end of a subscription. Each subscriber has its own bounded
+/// queue; when it is full new events for that subscriber are dropped and
+/// counted instead of holding up processing.
+pub struct Subscription {
+    id: u64,
+    receiver: Receiver<MarketEvent>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn try_recv(&self) -> Option<MarketEvent> {
+        match self.receiver.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> MarketDataResult<Option<MarketEvent>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => Ok(Some(event)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(MarketDataError::Disconnected),
+        }
+    }
+
+    pub fn drain(&self) -> Vec<MarketEvent> {
+        self.receiver.try_iter().collect()
+    }
+
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: RwLock<Vec<Subscriber>>,
+    // Subscribers per topic, so publishers can skip building unwanted events
+    topic_counts: [AtomicUsize; EventTopic::ALL.len()],
+    next_id: AtomicU64,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self, topics: &[EventTopic], symbol: Option<&str>, capacity: usize) -> MarketDataResult<Subscription> {
+        if topics.is_empty() {
+            return Err(MarketDataError::Validation("subscription without topics".to_string()));
+        }
+        if capacity == 0 {
+            return Err(MarketDataError::Validation("subscription capacity must be positive".to_string()));
+        }
+
+        let (sender, receiver) = bounded(capacity);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        let mut topics = topics.to_vec();
+        topics.sort_by_key(|topic| topic.index());
+        topics.dedup();
+
+        let mut subscribers = self.subscribers.write()?;
+        for topic in &topics {
+            self.topic_counts[topic.index()].fetch_add(1, Ordering::Relaxed);
+        }
+        subscribers.push(Subscriber {
+            id,
+            topics,
+            symbol: symbol.map(str::to_string),
+            sender,
+            dropped: Arc::clone(&dropped),
+        });
+
+        Ok(Subscription { id, receiver, dropped })
+    }
+
+    pub fn unsubscribe(&self, id: u64) -> MarketDataResult<bool> {
+        let mut subscribers = self.subscribers.write()?;
+        match subscribers.iter().position(|subscriber| subscriber.id == id) {
+            Some(index) => {
+                let removed = subscribers.swap_remove(index);
+                for topic in &removed.topics {
+                    self.topic_counts[topic.index()].fetch_sub(1, Ordering::Relaxed);
+                }
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.read().map(|subscribers| subscribers.len()).unwrap_or(0)
+    }
+
+    pub fn has_subscribers(&self, topic: EventTopic) -> bool {
+        self.topic_counts[topic.index()].load(Ordering::Relaxed) > 0
+    }
+
+    pub fn has_any_subscribers(&self) -> bool {
+        EventTopic::ALL.iter().any(|topic| self.has_subscribers(*topic))
+    }
+
+    pub fn publish(&self, event: MarketEvent) {
+        let mut disconnected = Vec::new();
+
+        if let Ok(subscribers) = self.subscribers.read() {
+            for subscriber in subscribers.iter().filter(|subscriber| subscriber.wants(&event)) {
+                match subscriber.sender.try_send(event.clone()) {
+                    Ok(()) => {},
+                    Err(TrySendError::Full(_)) => {
+                        subscriber.dropped.fetch_add(1, Ordering::Relaxed);
+                    },
+                    Err(TrySendError::Disconnected(_)) => disconnected.push(subscriber.id),
+                }
+            }
+        }
+
+        // Subscriptions dropped by their owner are cleaned up lazily
+        for id in disconnected {
+            let _ = self.unsubscribe(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(symbol: &str, timestamp_ns: u64) -> MarketEvent {
+        MarketEvent::Trade {
+            symbol: symbol.to_string(),
+            timestamp_ns,
+            price: 100.0,
+            quantity: 1.0,
+            aggressor: TradeSign::unclassified(),
+        }
+    }
+
+    #[test]
+    fn slow_subscriber_drops_without_blocking_others() {
+        let bus = EventBus::new();
+        let slow = bus.subscribe(&[EventTopic::Trade], None, 1).unwrap();
+        let fast = bus.subscribe(&[EventTopic::Trade], None, 100).unwrap();
+
+        for timestamp_ns in 0..10 {
+            bus.publish(trade("X", timestamp_ns));
+        }
+
+        assert_eq!(fast.drain().len(), 10);
+        assert_eq!(fast.dropped_count(), 0);
+        // The slow subscriber keeps the first event and drops the rest
+        assert_eq!(slow.drain(), vec![trade("X", 0)]);
+        assert_eq!(slow.dropped_count(), 9);
+
+        bus.publish(trade("X", 10));
+        assert_eq!(slow.try_recv(), Some(trade("X", 10)));
+        assert_eq!(slow.dropped_count(), 9);
+    }
+
+    #[test]
+    fn events_are_routed_by_topic_and_symbol() {
+        let bus = EventBus::new();
+        let all = bus.subscribe(&[EventTopic::Trade], None, 10).unwrap();
+        let only_y = bus.subscribe(&[EventTopic::Trade], Some("Y"), 10).unwrap();
+        let bars = bus.subscribe(&[EventTopic::Bar], None, 10).unwrap();
+
+        assert!(bus.has_subscribers(EventTopic::Trade));
+        assert!(!bus.has_subscribers(EventTopic::Vpin));
+
+        bus.publish(trade("X", 1));
+        bus.publish(trade("Y", 2));
+
+        assert_eq!(all.drain(), vec![trade("X", 1), trade("Y", 2)]);
+        assert_eq!(only_y.drain(), vec![trade("Y", 2)]);
+        assert!(bars.drain().is_empty());
+        assert_eq!(only_y.dropped_count(), 0);
+    }
+
+    #[test]
+    fn dropped_subscriptions_are_removed_on_publish() {
+        let bus = EventBus::new();
+        let kept = bus.subscribe(&[EventTopic::Trade], None, 10).unwrap();
+        drop(bus.subscribe(&[EventTopic::Trade, EventTopic::Bar], None, 10).unwrap());
+        assert_eq!(bus.subscriber_count(), 2);
+
+        bus.publish(trade("X", 1));
+
+        assert_eq!(bus.subscriber_count(), 1);
+        assert!(!bus.has_subscribers(EventTopic::Bar));
+        assert_eq!(kept.drain().len(), 1);
+        assert!(bus.unsubscribe(kept.id()).unwrap());
+        assert!(!bus.has_any_subscribers());
+    }
+}
@@ -0,0 +1,397 @@
+use super::fixed_point::{Precision, Price, Total};
+use super::order_book::{OrderBook, Side};
+
+/// Levels `0..depth` of both sides, each size-weighted like the top of the
+/// book and weighted `decay^level` against the others.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeightedMidSpec {
+    pub depth: usize,
+    pub decay: f64,
+}
+
+impl WeightedMidSpec {
+    pub fn new(depth: usize, decay: f64) -> Self {
+        WeightedMidSpec { depth, decay }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FairValueConfig {
+    pub weighted_mids: Vec<WeightedMidSpec>,
+    /// Equal-width buckets of the queue imbalance in the microprice model.
+    pub imbalance_buckets: usize,
+    /// Widest spread, in ticks, the model covers; wider books have no
+    /// microprice.
+    pub max_spread_ticks: usize,
+    /// Tick the spread is measured in; the symbol's price increment when
+    /// None. Unscaled symbols have no increment, so need it set to get a
+    /// microprice.
+    pub tick_size: Option<f64>,
+    /// Top-of-book changes between refits of the model.
+    pub refit_every: usize,
+    /// Terms of the series the microprice adjustment is summed over.
+    pub iterations: usize,
+}
+
+impl Default for FairValueConfig {
+    fn default() -> Self {
+        FairValueConfig {
+            weighted_mids: vec![WeightedMidSpec::new(5, 0.5)],
+            imbalance_buckets: 10,
+            max_spread_ticks: 2,
+            tick_size: None,
+            refit_every: 1_000,
+            iterations: 6,
+        }
+    }
+}
+
+/// Fair-value estimates of a two-sided book.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FairValue {
+    pub timestamp_ns: u64,
+    pub mid: f64,
+    /// Best bid and ask, each weighted by the size on the other side.
+    pub weighted_mid: f64,
+    /// Stoikov's microprice: the mid plus the expected sum of its future
+    /// moves given the current imbalance and spread. None until the model
+    /// has been fitted and has seen the current state.
+    pub microprice: Option<f64>,
+    /// One per configured spec.
+    pub weighted_mids: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Top {
+    bid: Price,
+    bid_quantity: Total,
+    ask: Price,
+    ask_quantity: Total,
+}
+
+/// Keeps the estimates current on every message and learns the microprice
+/// model from top-of-book transitions, mirrored so that buy and sell
+/// pressure are treated alike.
+#[derive(Debug)]
+pub(crate) struct FairValueTracker {
+    config: FairValueConfig,
+    precision: Precision,
+    tick: Option<Price>,
+    buckets: usize,
+    // Levels read from the book: the deepest spec, and at least the top
+    depth: usize,
+    latest: Option<FairValue>,
+    // Top of book after the previous message
+    top: Option<Top>,
+    transitions: TransitionCounts,
+    since_refit: usize,
+    // Microprice adjustment by state, once fitted
+    adjustments: Option<Vec<f64>>,
+}
+
+impl FairValueTracker {
+    pub(crate) fn new(config: &FairValueConfig, precision: Precision) -> Self {
+        let tick = config.tick_size
+            .and_then(|tick| precision.price(tick).ok())
+            .filter(|tick| tick.0 > 0)
+            .or_else(|| precision.price_increment());
+        let buckets = config.imbalance_buckets.max(1);
+        FairValueTracker {
+            depth: config.weighted_mids.iter().map(|spec| spec.depth).max().unwrap_or(0).max(1),
+            transitions: TransitionCounts::new(buckets * config.max_spread_ticks.max(1)),
+            config: config.clone(),
+            precision,
+            tick,
+            buckets,
+            latest: None,
+            top: None,
+            since_refit: 0,
+            adjustments: None,
+        }
+    }
+
+    pub(crate) fn latest(&self) -> Option<FairValue> {
+        self.latest.clone()
+    }
+
+    /// Called after every applied message with the book it left; returns
+    /// the estimates when they changed.
+    pub(crate) fn update(&mut self, timestamp_ns: u64, book: &OrderBook) -> Option<FairValue> {
+        let bids = book.depth(Side::Bid, self.depth);
+        let asks = book.depth(Side::Ask, self.depth);
+        let top = match (bids.first(), asks.first()) {
+            (Some((bid, bid_quantity)), Some((ask, ask_quantity))) => Some(Top {
+                bid: *bid,
+                bid_quantity: *bid_quantity,
+                ask: *ask,
+                ask_quantity: *ask_quantity,
+            }),
+            _ => None,
+        };
+        if top != self.top {
+            if let (Some(before), Some(after)) = (self.top, top) {
+                self.learn(before, after);
+            }
+            self.top = top;
+        }
+
+        let value = top.map(|top| {
+            let mid = self.precision.midpoint_to_f64(top.bid, top.ask);
+            FairValue {
+                timestamp_ns,
+                mid,
+                weighted_mid: self.weighted_mid(&bids, &asks, WeightedMidSpec::new(1, 1.0)).unwrap_or(mid),
+                microprice: self.state(top)
+                    .and_then(|state| self.adjustments.as_ref().map(|adjustments| adjustments[state]))
+                    .filter(|adjustment| adjustment.is_finite())
+                    .map(|adjustment| mid + adjustment),
+                weighted_mids: self.config.weighted_mids.iter()
+                    .map(|spec| self.weighted_mid(&bids, &asks, *spec).unwrap_or(mid))
+                    .collect(),
+            }
+        });
+        let changed = match (&value, &self.latest) {
+            (Some(value), Some(latest)) => !same_estimates(value, latest),
+            (None, None) => false,
+            _ => true,
+        };
+        self.latest = value;
+        if changed { self.latest.clone() } else { None }
+    }
+
+    // Levels present on both sides, each priced at its bid and ask weighted
+    // by the opposite sizes. None when every level has zero size
+    fn weighted_mid(&self, bids: &[(Price, Total)], asks: &[(Price, Total)], spec: WeightedMidSpec) -> Option<f64> {
+        let (mut numerator, mut denominator, mut weight) = (0.0, 0.0, 1.0);
+        for ((bid, bid_quantity), (ask, ask_quantity)) in bids.iter().zip(asks).take(spec.depth.max(1)) {
+            let bid_size = self.precision.quantity_total_to_f64(*bid_quantity);
+            let ask_size = self.precision.quantity_total_to_f64(*ask_quantity);
+            numerator += weight * (self.precision.price_to_f64(*bid) * ask_size + self.precision.price_to_f64(*ask) * bid_size);
+            denominator += weight * (bid_size + ask_size);
+            weight *= spec.decay;
+        }
+        if denominator <= 0.0 {
+            return None;
+        }
+        Some(numerator / denominator)
+    }
+
+    // Model state of a top of book: spread in ticks, then imbalance bucket
+    fn state(&self, top: Top) -> Option<usize> {
+        let spread = self.precision.ticks_in(self.precision.price_difference(top.ask, top.bid), self.tick?);
+        if spread < 1 || spread as usize > self.config.max_spread_ticks {
+            return None;
+        }
+        let bid_size = self.precision.quantity_total_to_f64(top.bid_quantity);
+        let total = bid_size + self.precision.quantity_total_to_f64(top.ask_quantity);
+        if total <= 0.0 {
+            return None;
+        }
+        let imbalance = bid_size / total;
+        let bucket = ((imbalance * self.buckets as f64) as usize).min(self.buckets - 1);
+        Some((spread as usize - 1) * self.buckets + bucket)
+    }
+
+    fn mirror(&self, state: usize) -> usize {
+        let spread = state / self.buckets;
+        spread * self.buckets + self.buckets - 1 - state % self.buckets
+    }
+
+    fn learn(&mut self, before: Top, after: Top) {
+        let (from, to) = match (self.state(before), self.state(after)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return,
+        };
+        let mid_change = self.precision.midpoint_to_f64(after.bid, after.ask) - self.precision.midpoint_to_f64(before.bid, before.ask);
+        self.transitions.record(from, to, mid_change);
+        self.transitions.record(self.mirror(from), self.mirror(to), -mid_change);
+
+        self.since_refit += 1;
+        if self.since_refit >= self.config.refit_every.max(1) {
+            self.since_refit = 0;
+            if let Some(adjustments) = self.transitions.fit(self.config.iterations) {
+                self.adjustments = Some(adjustments);
+            }
+        }
+    }
+}
+
+// Only the estimates decide whether a change is published
+fn same_estimates(value: &FairValue, latest: &FairValue) -> bool {
+    value.mid == latest.mid
+        && value.weighted_mid == latest.weighted_mid
+        && value.microprice == latest.microprice
+        && value.weighted_mids == latest.weighted_mids
+}
+
+/// Transition counts between model states, split by whether the mid moved.
+#[derive(Debug)]
+struct TransitionCounts {
+    states: usize,
+    totals: Vec<f64>,
+    // Row-major `states x states`
+    unchanged: Vec<f64>,
+    moved: Vec<f64>,
+    mid_change_sums: Vec<f64>,
+}
+
+impl TransitionCounts {
+    fn new(states: usize) -> Self {
+        TransitionCounts {
+            states,
+            totals: vec![0.0; states],
+            unchanged: vec![0.0; states * states],
+            moved: vec![0.0; states * states],
+            mid_change_sums: vec![0.0; states],
+        }
+    }
+
+    fn record(&mut self, from: usize, to: usize, mid_change: f64) {
+        self.totals[from] += 1.0;
+        if mid_change == 0.0 {
+            self.unchanged[from * self.states + to] += 1.0;
+        } else {
+            self.moved[from * self.states + to] += 1.0;
+            self.mid_change_sums[from] += mid_change;
+        }
+    }
+
+    // With Q the transitions that keep the mid, T those that move it and R
+    // the expected move: G1 = (I - Q)^-1 R, B = (I - Q)^-1 T, and the
+    // adjustment is G1 + B G1 + B^2 G1 + ... Unseen states get NaN. Q is
+    // shrunk slightly so states never seen to move the mid stay solvable
+    fn fit(&self, iterations: usize) -> Option<Vec<f64>> {
+        let n = self.states;
+        let width = 2 * n + 1;
+        let mut matrix = vec![0.0; n * width];
+        for from in 0..n {
+            let total = self.totals[from];
+            let row = &mut matrix[from * width..(from + 1) * width];
+            row[from] = 1.0;
+            if total == 0.0 {
+                continue;
+            }
+            for to in 0..n {
+                row[to] -= (1.0 - 1e-9) * self.unchanged[from * n + to] / total;
+                row[n + to] = self.moved[from * n + to] / total;
+            }
+            row[2 * n] = self.mid_change_sums[from] / total;
+        }
+        solve(&mut matrix, n, width)?;
+
+        let b = |from: usize, to: usize| matrix[from * width + n + to];
+        let first: Vec<f64> = (0..n).map(|state| matrix[state * width + 2 * n]).collect();
+        let mut adjustments = first.clone();
+        let mut term = first;
+        for _ in 1..iterations.max(1) {
+            term = (0..n).map(|from| (0..n).map(|to| b(from, to) * term[to]).sum()).collect();
+            for (adjustment, value) in adjustments.iter_mut().zip(&term) {
+                *adjustment += value;
+            }
+        }
+        for (adjustment, total) in adjustments.iter_mut().zip(&self.totals) {
+            if *total == 0.0 {
+                *adjustment = f64::NAN;
+            }
+        }
+        Some(adjustments)
+    }
+}
+
+// Gauss-Jordan elimination with partial pivoting on the left `n` columns of
+// a row-major `n x width` matrix, leaving the solutions in the rest
+fn solve(matrix: &mut [f64], n: usize, width: usize) -> Option<()> {
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|a, b| matrix[a * width + column].abs().total_cmp(&matrix[b * width + column].abs()))?;
+        if matrix[pivot * width + column].abs() < 1e-15 {
+            return None;
+        }
+        for k in 0..width {
+            matrix.swap(column * width + k, pivot * width + k);
+        }
+        let scale = matrix[column * width + column];
+        for k in 0..width {
+            matrix[column * width + k] /= scale;
+        }
+        for row in 0..n {
+            let factor = matrix[row * width + column];
+            if row == column || factor == 0.0 {
+                continue;
+            }
+            for k in 0..width {
+                matrix[row * width + k] -= factor * matrix[column * width + k];
+            }
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixed_point::{FixedScale, Quantity};
+
+    fn tracker(refit_every: usize) -> FairValueTracker {
+        let config = FairValueConfig {
+            weighted_mids: vec![WeightedMidSpec::new(2, 0.5)],
+            imbalance_buckets: 2,
+            max_spread_ticks: 2,
+            tick_size: None,
+            refit_every,
+            iterations: 6,
+        };
+        FairValueTracker::new(&config, Precision::new(FixedScale::decimals(2), FixedScale::decimals(0)))
+    }
+
+    #[test]
+    fn weighted_mids_lean_towards_the_thinner_side() {
+        let mut tracker = tracker(1_000);
+        let mut book = OrderBook::new();
+        book.add_order("b1", Side::Bid, Price(10_000), Quantity(30), 1).unwrap();
+        assert_eq!(tracker.update(1, &book), None);
+        book.add_order("a1", Side::Ask, Price(10_001), Quantity(10), 2).unwrap();
+        let value = tracker.update(2, &book).unwrap();
+        assert_eq!(value.mid, 100.005);
+        assert!((value.weighted_mid - (100.0 * 10.0 + 100.01 * 30.0) / 40.0).abs() < 1e-9);
+        assert_eq!(value.microprice, None);
+
+        book.add_order("b2", Side::Bid, Price(9_999), Quantity(10), 3).unwrap();
+        book.add_order("a2", Side::Ask, Price(10_002), Quantity(10), 3).unwrap();
+        let value = tracker.update(3, &book).unwrap();
+        let expected = (100.0 * 10.0 + 100.01 * 30.0 + 0.5 * (99.99 * 10.0 + 100.02 * 10.0)) / (40.0 + 0.5 * 20.0);
+        assert!((value.weighted_mids[0] - expected).abs() < 1e-9);
+        // Nothing changed
+        assert_eq!(tracker.update(4, &book), None);
+    }
+
+    #[test]
+    fn microprice_follows_learned_imbalance_and_is_symmetric() {
+        let mut tracker = tracker(20);
+        let mut book = OrderBook::new();
+        book.add_order("b0", Side::Bid, Price(10_000), Quantity(9), 0).unwrap();
+        book.add_order("a", Side::Ask, Price(10_001), Quantity(1), 0).unwrap();
+        tracker.update(0, &book);
+
+        // A bid-heavy book where the thin ask keeps lifting and the bid follows
+        for (step, bid) in (1..=10).zip(10_000..) {
+            book.modify_order("a", Some(Price(bid + 2)), None, step).unwrap();
+            tracker.update(step, &book);
+            book.add_order(&format!("b{}", step), Side::Bid, Price(bid + 1), Quantity(9), step).unwrap();
+            tracker.update(step, &book);
+        }
+        let bid_heavy = tracker.latest().unwrap();
+        let bid_heavy_adjustment = bid_heavy.microprice.unwrap() - bid_heavy.mid;
+        assert!(bid_heavy_adjustment > 0.0);
+
+        let mut mirrored = OrderBook::new();
+        mirrored.add_order("b", Side::Bid, Price(10_000), Quantity(1), 20).unwrap();
+        mirrored.add_order("a", Side::Ask, Price(10_001), Quantity(9), 20).unwrap();
+        let ask_heavy = tracker.update(20, &mirrored).unwrap();
+        assert!((ask_heavy.microprice.unwrap() - ask_heavy.mid + bid_heavy_adjustment).abs() < 1e-9);
+
+        // Too wide for the model
+        mirrored.modify_order("a", Some(Price(10_005)), None, 21).unwrap();
+        assert_eq!(tracker.update(21, &mirrored).unwrap().microprice, None);
+    }
+}
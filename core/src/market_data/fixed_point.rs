@@ -0,0 +1,421 @@
+use std::cmp::Ordering;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+use super::error::{MarketDataError, MarketDataResult};
+
+// Largest power of ten that fits in an i64
+const MAX_DECIMALS: u32 = 18;
+
+// Distance from the grid, in ulps of the scaled value, still taken as float
+// representation error
+const ROUNDING_ULPS: f64 = 4.0;
+
+/// Price in integer units of the symbol's price scale. Arithmetic goes
+/// through the symbol's `Precision`, since unscaled symbols carry the float
+/// itself here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Price(pub i64);
+
+/// Quantity in integer units of the symbol's quantity scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Quantity(pub i64);
+
+impl Quantity {
+    pub const ZERO: Quantity = Quantity(0);
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// A running sum of quantities, notionals or price-time products. Exact in
+/// i128 units on a grid, where it cannot overflow on any realistic feed, and
+/// a float for unscaled symbols.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Total {
+    Units(i128),
+    Float(f64),
+}
+
+impl Total {
+    pub const ZERO: Total = Total::Units(0);
+
+    pub fn is_zero(self) -> bool {
+        match self {
+            Total::Units(units) => units == 0,
+            Total::Float(value) => value == 0.0,
+        }
+    }
+}
+
+impl Default for Total {
+    fn default() -> Self {
+        Total::ZERO
+    }
+}
+
+// Totals of one symbol share a representation; a zero in units is the
+// starting value of every sum and mixes with a float as zero
+impl Add for Total {
+    type Output = Total;
+
+    fn add(self, other: Total) -> Total {
+        match (self, other) {
+            (Total::Units(a), Total::Units(b)) => Total::Units(a.saturating_add(b)),
+            (Total::Float(a), Total::Float(b)) => Total::Float(a + b),
+            (Total::Units(a), Total::Float(b)) | (Total::Float(b), Total::Units(a)) => Total::Float(a as f64 + b),
+        }
+    }
+}
+
+impl Sub for Total {
+    type Output = Total;
+
+    fn sub(self, other: Total) -> Total {
+        match (self, other) {
+            (Total::Units(a), Total::Units(b)) => Total::Units(a.saturating_sub(b)),
+            (Total::Float(a), Total::Float(b)) => Total::Float(a - b),
+            (Total::Units(a), Total::Float(b)) => Total::Float(a as f64 - b),
+            (Total::Float(a), Total::Units(b)) => Total::Float(a - b as f64),
+        }
+    }
+}
+
+impl AddAssign for Total {
+    fn add_assign(&mut self, other: Total) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Total {
+    fn sub_assign(&mut self, other: Total) {
+        *self = *self - other;
+    }
+}
+
+/// A decimal grid: values are carried as integer multiples of
+/// `10^-decimals`, and must be whole multiples of `increment` such units.
+/// A tick size of 0.05 is `FixedScale::tick(2, 5)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedScale {
+    pub decimals: u32,
+    pub increment: i64,
+}
+
+impl FixedScale {
+    pub const fn decimals(decimals: u32) -> Self {
+        FixedScale { decimals, increment: 1 }
+    }
+
+    pub const fn tick(decimals: u32, increment: i64) -> Self {
+        FixedScale { decimals, increment }
+    }
+
+    fn factor(&self) -> f64 {
+        10f64.powi(self.decimals.min(MAX_DECIMALS) as i32)
+    }
+
+    /// Converts a float to units, rejecting values that are not on the grid
+    /// beyond float representation error.
+    pub fn to_units(&self, value: f64) -> MarketDataResult<i64> {
+        let scaled = value * self.factor();
+        let units = self.round_to_units(value)?;
+        // Parsing and scaling each cost at most an ulp of the scaled value
+        let tolerance = ROUNDING_ULPS * f64::EPSILON * scaled.abs().max(1.0);
+        if (scaled - units as f64).abs() > tolerance {
+            return Err(MarketDataError::Validation(format!("{} has more than {} decimals", value, self.decimals)));
+        }
+        if units % self.increment.max(1) != 0 {
+            return Err(MarketDataError::Validation(format!("{} is not a multiple of the {} increment", value, self.to_f64(self.increment))));
+        }
+        Ok(units)
+    }
+
+    /// Converts a float to the nearest unit, only rejecting values out of
+    /// range. The increment is not applied.
+    pub fn round_to_units(&self, value: f64) -> MarketDataResult<i64> {
+        let units = (value * self.factor()).round();
+        if !units.is_finite() || units.abs() >= i64::MAX as f64 {
+            return Err(MarketDataError::Validation(format!("{} is out of range for {} decimals", value, self.decimals)));
+        }
+        if units == 0.0 && value != 0.0 {
+            return Err(MarketDataError::Validation(format!("{} rounds to zero at {} decimals", value, self.decimals)));
+        }
+        Ok(units as i64)
+    }
+
+    pub fn to_f64(&self, units: i64) -> f64 {
+        units as f64 / self.factor()
+    }
+}
+
+/// Price and quantity grids of a symbol, or none for a symbol kept
+/// unscaled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Precision {
+    pub price: FixedScale,
+    pub quantity: FixedScale,
+    // Values are the floats themselves, encoded by `encode`, and the grids
+    // above are unused
+    unscaled: bool,
+}
+
+impl Default for Precision {
+    /// Eight decimals for both, with no tick constraint.
+    fn default() -> Self {
+        Precision::new(FixedScale::decimals(8), FixedScale::decimals(8))
+    }
+}
+
+impl Precision {
+    pub fn new(price: FixedScale, quantity: FixedScale) -> Self {
+        Precision { price, quantity, unscaled: false }
+    }
+
+    /// Keeps prices and quantities as given, with float arithmetic and no
+    /// range limit beyond that of f64.
+    pub fn unscaled() -> Self {
+        Precision {
+            price: FixedScale::decimals(0),
+            quantity: FixedScale::decimals(0),
+            unscaled: true,
+        }
+    }
+
+    pub fn is_unscaled(&self) -> bool {
+        self.unscaled
+    }
+
+    /// Converts a price, rejecting one that is off the price grid.
+    pub fn price(&self, value: f64) -> MarketDataResult<Price> {
+        if self.unscaled {
+            return encode(value).map(Price);
+        }
+        self.price.to_units(value).map(Price)
+    }
+
+    pub fn quantity(&self, value: f64) -> MarketDataResult<Quantity> {
+        if self.unscaled {
+            return encode(value).map(Quantity);
+        }
+        self.quantity.to_units(value).map(Quantity)
+    }
+
+    pub fn price_to_f64(&self, price: Price) -> f64 {
+        if self.unscaled { decode(price.0) } else { self.price.to_f64(price.0) }
+    }
+
+    pub fn quantity_to_f64(&self, quantity: Quantity) -> f64 {
+        if self.unscaled { decode(quantity.0) } else { self.quantity.to_f64(quantity.0) }
+    }
+
+    /// The symbol's price increment; None when unscaled.
+    pub fn price_increment(&self) -> Option<Price> {
+        (!self.unscaled).then(|| Price(self.price.increment.max(1)))
+    }
+
+    /// `high - low`, e.g. a spread.
+    pub fn price_difference(&self, high: Price, low: Price) -> Price {
+        if self.unscaled {
+            return Price(encode_unchecked(decode(high.0) - decode(low.0)));
+        }
+        Price(high.0.saturating_sub(low.0))
+    }
+
+    /// `high - low`, negative when `low` is larger.
+    pub fn quantity_difference(&self, high: Quantity, low: Quantity) -> Quantity {
+        if self.unscaled {
+            return Quantity(encode_unchecked(decode(high.0) - decode(low.0)));
+        }
+        Quantity(high.0.saturating_sub(low.0))
+    }
+
+    /// Whole ticks in a price distance, rounded half up.
+    pub fn ticks_in(&self, distance: Price, tick: Price) -> i64 {
+        if self.unscaled {
+            return (decode(distance.0) / decode(tick.0) + 0.5).floor() as i64;
+        }
+        let tick = tick.0.max(1);
+        distance.0.saturating_add(tick / 2) / tick
+    }
+
+    /// Halfway between two prices; may fall between grid points.
+    pub fn midpoint_to_f64(&self, low: Price, high: Price) -> f64 {
+        if self.unscaled {
+            return (decode(low.0) + decode(high.0)) / 2.0;
+        }
+        (low.0 as i128 + high.0 as i128) as f64 / self.price.factor() / 2.0
+    }
+
+    /// How a price compares with the midpoint of two others, exactly on a
+    /// grid.
+    pub fn cmp_to_midpoint(&self, price: Price, low: Price, high: Price) -> Ordering {
+        if self.unscaled {
+            let doubled_mid = decode(low.0) + decode(high.0);
+            return (2.0 * decode(price.0)).partial_cmp(&doubled_mid).unwrap_or(Ordering::Equal);
+        }
+        (2 * price.0 as i128).cmp(&(low.0 as i128 + high.0 as i128))
+    }
+
+    pub fn quantity_total(&self, quantity: Quantity) -> Total {
+        if self.unscaled {
+            return Total::Float(decode(quantity.0));
+        }
+        Total::Units(quantity.0 as i128)
+    }
+
+    /// Price times quantity, exact in units of both scales combined.
+    pub fn notional(&self, price: Price, quantity: Quantity) -> Total {
+        if self.unscaled {
+            return Total::Float(decode(price.0) * decode(quantity.0));
+        }
+        Total::Units(price.0 as i128 * quantity.0 as i128)
+    }
+
+    /// A price held for a duration, for time-weighted averages.
+    pub fn price_time(&self, price: Price, duration_ns: u64) -> Total {
+        if self.unscaled {
+            return Total::Float(decode(price.0) * duration_ns as f64);
+        }
+        Total::Units((price.0 as i128).saturating_mul(duration_ns as i128))
+    }
+
+    pub fn quantity_total_to_f64(&self, total: Total) -> f64 {
+        match total {
+            Total::Units(units) => units as f64 / self.quantity.factor(),
+            Total::Float(value) => value,
+        }
+    }
+
+    pub fn notional_to_f64(&self, notional: Total) -> f64 {
+        match notional {
+            Total::Units(units) => units as f64 / (self.price.factor() * self.quantity.factor()),
+            Total::Float(value) => value,
+        }
+    }
+
+    pub fn price_time_to_f64(&self, price_time: Total) -> f64 {
+        match price_time {
+            Total::Units(units) => units as f64 / self.price.factor(),
+            Total::Float(value) => value,
+        }
+    }
+
+    /// Average price of a notional over a volume.
+    pub fn average_price(&self, notional: Total, volume: Total) -> Option<f64> {
+        if volume.is_zero() {
+            return None;
+        }
+        match (notional, volume) {
+            (Total::Units(notional), Total::Units(volume)) => Some(notional as f64 / volume as f64 / self.price.factor()),
+            _ => Some(self.notional_to_f64(notional) / self.quantity_total_to_f64(volume)),
+        }
+    }
+}
+
+// Maps a float onto an i64 that orders the same way, so unscaled values sort
+// and compare like grid units; zero maps to zero and the sign is kept
+fn encode(value: f64) -> MarketDataResult<i64> {
+    if !value.is_finite() {
+        return Err(MarketDataError::Validation(format!("{} is out of range", value)));
+    }
+    Ok(encode_unchecked(value))
+}
+
+fn encode_unchecked(value: f64) -> i64 {
+    // Adding zero turns -0.0 into 0.0
+    let bits = (value + 0.0).to_bits() as i64;
+    if bits < 0 { bits ^ i64::MAX } else { bits }
+}
+
+fn decode(units: i64) -> f64 {
+    let bits = if units < 0 { units ^ i64::MAX } else { units };
+    f64::from_bits(bits as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_error_is_tolerated_on_the_grid() {
+        let cents = FixedScale::decimals(2);
+        assert_eq!(cents.to_units(0.1 + 0.2).unwrap(), 30);
+        assert_eq!(cents.to_units(1.15).unwrap(), 115);
+        assert_eq!(cents.to_units(-2.68).unwrap(), -268);
+        assert_eq!(FixedScale::decimals(8).to_units(123_456.789_012_34).unwrap(), 12_345_678_901_234);
+        assert!(cents.to_units(0.305).is_err());
+        assert!(cents.to_units(0.3001).is_err());
+    }
+
+    #[test]
+    fn increments_are_enforced() {
+        let nickels = FixedScale::tick(2, 5);
+        assert_eq!(nickels.to_units(0.05).unwrap(), 5);
+        assert_eq!(nickels.to_units(12.35).unwrap(), 1_235);
+        assert!(nickels.to_units(0.03).is_err());
+        // Rounding ignores the increment
+        assert_eq!(nickels.round_to_units(0.034).unwrap(), 3);
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        let scale = FixedScale::decimals(8);
+        assert!(scale.round_to_units(1e10).is_ok());
+        assert!(scale.round_to_units(1e11).is_err());
+        assert!(scale.round_to_units(f64::NAN).is_err());
+        assert!(scale.to_units(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn exact_arithmetic_in_units() {
+        let precision = Precision::new(FixedScale::decimals(2), FixedScale::decimals(3));
+        let price = precision.price(101.25).unwrap();
+        let quantity = precision.quantity(0.5).unwrap();
+        let notional = precision.notional(price, quantity);
+        assert_eq!(notional, Total::Units(10_125 * 500));
+        assert_eq!(precision.notional_to_f64(notional), 50.625);
+        assert_eq!(precision.average_price(notional + notional, Total::Units(1_000)), Some(101.25));
+        assert_eq!(precision.average_price(Total::ZERO, Total::ZERO), None);
+        assert_eq!(precision.midpoint_to_f64(Price(10_000), Price(10_001)), 100.005);
+        assert_eq!(precision.cmp_to_midpoint(Price(10_001), Price(10_000), Price(10_002)), Ordering::Equal);
+    }
+
+    #[test]
+    fn totals_hold_volumes_beyond_i64_units() {
+        let precision = Precision::default();
+        let quantity = precision.quantity(9e10).unwrap();
+        let mut volume = Total::ZERO;
+        for _ in 0..3 {
+            volume += precision.quantity_total(quantity);
+        }
+        assert_eq!(precision.quantity_total_to_f64(volume), 2.7e11);
+        assert_eq!(precision.midpoint_to_f64(Price(i64::MAX - 1), Price(i64::MAX - 1)), (i64::MAX - 1) as f64 / 1e8);
+    }
+
+    #[test]
+    fn positive_values_that_round_to_zero_are_rejected() {
+        let cents = FixedScale::decimals(2);
+        assert!(cents.round_to_units(0.004).is_err());
+        assert!(cents.to_units(1e-9).is_err());
+        assert_eq!(cents.round_to_units(0.0).unwrap(), 0);
+    }
+
+    #[test]
+    fn unscaled_values_keep_their_order_and_value() {
+        let precision = Precision::unscaled();
+        let values = [-2.5, -1e-9, 0.0, 1e-9, 0.1, 2e11, 1e300];
+        let prices: Vec<Price> = values.iter().map(|value| precision.price(*value).unwrap()).collect();
+        assert!(prices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(prices.iter().map(|price| precision.price_to_f64(*price)).collect::<Vec<_>>(), values);
+        assert_eq!(precision.price(-0.0).unwrap(), Price(0));
+        assert!(precision.price(f64::NAN).is_err());
+
+        let (bid, ask) = (precision.price(100.25).unwrap(), precision.price(100.75).unwrap());
+        assert_eq!(precision.price_to_f64(precision.price_difference(ask, bid)), 0.5);
+        assert_eq!(precision.ticks_in(precision.price_difference(ask, bid), precision.price(0.25).unwrap()), 2);
+        let quantity = precision.quantity(3.0).unwrap();
+        assert_eq!(precision.notional_to_f64(precision.notional(bid, quantity)), 300.75);
+        assert!(precision.quantity_difference(quantity, precision.quantity(4.0).unwrap()) < Quantity::ZERO);
+    }
+}
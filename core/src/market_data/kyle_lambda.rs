@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::chunked::ChunkedVec;
+use super::regression::fit_line;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KyleLambdaConfig {
+    /// Length of each observation, aligned to multiples of it in event time.
+    pub interval: Duration,
+    /// Intervals in each regression.
+    pub window: usize,
+    /// Estimates kept in the series.
+    pub max_points: usize,
+}
+
+impl Default for KyleLambdaConfig {
+    fn default() -> Self {
+        KyleLambdaConfig {
+            interval: Duration::from_secs(5),
+            window: 60,
+            max_points: 10_000,
+        }
+    }
+}
+
+/// Regression of the interval's mid change on its signed volume, with an
+/// intercept, over the intervals ending at `timestamp_ns`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KyleLambdaPoint {
+    /// Exclusive end of the last interval in the regression.
+    pub timestamp_ns: u64,
+    /// Price change per unit of net buying.
+    pub lambda: f64,
+    pub standard_error: f64,
+    pub r_squared: f64,
+    pub observations: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct KyleLambdaSeries {
+    pub config: KyleLambdaConfig,
+    pub points: ChunkedVec<KyleLambdaPoint>,
+}
+
+impl KyleLambdaSeries {
+    pub fn latest(&self) -> Option<&KyleLambdaPoint> {
+        self.points.last()
+    }
+
+    /// Estimates whose last interval ended within `[start_ns, end_ns]`.
+    pub fn range(&self, start_ns: u64, end_ns: u64) -> Vec<KyleLambdaPoint> {
+        let from = self.points.partition_point(|point| point.timestamp_ns < start_ns);
+        let to = self.points.partition_point(|point| point.timestamp_ns <= end_ns);
+        self.points.range(from, to).copied().collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OpenInterval {
+    start_ns: u64,
+    start_price: Option<f64>,
+    signed_volume: f64,
+}
+
+/// Accumulates signed volume per interval and refits when one closes.
+/// Intervals without messages have no flow and no price change, so they are
+/// left out rather than recorded as zeros.
+#[derive(Debug)]
+pub(crate) struct KyleLambdaEstimator {
+    config: KyleLambdaConfig,
+    interval_ns: u64,
+    current: Option<OpenInterval>,
+    // (signed volume, price change) of recent intervals
+    observations: VecDeque<(f64, f64)>,
+    points: ChunkedVec<KyleLambdaPoint>,
+}
+
+impl KyleLambdaEstimator {
+    pub(crate) fn new(config: KyleLambdaConfig) -> Self {
+        KyleLambdaEstimator {
+            config,
+            interval_ns: u64::try_from(config.interval.as_nanos()).unwrap_or(u64::MAX).max(1),
+            current: None,
+            observations: VecDeque::new(),
+            points: ChunkedVec::new(),
+        }
+    }
+
+    /// Called before a message is applied with the price prevailing before
+    /// it. Closes the open interval if the message starts a later one.
+    pub(crate) fn advance(&mut self, timestamp_ns: u64, price: Option<f64>) {
+        let start_ns = timestamp_ns - timestamp_ns % self.interval_ns;
+        match self.current {
+            Some(current) if start_ns <= current.start_ns => return,
+            Some(current) => {
+                if let (Some(start_price), Some(end_price)) = (current.start_price, price) {
+                    self.add_observation(current.signed_volume, end_price - start_price, current.start_ns.saturating_add(self.interval_ns));
+                }
+            },
+            None => {},
+        }
+        self.current = Some(OpenInterval { start_ns, start_price: price, signed_volume: 0.0 });
+    }
+
+    /// Adds a trade to the open interval; `buy_fraction` signs its volume.
+    pub(crate) fn add_trade(&mut self, quantity: f64, buy_fraction: f64) {
+        if let Some(current) = &mut self.current {
+            current.signed_volume += quantity * (2.0 * buy_fraction - 1.0);
+        }
+    }
+
+    pub(crate) fn series(&self) -> KyleLambdaSeries {
+        KyleLambdaSeries {
+            config: self.config,
+            points: self.points.clone(),
+        }
+    }
+
+    fn add_observation(&mut self, signed_volume: f64, price_change: f64, end_ns: u64) {
+        self.observations.push_back((signed_volume, price_change));
+        if self.observations.len() > self.config.window.max(3) {
+            self.observations.pop_front();
+        }
+
+        if let Some(point) = self.fit(end_ns) {
+            self.points.push(point);
+            self.points.remove_front(self.points.len().saturating_sub(self.config.max_points.max(1)));
+        }
+    }
+
+    fn fit(&self, end_ns: u64) -> Option<KyleLambdaPoint> {
+        let fit = fit_line(&self.observations)?;
+        Some(KyleLambdaPoint {
+            timestamp_ns: end_ns,
+            lambda: fit.slope,
+            standard_error: fit.standard_error,
+            r_squared: fit.r_squared,
+            observations: self.observations.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimator(window: usize) -> KyleLambdaEstimator {
+        KyleLambdaEstimator::new(KyleLambdaConfig { interval: Duration::from_nanos(10), window, max_points: 100 })
+    }
+
+    // One trade per interval, the mid moving 0.002 plus `lambda` per unit of
+    // net buying; returns the final mid
+    fn feed(estimator: &mut KyleLambdaEstimator, signed_volumes: &[f64], lambda: f64) -> f64 {
+        let mut mid = 100.0;
+        for (i, signed_volume) in signed_volumes.iter().enumerate() {
+            estimator.advance(i as u64 * 10 + 3, Some(mid));
+            estimator.add_trade(signed_volume.abs(), if *signed_volume > 0.0 { 1.0 } else { 0.0 });
+            mid += 0.002 + lambda * signed_volume;
+        }
+        mid
+    }
+
+    #[test]
+    fn lambda_of_a_linear_series() {
+        let mut estimator = estimator(60);
+        let mid = feed(&mut estimator, &[10.0, -20.0, 5.0, 30.0, -15.0, 0.0], 0.01);
+        estimator.advance(60, Some(mid));
+
+        let series = estimator.series();
+        // Three intervals are needed for the first fit
+        assert_eq!(series.points.len(), 4);
+        let point = *series.latest().unwrap();
+        assert!((point.lambda - 0.01).abs() < 1e-12);
+        assert!(point.standard_error < 1e-9);
+        assert!((point.r_squared - 1.0).abs() < 1e-9);
+        assert_eq!(point.observations, 6);
+        assert_eq!(point.timestamp_ns, 60);
+        assert_eq!(series.range(0, 49).len(), 2);
+    }
+
+    #[test]
+    fn window_drops_old_intervals() {
+        let mut estimator = estimator(3);
+        // A regime change: only the last three intervals follow lambda 0.05
+        let mut mid = feed(&mut estimator, &[10.0, -20.0, 5.0], 0.01);
+        for (i, signed_volume) in [4.0f64, -8.0, 2.0].into_iter().enumerate() {
+            estimator.advance(30 + i as u64 * 10, Some(mid));
+            estimator.add_trade(signed_volume.abs(), if signed_volume > 0.0 { 1.0 } else { 0.0 });
+            mid += 0.05 * signed_volume;
+        }
+        estimator.advance(60, Some(mid));
+
+        let point = *estimator.series().latest().unwrap();
+        assert_eq!(point.observations, 3);
+        assert!((point.lambda - 0.05).abs() < 1e-12);
+    }
+
+    #[test]
+    fn quiet_intervals_are_left_out() {
+        let mut estimator = estimator(60);
+        estimator.advance(5, Some(100.0));
+        estimator.add_trade(10.0, 1.0);
+        // Nothing between 10 and 40: one observation, not three
+        estimator.advance(45, Some(100.1));
+        estimator.advance(55, None);
+        estimator.advance(65, Some(100.2));
+
+        assert_eq!(estimator.observations.len(), 1);
+        let (signed_volume, price_change) = estimator.observations[0];
+        assert_eq!(signed_volume, 10.0);
+        assert!((price_change - 0.1).abs() < 1e-9);
+    }
+}
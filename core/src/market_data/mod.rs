@@ -0,0 +1,25 @@
+pub mod activity;
+pub mod backpressure;
+pub mod bars;
+pub mod calendar;
+pub mod chunked;
+pub mod classification;
+pub mod error;
+pub mod events;
+pub mod fair_value;
+pub mod fixed_point;
+pub mod kyle_lambda;
+pub mod ofi;
+pub mod order_book;
+pub mod processor;
+pub mod regression;
+pub mod reorder;
+pub mod retention;
+pub mod sequencing;
+pub mod snapshot;
+pub mod spreads;
+pub mod symbols;
+pub mod toxicity;
+pub mod trade_index;
+pub mod volatility;
+pub mod vpin;
@@ -0,0 +1,411 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::chunked::ChunkedVec;
+use super::fixed_point::{Precision, Price, Total};
+use super::order_book::{OrderBook, Side};
+use super::regression::fit_line;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfiConfig {
+    /// Weight of each level's OFI in the integrated measure, best level
+    /// first. Its length is the number of levels tracked; the best level is
+    /// tracked even when it is empty.
+    pub level_weights: Vec<f64>,
+    /// Windows the rolling sums are kept over.
+    pub windows: Vec<Duration>,
+    /// Length of each regression observation, aligned to multiples of it in
+    /// event time.
+    pub regression_interval: Duration,
+    /// Intervals in each regression.
+    pub regression_window: usize,
+    /// Estimates kept in the series.
+    pub max_points: usize,
+}
+
+impl Default for OfiConfig {
+    fn default() -> Self {
+        OfiConfig {
+            level_weights: vec![1.0; 5],
+            windows: vec![Duration::from_secs(1), Duration::from_secs(10), Duration::from_secs(60)],
+            regression_interval: Duration::from_secs(10),
+            regression_window: 180,
+            max_points: 10_000,
+        }
+    }
+}
+
+/// Order flow imbalance summed over the `window` ending at `end_ns`, the
+/// latest message of the symbol, in quantity units. Positive when bids grow
+/// or asks shrink.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderFlowImbalance {
+    pub window: Duration,
+    pub end_ns: u64,
+    /// Per level, best first.
+    pub levels: Vec<f64>,
+    /// `levels` combined with the configured weights.
+    pub integrated: f64,
+    /// Book changes in the window.
+    pub events: u64,
+}
+
+impl OrderFlowImbalance {
+    /// The best-level OFI of Cont, Kukanov and Stoikov.
+    pub fn best(&self) -> f64 {
+        self.levels.first().copied().unwrap_or(0.0)
+    }
+}
+
+/// Regression of the interval's mid change on its best-level OFI, with an
+/// intercept, over the intervals ending at `timestamp_ns`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OfiRegressionPoint {
+    /// Exclusive end of the last interval in the regression.
+    pub timestamp_ns: u64,
+    /// Mid change per unit of OFI.
+    pub beta: f64,
+    pub standard_error: f64,
+    pub r_squared: f64,
+    pub observations: usize,
+}
+
+/// Published order flow imbalance of a symbol.
+#[derive(Debug, Clone)]
+pub struct OfiSeries {
+    /// One per configured window.
+    pub windows: Vec<OrderFlowImbalance>,
+    pub regression: ChunkedVec<OfiRegressionPoint>,
+}
+
+impl OfiSeries {
+    pub fn latest(&self) -> Option<&OfiRegressionPoint> {
+        self.regression.last()
+    }
+
+    /// Estimates whose last interval ended within `[start_ns, end_ns]`.
+    pub fn range(&self, start_ns: u64, end_ns: u64) -> Vec<OfiRegressionPoint> {
+        let from = self.regression.partition_point(|point| point.timestamp_ns < start_ns);
+        let to = self.regression.partition_point(|point| point.timestamp_ns <= end_ns);
+        self.regression.range(from, to).copied().collect()
+    }
+}
+
+#[derive(Debug)]
+struct Contribution {
+    timestamp_ns: u64,
+    levels: Vec<Total>,
+}
+
+#[derive(Debug)]
+struct WindowSum {
+    window: Duration,
+    window_ns: u64,
+    // Absolute index of the oldest contribution inside the window
+    start: usize,
+    sums: Vec<Total>,
+    events: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OpenInterval {
+    start_ns: u64,
+    start_mid: Option<f64>,
+    best: Total,
+}
+
+/// Diffs the top of the book against its state before each message. Sums
+/// are exact on a quantity grid and windows drop contributions in arrival
+/// order.
+#[derive(Debug)]
+pub(crate) struct OfiTracker {
+    config: OfiConfig,
+    precision: Precision,
+    levels: usize,
+    bids: Vec<(Price, Total)>,
+    asks: Vec<(Price, Total)>,
+    contributions: VecDeque<Contribution>,
+    // Contributions popped from the front, so window starts can be absolute
+    popped: usize,
+    latest_ns: u64,
+    windows: Vec<WindowSum>,
+    current: Option<OpenInterval>,
+    interval_ns: u64,
+    // (best-level OFI, mid change) of recent intervals
+    observations: VecDeque<(f64, f64)>,
+    points: ChunkedVec<OfiRegressionPoint>,
+}
+
+impl OfiTracker {
+    pub(crate) fn new(config: &OfiConfig, precision: Precision) -> Self {
+        let levels = config.level_weights.len().max(1);
+        OfiTracker {
+            windows: config.windows.iter()
+                .map(|window| WindowSum {
+                    window: *window,
+                    window_ns: u64::try_from(window.as_nanos()).unwrap_or(u64::MAX),
+                    start: 0,
+                    sums: vec![Total::ZERO; levels],
+                    events: 0,
+                })
+                .collect(),
+            interval_ns: u64::try_from(config.regression_interval.as_nanos()).unwrap_or(u64::MAX).max(1),
+            config: config.clone(),
+            precision,
+            levels,
+            bids: Vec::new(),
+            asks: Vec::new(),
+            contributions: VecDeque::new(),
+            popped: 0,
+            latest_ns: 0,
+            current: None,
+            observations: VecDeque::new(),
+            points: ChunkedVec::new(),
+        }
+    }
+
+    /// Called after every applied message with the book it left.
+    pub(crate) fn observe(&mut self, timestamp_ns: u64, book: &OrderBook) {
+        self.advance_interval(timestamp_ns);
+
+        let bids = book.depth(Side::Bid, self.levels);
+        let asks = book.depth(Side::Ask, self.levels);
+        let levels: Vec<Total> = (0..self.levels)
+            .map(|level| {
+                let bid = level_flow(at(&self.bids, level), at(&bids, level));
+                // An ask improves as its price falls
+                let reverse = |side: Option<(Price, Total)>| side.map(|(price, quantity)| (Reverse(price), quantity));
+                let ask = level_flow(reverse(at(&self.asks, level)), reverse(at(&asks, level)));
+                bid - ask
+            })
+            .collect();
+        self.bids = bids;
+        self.asks = asks;
+
+        if let Some(current) = &mut self.current {
+            current.best += levels[0];
+        }
+        self.latest_ns = self.latest_ns.max(timestamp_ns);
+        if levels.iter().any(|flow| !flow.is_zero()) {
+            for window in &mut self.windows {
+                for (sum, flow) in window.sums.iter_mut().zip(&levels) {
+                    *sum += *flow;
+                }
+                window.events += 1;
+            }
+            self.contributions.push_back(Contribution { timestamp_ns, levels });
+        }
+        self.evict();
+    }
+
+    pub(crate) fn series(&self) -> OfiSeries {
+        OfiSeries {
+            windows: self.windows.iter()
+                .map(|window| {
+                    let levels: Vec<f64> = window.sums.iter().map(|sum| self.precision.quantity_total_to_f64(*sum)).collect();
+                    OrderFlowImbalance {
+                        window: window.window,
+                        end_ns: self.latest_ns,
+                        integrated: levels.iter().zip(&self.config.level_weights).map(|(flow, weight)| flow * weight).sum(),
+                        levels,
+                        events: window.events,
+                    }
+                })
+                .collect(),
+            regression: self.points.clone(),
+        }
+    }
+
+    fn mid(&self) -> Option<f64> {
+        match (self.bids.first(), self.asks.first()) {
+            (Some((bid, _)), Some((ask, _))) => Some(self.precision.midpoint_to_f64(*bid, *ask)),
+            _ => None,
+        }
+    }
+
+    // Closes the open interval if the message starts a later one, using the
+    // mid prevailing before the message
+    fn advance_interval(&mut self, timestamp_ns: u64) {
+        let start_ns = timestamp_ns - timestamp_ns % self.interval_ns;
+        let mid = self.mid();
+        match self.current {
+            Some(current) if start_ns <= current.start_ns => return,
+            Some(current) => {
+                if let (Some(start_mid), Some(end_mid)) = (current.start_mid, mid) {
+                    let ofi = self.precision.quantity_total_to_f64(current.best);
+                    self.add_observation(ofi, end_mid - start_mid, current.start_ns.saturating_add(self.interval_ns));
+                }
+            },
+            None => {},
+        }
+        self.current = Some(OpenInterval { start_ns, start_mid: mid, best: Total::ZERO });
+    }
+
+    fn add_observation(&mut self, ofi: f64, mid_change: f64, end_ns: u64) {
+        self.observations.push_back((ofi, mid_change));
+        if self.observations.len() > self.config.regression_window.max(3) {
+            self.observations.pop_front();
+        }
+
+        if let Some(fit) = fit_line(&self.observations) {
+            self.points.push(OfiRegressionPoint {
+                timestamp_ns: end_ns,
+                beta: fit.slope,
+                standard_error: fit.standard_error,
+                r_squared: fit.r_squared,
+                observations: self.observations.len(),
+            });
+            self.points.remove_front(self.points.len().saturating_sub(self.config.max_points.max(1)));
+        }
+    }
+
+    fn evict(&mut self) {
+        for window in &mut self.windows {
+            let cutoff = match self.latest_ns.checked_sub(window.window_ns) {
+                Some(cutoff) => cutoff,
+                None => continue,
+            };
+            while let Some(contribution) = self.contributions.get(window.start - self.popped) {
+                if contribution.timestamp_ns > cutoff {
+                    break;
+                }
+                for (sum, flow) in window.sums.iter_mut().zip(&contribution.levels) {
+                    *sum -= *flow;
+                }
+                window.events -= 1;
+                window.start += 1;
+            }
+        }
+
+        let oldest = self.windows.iter().map(|window| window.start).min().unwrap_or(self.popped + self.contributions.len());
+        while self.popped < oldest && self.contributions.pop_front().is_some() {
+            self.popped += 1;
+        }
+    }
+}
+
+// One side's flow at a level: the new quantity counts when the level's price
+// held or improved, the old one is taken off when it held or worsened. A
+// missing level ranks below every price, so `K` orders prices from worst to
+// best.
+fn level_flow<K: Ord>(before: Option<(K, Total)>, after: Option<(K, Total)>) -> Total {
+    let (before_key, before_quantity) = split(before);
+    let (after_key, after_quantity) = split(after);
+    let mut flow = Total::ZERO;
+    if after_key >= before_key {
+        flow += after_quantity;
+    }
+    if after_key <= before_key {
+        flow -= before_quantity;
+    }
+    flow
+}
+
+fn at(levels: &[(Price, Total)], level: usize) -> Option<(Price, Total)> {
+    levels.get(level).copied()
+}
+
+fn split<K>(level: Option<(K, Total)>) -> (Option<K>, Total) {
+    match level {
+        Some((key, quantity)) => (Some(key), quantity),
+        None => (None, Total::ZERO),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixed_point::{FixedScale, Quantity};
+
+    fn precision() -> Precision {
+        Precision::new(FixedScale::decimals(2), FixedScale::decimals(0))
+    }
+
+    fn config(windows: Vec<Duration>) -> OfiConfig {
+        OfiConfig {
+            level_weights: vec![1.0, 0.5],
+            windows,
+            regression_interval: Duration::from_nanos(10),
+            regression_window: 10,
+            max_points: 100,
+        }
+    }
+
+    #[test]
+    fn best_level_flow_follows_price_and_size_changes() {
+        let mut tracker = OfiTracker::new(&config(vec![Duration::from_secs(1)]), precision());
+        let mut book = OrderBook::new();
+        let mut step = |book: &OrderBook, timestamp_ns: u64| {
+            tracker.observe(timestamp_ns, book);
+            tracker.series().windows[0].best()
+        };
+
+        book.add_order("bid", Side::Bid, Price(10_000), Quantity(10), 1).unwrap();
+        assert_eq!(step(&book, 1), 10.0);
+        // A new ask takes from the flow
+        book.add_order("ask", Side::Ask, Price(10_100), Quantity(5), 2).unwrap();
+        assert_eq!(step(&book, 2), 5.0);
+        // Size added at an unchanged price counts the difference
+        book.add_order("bid2", Side::Bid, Price(10_000), Quantity(3), 3).unwrap();
+        assert_eq!(step(&book, 3), 8.0);
+        // An improved ask counts its full size
+        book.add_order("ask2", Side::Ask, Price(10_050), Quantity(2), 4).unwrap();
+        assert_eq!(step(&book, 4), 6.0);
+        // A bid level that disappears takes its full size off
+        book.cancel_order("bid").unwrap();
+        book.cancel_order("bid2").unwrap();
+        assert_eq!(step(&book, 5), -7.0);
+
+        let window = &tracker.series().windows[0];
+        assert_eq!((window.events, window.end_ns), (5, 5));
+        // The old best ask moving down a level adds ask size there
+        assert_eq!(window.levels[1], -5.0);
+        assert_eq!(window.integrated, window.levels[0] + 0.5 * window.levels[1]);
+    }
+
+    #[test]
+    fn windows_drop_old_contributions() {
+        let mut tracker = OfiTracker::new(&config(vec![Duration::from_nanos(100), Duration::from_secs(1)]), precision());
+        let mut book = OrderBook::new();
+        book.add_order("a", Side::Bid, Price(10_000), Quantity(1), 100).unwrap();
+        tracker.observe(100, &book);
+        book.add_order("b", Side::Bid, Price(10_000), Quantity(2), 150).unwrap();
+        tracker.observe(150, &book);
+        // Unchanged top of book adds no contribution
+        tracker.observe(180, &book);
+        book.add_order("c", Side::Bid, Price(10_000), Quantity(4), 250).unwrap();
+        tracker.observe(250, &book);
+
+        let series = tracker.series();
+        assert_eq!((series.windows[0].best(), series.windows[0].events), (4.0, 1));
+        assert_eq!((series.windows[1].best(), series.windows[1].events), (7.0, 3));
+    }
+
+    #[test]
+    fn regression_recovers_a_linear_price_impact() {
+        let mut tracker = OfiTracker::new(&config(vec![Duration::from_secs(1)]), precision());
+        let mut book = OrderBook::new();
+        book.add_order("ask", Side::Ask, Price(20_000), Quantity(1), 0).unwrap();
+        tracker.observe(0, &book);
+        book.add_order("bid0", Side::Bid, Price(10_000), Quantity(1), 0).unwrap();
+        tracker.observe(0, &book);
+
+        // Each interval improves the bid by k ticks with size k, moving the
+        // mid by half a tick per unit of OFI
+        let mut price = 10_000;
+        for k in 1..=6 {
+            price += k;
+            let timestamp_ns = k as u64 * 10;
+            book.add_order(&format!("bid{}", k), Side::Bid, Price(price), Quantity(k), timestamp_ns).unwrap();
+            tracker.observe(timestamp_ns, &book);
+        }
+
+        let series = tracker.series();
+        let latest = series.latest().unwrap();
+        assert_eq!((latest.timestamp_ns, latest.observations), (60, 5));
+        assert!((latest.beta - 0.005).abs() < 1e-12);
+        assert!((latest.r_squared - 1.0).abs() < 1e-9);
+        assert_eq!(series.range(0, 59).len(), series.regression.len() - 1);
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::error::{MarketDataError, MarketDataResult};
+use super::fixed_point::{Precision, Price, Quantity, Total};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+impl Side {
+    pub fn from_is_buy(is_buy: bool) -> Self {
+        if is_buy { Side::Bid } else { Side::Ask }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BookOrder {
+    pub order_id: String,
+    pub side: Side,
+    pub price: Price,
+    pub quantity: Quantity,
+    pub timestamp_ns: u64,
+    priority: u64,
+}
+
+#[derive(Debug, Default)]
+struct PriceLevel {
+    // Keyed by arrival sequence for time priority within the level
+    orders: BTreeMap<u64, String>,
+    total_quantity: Total,
+}
+
+/// A price level converted to floats for output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookLevel {
+    pub price: f64,
+    pub quantity: f64,
+    pub order_count: usize,
+}
+
+/// Price levels are keyed by exact fixed-point prices, so two orders at the
+/// same price always share a level.
+#[derive(Debug, Default)]
+pub struct OrderBook {
+    precision: Precision,
+    orders: HashMap<String, BookOrder>,
+    bids: BTreeMap<Price, PriceLevel>,
+    asks: BTreeMap<Price, PriceLevel>,
+    next_priority: u64,
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_precision(precision: Precision) -> Self {
+        OrderBook {
+            precision,
+            ..Self::default()
+        }
+    }
+
+    pub fn precision(&self) -> &Precision {
+        &self.precision
+    }
+
+    pub fn add_order(&mut self, order_id: &str, side: Side, price: Price, quantity: Quantity, timestamp_ns: u64) -> MarketDataResult<()> {
+        if self.orders.contains_key(order_id) {
+            return Err(MarketDataError::DuplicateOrder(order_id.to_string()));
+        }
+        if price.0 <= 0 {
+            return Err(MarketDataError::Validation(format!("invalid price {} for order {}", self.precision.price_to_f64(price), order_id)));
+        }
+        if quantity.0 <= 0 {
+            return Err(MarketDataError::Validation(format!("invalid quantity {} for order {}", self.precision.quantity_to_f64(quantity), order_id)));
+        }
+
+        let priority = self.next_priority();
+        let order = BookOrder {
+            order_id: order_id.to_string(),
+            side,
+            price,
+            quantity,
+            timestamp_ns,
+            priority,
+        };
+
+        self.insert_into_level(&order);
+        self.orders.insert(order.order_id.clone(), order);
+        Ok(())
+    }
+
+    /// Changes price and/or quantity of a resting order. A price change or a
+    /// size increase sends the order to the back of the queue; a size decrease
+    /// keeps its priority.
+    pub fn modify_order(&mut self, order_id: &str, new_price: Option<Price>, new_quantity: Option<Quantity>, timestamp_ns: u64) -> MarketDataResult<()> {
+        let existing = self.orders.get(order_id)
+            .ok_or_else(|| MarketDataError::UnknownOrder(order_id.to_string()))?
+            .clone();
+
+        let price = new_price.unwrap_or(existing.price);
+        let quantity = new_quantity.unwrap_or(existing.quantity);
+        if price.0 <= 0 {
+            return Err(MarketDataError::Validation(format!("invalid price {} for order {}", self.precision.price_to_f64(price), order_id)));
+        }
+        if quantity.0 < 0 {
+            return Err(MarketDataError::Validation(format!("invalid quantity {} for order {}", self.precision.quantity_to_f64(quantity), order_id)));
+        }
+        if quantity.is_zero() {
+            return self.cancel_order(order_id);
+        }
+
+        self.remove_from_level(&existing);
+
+        let loses_priority = price != existing.price || quantity > existing.quantity;
+        let priority = if loses_priority { self.next_priority() } else { existing.priority };
+        let order = BookOrder {
+            price,
+            quantity,
+            timestamp_ns: if loses_priority { timestamp_ns } else { existing.timestamp_ns },
+            priority,
+            ..existing
+        };
+
+        self.insert_into_level(&order);
+        self.orders.insert(order.order_id.clone(), order);
+        Ok(())
+    }
+
+    pub fn cancel_order(&mut self, order_id: &str) -> MarketDataResult<()> {
+        let order = self.orders.remove(order_id)
+            .ok_or_else(|| MarketDataError::UnknownOrder(order_id.to_string()))?;
+        self.remove_from_level(&order);
+        Ok(())
+    }
+
+    /// Reduces a resting order by an executed quantity, removing it once filled.
+    pub fn execute_order(&mut self, order_id: &str, quantity: Quantity) -> MarketDataResult<()> {
+        let resting = self.orders.get(order_id)
+            .ok_or_else(|| MarketDataError::UnknownOrder(order_id.to_string()))?
+            .quantity;
+        let remaining = self.precision.quantity_difference(resting, quantity);
+
+        if remaining <= Quantity::ZERO {
+            self.cancel_order(order_id)
+        } else {
+            self.modify_order(order_id, None, Some(remaining), 0)
+        }
+    }
+
+    pub fn get_order(&self, order_id: &str) -> Option<&BookOrder> {
+        self.orders.get(order_id)
+    }
+
+    pub fn order_count(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn best_bid(&self) -> Option<Price> {
+        self.bids.keys().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<Price> {
+        self.asks.keys().next().copied()
+    }
+
+    /// Mid of the best bid and ask. Not necessarily on the price grid, hence
+    /// a float.
+    pub fn mid_price(&self) -> Option<f64> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => Some(self.precision.midpoint_to_f64(bid, ask)),
+            _ => None,
+        }
+    }
+
+    pub fn spread(&self) -> Option<Price> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => Some(self.precision.price_difference(ask, bid)),
+            _ => None,
+        }
+    }
+
+    pub fn bid_levels(&self, count: usize) -> Vec<BookLevel> {
+        self.bids.iter().rev().take(count).map(|(price, level)| self.to_book_level(*price, level)).collect()
+    }
+
+    pub fn ask_levels(&self, count: usize) -> Vec<BookLevel> {
+        self.asks.iter().take(count).map(|(price, level)| self.to_book_level(*price, level)).collect()
+    }
+
+    /// The best `count` levels of a side as exact prices and total
+    /// quantities, best first.
+    pub fn depth(&self, side: Side, count: usize) -> Vec<(Price, Total)> {
+        match side {
+            Side::Bid => self.bids.iter().rev().take(count).map(|(price, level)| (*price, level.total_quantity)).collect(),
+            Side::Ask => self.asks.iter().take(count).map(|(price, level)| (*price, level.total_quantity)).collect(),
+        }
+    }
+
+    pub fn level_quantity(&self, side: Side, price: Price) -> Total {
+        let levels = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        levels.get(&price).map_or(Total::ZERO, |level| level.total_quantity)
+    }
+
+    /// Resting orders at a price in time priority order.
+    pub fn orders_at(&self, side: Side, price: Price) -> Vec<&BookOrder> {
+        let levels = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        levels.get(&price)
+            .map(|level| level.orders.values().filter_map(|id| self.orders.get(id)).collect())
+            .unwrap_or_default()
+    }
+
+    fn to_book_level(&self, price: Price, level: &PriceLevel) -> BookLevel {
+        BookLevel {
+            price: self.precision.price_to_f64(price),
+            quantity: self.precision.quantity_total_to_f64(level.total_quantity),
+            order_count: level.orders.len(),
+        }
+    }
+
+    fn next_priority(&mut self) -> u64 {
+        let priority = self.next_priority;
+        self.next_priority += 1;
+        priority
+    }
+
+    fn insert_into_level(&mut self, order: &BookOrder) {
+        let quantity = self.precision.quantity_total(order.quantity);
+        let levels = match order.side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        let level = levels.entry(order.price).or_default();
+        level.orders.insert(order.priority, order.order_id.clone());
+        level.total_quantity += quantity;
+    }
+
+    fn remove_from_level(&mut self, order: &BookOrder) {
+        let quantity = self.precision.quantity_total(order.quantity);
+        let levels = match order.side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        if let Some(level) = levels.get_mut(&order.price) {
+            level.orders.remove(&order.priority);
+            level.total_quantity -= quantity;
+            if level.orders.is_empty() {
+                levels.remove(&order.price);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixed_point::FixedScale;
+
+    fn ids(orders: Vec<&BookOrder>) -> Vec<&str> {
+        orders.into_iter().map(|order| order.order_id.as_str()).collect()
+    }
+
+    fn book() -> OrderBook {
+        let mut book = OrderBook::new();
+        book.add_order("a", Side::Bid, Price(100), Quantity(10), 1).unwrap();
+        book.add_order("b", Side::Bid, Price(100), Quantity(20), 2).unwrap();
+        book.add_order("c", Side::Bid, Price(100), Quantity(30), 3).unwrap();
+        book
+    }
+
+    #[test]
+    fn orders_at_a_price_keep_arrival_order() {
+        let book = book();
+        assert_eq!(ids(book.orders_at(Side::Bid, Price(100))), ["a", "b", "c"]);
+        assert_eq!(book.level_quantity(Side::Bid, Price(100)), Total::Units(60));
+        assert_eq!(book.bid_levels(1)[0].order_count, 3);
+    }
+
+    #[test]
+    fn size_decrease_keeps_priority() {
+        let mut book = book();
+        book.modify_order("a", None, Some(Quantity(5)), 4).unwrap();
+        assert_eq!(ids(book.orders_at(Side::Bid, Price(100))), ["a", "b", "c"]);
+        assert_eq!(book.get_order("a").unwrap().timestamp_ns, 1);
+        assert_eq!(book.level_quantity(Side::Bid, Price(100)), Total::Units(55));
+    }
+
+    #[test]
+    fn size_increase_and_price_change_lose_priority() {
+        let mut book = book();
+        book.modify_order("a", None, Some(Quantity(11)), 4).unwrap();
+        assert_eq!(ids(book.orders_at(Side::Bid, Price(100))), ["b", "c", "a"]);
+        assert_eq!(book.get_order("a").unwrap().timestamp_ns, 4);
+
+        book.modify_order("b", Some(Price(101)), None, 5).unwrap();
+        book.modify_order("b", Some(Price(100)), None, 6).unwrap();
+        assert_eq!(ids(book.orders_at(Side::Bid, Price(100))), ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn modify_to_zero_cancels() {
+        let mut book = book();
+        book.modify_order("b", None, Some(Quantity::ZERO), 4).unwrap();
+        assert!(book.get_order("b").is_none());
+        assert_eq!(book.order_count(), 2);
+    }
+
+    #[test]
+    fn partial_execution_keeps_priority_and_full_execution_removes() {
+        let mut book = book();
+        book.execute_order("a", Quantity(4)).unwrap();
+        assert_eq!(ids(book.orders_at(Side::Bid, Price(100))), ["a", "b", "c"]);
+        assert_eq!(book.get_order("a").unwrap().quantity, Quantity(6));
+
+        book.execute_order("a", Quantity(6)).unwrap();
+        assert_eq!(ids(book.orders_at(Side::Bid, Price(100))), ["b", "c"]);
+        assert_eq!(book.execute_order("a", Quantity(1)), Err(MarketDataError::UnknownOrder("a".to_string())));
+    }
+
+    #[test]
+    fn empty_levels_are_removed() {
+        let mut book = book();
+        book.add_order("d", Side::Bid, Price(99), Quantity(1), 4).unwrap();
+        for id in ["a", "b", "c"] {
+            book.cancel_order(id).unwrap();
+        }
+        assert_eq!(book.best_bid(), Some(Price(99)));
+        assert_eq!(book.depth(Side::Bid, 5), [(Price(99), Total::Units(1))]);
+    }
+
+    #[test]
+    fn top_of_book() {
+        let mut book = OrderBook::with_precision(Precision::new(FixedScale::decimals(2), FixedScale::decimals(0)));
+        assert_eq!(book.mid_price(), None);
+        book.add_order("bid", Side::Bid, Price(1000), Quantity(1), 1).unwrap();
+        book.add_order("ask", Side::Ask, Price(1005), Quantity(1), 2).unwrap();
+        book.add_order("far", Side::Ask, Price(1010), Quantity(1), 3).unwrap();
+        assert_eq!(book.spread(), Some(Price(5)));
+        assert_eq!(book.mid_price(), Some(10.025));
+        assert_eq!(book.ask_levels(5).iter().map(|level| level.price).collect::<Vec<_>>(), [10.05, 10.1]);
+    }
+
+    #[test]
+    fn rejects_bad_orders() {
+        let mut book = book();
+        assert_eq!(book.add_order("a", Side::Ask, Price(101), Quantity(1), 4), Err(MarketDataError::DuplicateOrder("a".to_string())));
+        assert!(book.add_order("z", Side::Ask, Price(0), Quantity(1), 4).is_err());
+        assert!(book.add_order("z", Side::Ask, Price(101), Quantity(0), 4).is_err());
+        assert_eq!(book.cancel_order("z"), Err(MarketDataError::UnknownOrder("z".to_string())));
+    }
+}
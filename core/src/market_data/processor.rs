@@ -1,9 +1,33 @@
-use std::collections::{HashMap, BTreeMap};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use crossbeam_channel::{bounded, Receiver, Sender};
+use std::thread::JoinHandle;
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, SendTimeoutError, Sender, TrySendError};
+
+use super::activity::{ActivityTracker, MessageCounts, OrderActivity};
+use super::backpressure::{BackpressureCounters, BackpressurePolicy, BackpressureStats, ConflationState};
+use super::bars::{Bar, BarBuilder, BarSeries, BarSpec, EmptyBarPolicy};
+use super::calendar::{SessionAccumulator, SessionSummary, TradingCalendar};
+use super::classification::{ClassificationCounts, ClassificationMethod, TradeClassifier, TradeClassifierState, TradeSign};
+use super::error::{MarketDataError, MarketDataResult};
+use super::events::{EventBus, EventTopic, MarketEvent, Subscription, SymbolMetrics};
+use super::fixed_point::{Precision, Price, Quantity};
+use super::fair_value::{FairValue, FairValueConfig, FairValueTracker};
+use super::kyle_lambda::{KyleLambdaConfig, KyleLambdaEstimator, KyleLambdaPoint, KyleLambdaSeries};
+use super::reorder::{LateMessagePolicy, ReorderBuffer, ReorderCounters, ReorderStats};
+use super::retention::{RetentionPolicy, TradeHistory};
+use super::sequencing::{SequenceStats, SequenceTracker};
+use super::snapshot::{SnapshotStore, SymbolSnapshot};
+use super::spreads::{SpreadConfig, SpreadInterval, SpreadSeries, SpreadTracker, TradeSpread};
+use super::symbols::{SymbolId, SymbolRegistry};
+use super::toxicity::{FlowObservation, ToxicityConfig, ToxicityScore, ToxicityState};
+use super::trade_index::TradeIndex;
+use super::volatility::{self, VolatilityEstimate, VolatilityEstimator};
+use super::vpin::{VpinCalculator, VpinConfig, VpinPoint, VpinSeries};
+use super::ofi::{OfiConfig, OfiRegressionPoint, OfiSeries, OfiTracker, OrderFlowImbalance};
+use super::order_book::{BookLevel, OrderBook, Side};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MarketMessageType {
@@ -13,6 +37,17 @@ pub enum MarketMessageType {
     Trade,
 }
 
+impl MarketMessageType {
+    /// Whether a later message of this type supersedes an earlier one for the
+    /// same symbol, so `ConflateLatest` may drop the earlier one. Order and
+    /// trade events never do: each changes the book or the tape.
+    pub fn is_conflatable(&self) -> bool {
+        match self {
+            MarketMessageType::Add | MarketMessageType::Modify | MarketMessageType::Cancel | MarketMessageType::Trade => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarketMessage {
     pub timestamp_ns: u64,
@@ -23,113 +58,1840 @@ pub struct MarketMessage {
     pub quantity: Option<f64>,
     pub is_buy: Option<bool>,
     pub trade_id: Option<String>,
+    /// Feed channel the sequence number belongs to; defaults to channel 0.
+    #[serde(default)]
+    pub channel_id: Option<u32>,
+    #[serde(default)]
+    pub sequence: Option<u64>,
+}
+
+impl MarketMessage {
+    /// Checks that the fields required by the message type are present and sane.
+    pub fn validate(&self) -> MarketDataResult<()> {
+        if self.symbol.is_empty() {
+            return Err(MarketDataError::Validation("empty symbol".to_string()));
+        }
+        validate_fields(&self.message_type, self.order_id.as_ref(), self.price, self.quantity, self.is_buy)
+    }
+    
+    /// The same message keyed by `symbol`, the id of its ticker.
+    pub fn with_symbol_id(self, symbol: SymbolId) -> SymbolMessage {
+        SymbolMessage {
+            timestamp_ns: self.timestamp_ns,
+            symbol,
+            message_type: self.message_type,
+            order_id: self.order_id,
+            price: self.price,
+            quantity: self.quantity,
+            is_buy: self.is_buy,
+            trade_id: self.trade_id,
+            channel_id: self.channel_id,
+            sequence: self.sequence,
+        }
+    }
+}
+
+/// A `MarketMessage` keyed by the `SymbolId` of its ticker, for producers
+/// that register their symbols once and then submit without allocating or
+/// hashing the ticker per message.
+#[derive(Debug, Clone)]
+pub struct SymbolMessage {
+    pub timestamp_ns: u64,
+    pub symbol: SymbolId,
+    pub message_type: MarketMessageType,
+    pub order_id: Option<String>,
+    pub price: Option<f64>,
+    pub quantity: Option<f64>,
+    pub is_buy: Option<bool>,
+    pub trade_id: Option<String>,
+    /// Feed channel the sequence number belongs to; defaults to channel 0.
+    pub channel_id: Option<u32>,
+    pub sequence: Option<u64>,
+}
+
+impl SymbolMessage {
+    /// Checks that the fields required by the message type are present and sane.
+    pub fn validate(&self) -> MarketDataResult<()> {
+        validate_fields(&self.message_type, self.order_id.as_ref(), self.price, self.quantity, self.is_buy)
+    }
+}
+
+fn validate_fields(message_type: &MarketMessageType, order_id: Option<&String>, price: Option<f64>, quantity: Option<f64>, is_buy: Option<bool>) -> MarketDataResult<()> {
+    if let Some(price) = price {
+        if !price.is_finite() || price <= 0.0 {
+            return Err(MarketDataError::Validation(format!("invalid price {}", price)));
+        }
+    }
+    if let Some(quantity) = quantity {
+        if !quantity.is_finite() || quantity < 0.0 {
+            return Err(MarketDataError::Validation(format!("invalid quantity {}", quantity)));
+        }
+    }
+    
+    match message_type {
+        MarketMessageType::Trade => {
+            required(price, "price")?;
+            required(quantity, "quantity")?;
+        },
+        MarketMessageType::Add => {
+            required(order_id, "order_id")?;
+            required(price, "price")?;
+            required(quantity, "quantity")?;
+            required(is_buy, "is_buy")?;
+        },
+        MarketMessageType::Modify => {
+            required(order_id, "order_id")?;
+            if price.is_none() && quantity.is_none() {
+                return Err(MarketDataError::Validation("modify without price or quantity".to_string()));
+            }
+        },
+        MarketMessageType::Cancel => {
+            required(order_id, "order_id")?;
+        },
+    }
+    Ok(())
+}
+
+fn required<T>(value: Option<T>, field: &str) -> MarketDataResult<T> {
+    value.ok_or_else(|| MarketDataError::Validation(format!("missing {}", field)))
+}
+
+/// A message as it travels through a shard. The ticker is resolved to its
+/// `SymbolId` and prices and quantities put on the symbol's grid once at
+/// submission, and sequencing fields are already consumed.
+#[derive(Debug, Clone)]
+pub(crate) struct ShardMessage {
+    pub symbol: SymbolId,
+    pub timestamp_ns: u64,
+    pub message_type: MarketMessageType,
+    pub order_id: Option<String>,
+    pub price: Option<Price>,
+    pub quantity: Option<Quantity>,
+    pub is_buy: Option<bool>,
+}
+
+type SymbolMap = HashMap<SymbolId, SymbolData>;
+
+#[derive(Debug, Clone)]
+pub struct ProcessorConfig {
+    /// Queue capacity per shard
+    pub buffer_size: usize,
+    pub shard_count: usize,
+    pub backpressure: BackpressurePolicy,
+    /// A sequence number this far or further below the expected one is taken
+    /// as a feed reset rather than a duplicate.
+    pub sequence_reset_threshold: u64,
+    /// How long, in event time, messages are held back to be released in
+    /// timestamp order. Zero disables reordering.
+    pub reorder_window_ns: u64,
+    pub late_message_policy: LateMessagePolicy,
+    /// Drives rollover of the daily statistics.
+    pub calendar: TradingCalendar,
+    /// Per-symbol calendars for symbols that trade on other venues.
+    pub symbol_calendars: HashMap<String, TradingCalendar>,
+    /// Bounds on price and volume history and the trade index.
+    pub retention: RetentionPolicy,
+    pub symbol_retention: HashMap<String, RetentionPolicy>,
+    /// Fixed-point grid enforced on every symbol; values off a symbol's grid
+    /// are rejected at submission. When None, symbols without an entry in
+    /// `symbol_precision` are kept unscaled, as `Precision::unscaled()`.
+    pub precision: Option<Precision>,
+    pub symbol_precision: HashMap<String, Precision>,
+    /// Signs trades that arrive without `is_buy`.
+    pub trade_classifier: TradeClassifier,
+    /// Bar series built for every symbol, unless overridden per symbol.
+    /// Time bars close on the symbol's own messages, not the session clock;
+    /// see `BarSpec::Time`.
+    pub bar_specs: Vec<BarSpec>,
+    pub symbol_bar_specs: HashMap<String, Vec<BarSpec>>,
+    pub empty_bars: EmptyBarPolicy,
+    /// Completed bars kept per series; older ones are evicted.
+    pub max_bars: usize,
+    /// Longest a busy worker goes without publishing snapshots for readers;
+    /// an idle worker publishes immediately. Zero publishes after every message.
+    pub snapshot_interval: Duration,
+    /// Book levels per side included in snapshots.
+    pub snapshot_depth: usize,
+    /// Windows over which message counts and order-to-trade ratios are kept.
+    /// Each ends at the symbol's latest message, not the session clock, so
+    /// a symbol that goes quiet keeps reporting its last window; compare
+    /// `OrderActivity::end_ns` against the current time to spot stale counts.
+    pub activity_windows: Vec<Duration>,
+    /// Flow toxicity scoring for every symbol; off when None.
+    pub toxicity: Option<ToxicityConfig>,
+    pub symbol_toxicity: HashMap<String, ToxicityConfig>,
+    /// VPIN for every symbol; off when None. Bucket volumes usually need
+    /// setting per symbol.
+    pub vpin: Option<VpinConfig>,
+    pub symbol_vpin: HashMap<String, VpinConfig>,
+    /// Rolling regression of mid changes on signed volume; off when None.
+    pub kyle_lambda: Option<KyleLambdaConfig>,
+    /// Effective, realized and price-impact spreads of every trade; off
+    /// when None.
+    pub spreads: Option<SpreadConfig>,
+    /// Order flow imbalance from top-of-book changes; off when None.
+    pub ofi: Option<OfiConfig>,
+    /// Microprice and weighted mids, updated on every message; off when
+    /// None.
+    pub fair_value: Option<FairValueConfig>,
+}
+
+impl Default for ProcessorConfig {
+    fn default() -> Self {
+        ProcessorConfig {
+            buffer_size: 10_000,
+            shard_count: 1,
+            backpressure: BackpressurePolicy::Block,
+            sequence_reset_threshold: 100_000,
+            reorder_window_ns: 0,
+            late_message_policy: LateMessagePolicy::Process,
+            calendar: TradingCalendar::default(),
+            symbol_calendars: HashMap::new(),
+            retention: RetentionPolicy::unbounded(),
+            symbol_retention: HashMap::new(),
+            precision: None,
+            symbol_precision: HashMap::new(),
+            trade_classifier: TradeClassifier::LeeReady,
+            bar_specs: Vec::new(),
+            symbol_bar_specs: HashMap::new(),
+            empty_bars: EmptyBarPolicy::Skip,
+            max_bars: 10_000,
+            snapshot_interval: Duration::from_millis(1),
+            snapshot_depth: 20,
+            activity_windows: vec![Duration::from_secs(1), Duration::from_secs(60)],
+            toxicity: None,
+            symbol_toxicity: HashMap::new(),
+            vpin: None,
+            symbol_vpin: HashMap::new(),
+            kyle_lambda: None,
+            spreads: None,
+            ofi: None,
+            fair_value: None,
+        }
+    }
 }
 
 pub struct MarketDataProcessor {
-    sender: Sender<MarketMessage>,
-    receiver: Receiver<MarketMessage>,
+    config: ProcessorConfig,
+    // One intake queue and one symbol map per shard; a symbol always hashes
+    // to the same shard so its messages are applied in submission order.
+    intake: RwLock<Vec<Intake>>,
     message_count: Arc<AtomicUsize>,
-    symbol_data: Arc<Mutex<HashMap<String, SymbolData>>>,
+    error_count: Arc<AtomicUsize>,
+    shards: Vec<Arc<Shard>>,
+    conflation: Vec<Arc<Mutex<ConflationState>>>,
+    backpressure: BackpressureCounters,
+    context: Arc<ProcessingContext>,
+    sequencing: SequenceTracker,
+    reorder: Arc<ReorderCounters>,
+}
+
+// The receiver is handed to the worker on start; dropping the sender on stop
+// lets the worker drain what is queued and exit.
+struct Intake {
+    sender: Option<Sender<ShardMessage>>,
+    receiver: Option<Receiver<ShardMessage>>,
+}
+
+impl Intake {
+    fn open(buffer_size: usize) -> Self {
+        let (sender, receiver) = bounded(buffer_size);
+        Intake {
+            sender: Some(sender),
+            receiver: Some(receiver),
+        }
+    }
+}
+
+/// Returned by `start_processing`; pass it to `stop_processing` to shut the
+/// worker down.
+pub struct ProcessingHandle {
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ProcessingHandle {
+    pub fn is_finished(&self) -> bool {
+        self.workers.iter().all(|worker| worker.is_finished())
+    }
+}
+
+// Symbol state is owned by the shard worker; readers only see the published
+// snapshots.
+#[derive(Default)]
+struct Shard {
+    symbols: Mutex<SymbolMap>,
+    snapshots: SnapshotStore,
 }
 
 struct SymbolData {
-    last_price: f64,
-    daily_volume: f64,
+    name: Arc<str>,
+    // Resolved once when the symbol is first seen
+    calendar: Arc<TradingCalendar>,
+    retention: RetentionPolicy,
+    precision: Precision,
+    version: u64,
+    last_price: Price,
+    session: SessionAccumulator,
+    prior_session: Option<SessionSummary>,
     last_update_time: u64,
-    price_history: BTreeMap<u64, f64>,
-    volume_history: BTreeMap<u64, f64>,
+    last_trade_time: u64,
+    history: TradeHistory,
+    trades: TradeIndex,
+    classifier: TradeClassifierState,
+    classification_counts: ClassificationCounts,
+    last_trade_sign: Option<TradeSign>,
+    bars: Vec<BarBuilder>,
+    activity: ActivityTracker,
+    toxicity: Option<ToxicityState>,
+    last_toxicity: Option<ToxicityScore>,
+    vpin: Option<VpinCalculator>,
+    kyle_lambda: Option<KyleLambdaEstimator>,
+    spreads: Option<SpreadTracker>,
+    ofi: Option<OfiTracker>,
+    fair_value: Option<FairValueTracker>,
+    order_book: OrderBook,
+}
+
+impl SymbolData {
+    fn new(name: Arc<str>, context: &ProcessingContext) -> Self {
+        let precision = context.precision_for(&name);
+        let bars = context.bar_specs_for(&name).iter()
+            .map(|spec| BarBuilder::new(*spec, precision, context.empty_bars, context.max_bars))
+            .collect();
+        let toxicity = context.toxicity_for(&name).cloned().map(ToxicityState::new);
+        let vpin = context.vpin_for(&name).map(|config| VpinCalculator::new(config, precision));
+        SymbolData {
+            calendar: context.calendar_for(&name),
+            retention: *context.retention_for(&name),
+            precision,
+            name,
+            version: 0,
+            last_price: Price::default(),
+            session: SessionAccumulator::new(precision),
+            prior_session: None,
+            last_update_time: 0,
+            last_trade_time: 0,
+            history: TradeHistory::default(),
+            trades: TradeIndex::new(precision),
+            classifier: TradeClassifierState::new(precision),
+            classification_counts: ClassificationCounts::default(),
+            last_trade_sign: None,
+            bars,
+            activity: ActivityTracker::new(&context.activity_windows),
+            toxicity,
+            last_toxicity: None,
+            vpin,
+            kyle_lambda: context.kyle_lambda.map(KyleLambdaEstimator::new),
+            spreads: context.spreads.as_ref().map(SpreadTracker::new),
+            ofi: context.ofi.as_ref().map(|config| OfiTracker::new(config, precision)),
+            fair_value: context.fair_value.as_ref().map(|config| FairValueTracker::new(config, precision)),
+            order_book: OrderBook::with_precision(precision),
+        }
+    }
+    
+    fn snapshot(&mut self, symbol: SymbolId, depth: usize) -> SymbolSnapshot {
+        let precision = self.precision;
+        
+        SymbolSnapshot {
+            symbol: Arc::clone(&self.name),
+            symbol_id: symbol,
+            version: self.version,
+            last_update_time: self.last_update_time,
+            precision,
+            last_price: precision.price_to_f64(self.last_price),
+            daily_volume: self.session.volume(),
+            best_bid: self.order_book.best_bid().map(|price| precision.price_to_f64(price)),
+            best_ask: self.order_book.best_ask().map(|price| precision.price_to_f64(price)),
+            mid_price: self.order_book.mid_price(),
+            spread: self.order_book.spread().map(|spread| precision.price_to_f64(spread)),
+            bid_levels: self.order_book.bid_levels(depth),
+            ask_levels: self.order_book.ask_levels(depth),
+            order_count: self.order_book.order_count(),
+            last_trade_sign: self.last_trade_sign,
+            classification_counts: self.classification_counts,
+            bars: self.bars.iter().map(|bars| bars.series()).collect(),
+            message_counts: self.activity.totals(),
+            order_activity: self.activity.activity(),
+            toxicity: self.last_toxicity.clone(),
+            vpin: self.vpin.as_ref().map(|vpin| vpin.series()),
+            kyle_lambda: self.kyle_lambda.as_ref().map(|kyle_lambda| kyle_lambda.series()),
+            spreads: self.spreads.as_ref().map(|spreads| spreads.series()),
+            ofi: self.ofi.as_ref().map(|ofi| ofi.series()),
+            fair_value: self.fair_value.as_ref().and_then(|fair_value| fair_value.latest()),
+            session: self.session.summary(),
+            prior_session: self.prior_session,
+            price_history: self.history.prices().clone(),
+            volume_history: self.history.volumes().clone(),
+            history_bytes: self.history.approximate_bytes() + self.trades.approximate_bytes(),
+            trades: self.trades.clone(),
+        }
+    }
+    
+    // Mid price, or the last trade price while the book is one-sided
+    fn reference_price(&self) -> Option<f64> {
+        self.order_book.mid_price()
+            .or_else(|| (!self.trades.is_empty()).then(|| self.precision.price_to_f64(self.last_price)))
+    }
+    
+    fn metrics(&self) -> SymbolMetrics {
+        SymbolMetrics {
+            last_price: self.precision.price_to_f64(self.last_price),
+            daily_volume: self.session.volume(),
+            mid_price: self.order_book.mid_price(),
+            spread: self.order_book.spread().map(|spread| self.precision.price_to_f64(spread)),
+        }
+    }
 }
 
 impl MarketDataProcessor {
     pub fn new(buffer_size: usize) -> Self {
-        let (sender, receiver) = bounded(buffer_size);
+        Self::with_shards(buffer_size, 1)
+    }
+    
+    /// Creates a processor whose symbols are spread over `shard_count` worker
+    /// threads, each with its own queue of `buffer_size` messages.
+    pub fn with_shards(buffer_size: usize, shard_count: usize) -> Self {
+        Self::with_config(ProcessorConfig {
+            buffer_size,
+            shard_count,
+            ..ProcessorConfig::default()
+        })
+    }
+    
+    pub fn with_config(mut config: ProcessorConfig) -> Self {
+        config.shard_count = config.shard_count.max(1);
+        let shard_count = config.shard_count;
         
         MarketDataProcessor {
-            sender,
-            receiver,
+            intake: RwLock::new((0..shard_count).map(|_| Intake::open(config.buffer_size)).collect()),
             message_count: Arc::new(AtomicUsize::new(0)),
-            symbol_data: Arc::new(Mutex::new(HashMap::new())),
+            error_count: Arc::new(AtomicUsize::new(0)),
+            shards: (0..shard_count).map(|_| Arc::new(Shard::default())).collect(),
+            conflation: (0..shard_count).map(|_| Arc::new(Mutex::new(ConflationState::default()))).collect(),
+            backpressure: BackpressureCounters::default(),
+            context: Arc::new(ProcessingContext {
+                symbols: SymbolRegistry::new(),
+                events: EventBus::new(),
+                calendar: Arc::new(config.calendar.clone()),
+                symbol_calendars: config.symbol_calendars.iter()
+                    .map(|(symbol, calendar)| (symbol.clone(), Arc::new(calendar.clone())))
+                    .collect(),
+                retention: config.retention,
+                symbol_retention: config.symbol_retention.clone(),
+                precision: config.precision,
+                symbol_precision: config.symbol_precision.clone(),
+                grids: RwLock::new(Vec::new()),
+                trade_classifier: config.trade_classifier,
+                bar_specs: config.bar_specs.clone(),
+                symbol_bar_specs: config.symbol_bar_specs.clone(),
+                empty_bars: config.empty_bars,
+                max_bars: config.max_bars,
+                activity_windows: config.activity_windows.clone(),
+                toxicity: config.toxicity.clone(),
+                symbol_toxicity: config.symbol_toxicity.clone(),
+                vpin: config.vpin,
+                symbol_vpin: config.symbol_vpin.clone(),
+                kyle_lambda: config.kyle_lambda,
+                spreads: config.spreads.clone(),
+                ofi: config.ofi.clone(),
+                fair_value: config.fair_value.clone(),
+            }),
+            sequencing: SequenceTracker::new(config.sequence_reset_threshold),
+            reorder: Arc::new(ReorderCounters::default()),
+            config,
+        }
+    }
+    
+    pub fn get_shard_count(&self) -> usize {
+        self.shards.len()
+    }
+    
+    /// Ticker to `SymbolId` mapping shared by every shard.
+    pub fn symbols(&self) -> &SymbolRegistry {
+        &self.context.symbols
+    }
+    
+    /// Shard a symbol has been assigned to; symbols are assigned on first
+    /// submission.
+    pub fn shard_for_symbol(&self, symbol: &str) -> MarketDataResult<usize> {
+        Ok(self.shard_of(self.symbol_id(symbol)?))
+    }
+    
+    fn shard_of(&self, symbol: SymbolId) -> usize {
+        symbol.index() % self.shards.len()
+    }
+    
+    fn symbol_id(&self, symbol: &str) -> MarketDataResult<SymbolId> {
+        self.context.symbols.get(symbol)
+            .ok_or_else(|| MarketDataError::UnknownSymbol(symbol.to_string()))
+    }
+    
+    /// Id of `symbol`, registering it if it has not been seen. Producers on
+    /// the hot path resolve their tickers here once and then use
+    /// `submit_message_by_id`.
+    pub fn register_symbol(&self, symbol: &str) -> MarketDataResult<SymbolId> {
+        if symbol.is_empty() {
+            return Err(MarketDataError::Validation("empty symbol".to_string()));
+        }
+        self.context.symbols.intern(symbol)
+    }
+    
+    pub fn submit_message(&self, message: MarketMessage) -> MarketDataResult<()> {
+        message.validate()?;
+        let symbol = self.context.symbols.intern(&message.symbol)?;
+        self.submit(message.with_symbol_id(symbol))
+    }
+    
+    /// Submits a message whose ticker was resolved by `register_symbol`.
+    pub fn submit_message_by_id(&self, message: SymbolMessage) -> MarketDataResult<()> {
+        message.validate()?;
+        if !self.context.symbols.contains(message.symbol) {
+            return Err(MarketDataError::UnknownSymbol(message.symbol.to_string()));
+        }
+        self.submit(message)
+    }
+    
+    fn submit(&self, message: SymbolMessage) -> MarketDataResult<()> {
+        let SymbolMessage { timestamp_ns, symbol: symbol_id, message_type, order_id, price, quantity, is_buy, channel_id, sequence, .. } = message;
+        let grid = self.context.grid_for(symbol_id)?;
+        let price = price.map(|price| grid.price(price)).transpose()?;
+        let quantity = quantity.map(|quantity| grid.quantity(quantity)).transpose()?;
+        let message = ShardMessage { symbol: symbol_id, timestamp_ns, message_type, order_id, price, quantity, is_buy };
+        
+        let sequence = match sequence {
+            Some(sequence) => sequence,
+            None => return self.enqueue(message),
+        };
+        let channel_id = channel_id.unwrap_or(0);
+        
+        // Held across enqueue so a message rejected by backpressure is not
+        // recorded as seen and can be resubmitted. Only producers on the same
+        // channel wait behind a blocked send
+        let channel = self.sequencing.channel(channel_id)?;
+        let mut state = channel.lock()?;
+        let check = self.sequencing.classify(&state, sequence)?;
+        if !check.is_duplicate() {
+            self.enqueue(message)?;
+        }
+        let report = self.sequencing.commit(channel_id, &mut state, sequence, symbol_id, check)?;
+        drop(state);
+        
+        if let Some(report) = report {
+            if self.context.events.has_subscribers(EventTopic::Sequence) {
+                self.context.events.publish(MarketEvent::Sequence {
+                    symbol: self.context.symbol_name(symbol_id).to_string(),
+                    timestamp_ns,
+                    channel_id,
+                    anomaly: report.anomaly,
+                    expected: report.expected,
+                    received: report.received,
+                });
+            }
+        }
+        Ok(())
+    }
+    
+    fn enqueue(&self, message: ShardMessage) -> MarketDataResult<()> {
+        let shard = self.shard_of(message.symbol);
+        let intake = self.intake.read()?;
+        let sender = intake[shard].sender.as_ref()
+            .ok_or(MarketDataError::Disconnected)?;
+        
+        match self.config.backpressure {
+            BackpressurePolicy::Block => {
+                sender.send(message).map_err(|_| MarketDataError::Disconnected)?;
+            },
+            BackpressurePolicy::BlockWithTimeout(timeout) => {
+                match sender.send_timeout(message, timeout) {
+                    Ok(()) => {},
+                    Err(SendTimeoutError::Timeout(_)) => {
+                        self.backpressure.record_rejected();
+                        return Err(MarketDataError::QueueFull);
+                    },
+                    Err(SendTimeoutError::Disconnected(_)) => return Err(MarketDataError::Disconnected),
+                }
+            },
+            BackpressurePolicy::FailFast => {
+                match sender.try_send(message) {
+                    Ok(()) => {},
+                    Err(TrySendError::Full(_)) => {
+                        self.backpressure.record_rejected();
+                        return Err(MarketDataError::QueueFull);
+                    },
+                    Err(TrySendError::Disconnected(_)) => return Err(MarketDataError::Disconnected),
+                }
+            },
+            BackpressurePolicy::DropNewest => {
+                match sender.try_send(message) {
+                    Ok(()) => {},
+                    Err(TrySendError::Full(_)) => {
+                        self.backpressure.record_dropped();
+                        return Ok(());
+                    },
+                    Err(TrySendError::Disconnected(_)) => return Err(MarketDataError::Disconnected),
+                }
+            },
+            BackpressurePolicy::ConflateLatest => {
+                let mut conflation = self.conflation[shard].lock()?;
+                let symbol = message.symbol;
+                if !message.message_type.is_conflatable() {
+                    // Losing an order or trade event would corrupt the book, so
+                    // these fail fast instead, and may not overtake a parked message
+                    if conflation.has_pending(symbol) {
+                        self.backpressure.record_rejected();
+                        return Err(MarketDataError::QueueFull);
+                    }
+                    match sender.try_send(message) {
+                        Ok(()) => conflation.record_queued(symbol),
+                        Err(TrySendError::Full(_)) => {
+                            self.backpressure.record_rejected();
+                            return Err(MarketDataError::QueueFull);
+                        },
+                        Err(TrySendError::Disconnected(_)) => return Err(MarketDataError::Disconnected),
+                    }
+                } else if conflation.has_pending(symbol) {
+                    // Once a symbol is parked, later messages must not overtake it via the queue
+                    if conflation.park(message) {
+                        self.backpressure.record_conflated();
+                    }
+                    return Ok(());
+                } else {
+                    match sender.try_send(message) {
+                        Ok(()) => conflation.record_queued(symbol),
+                        Err(TrySendError::Full(message)) => {
+                            conflation.park(message);
+                            return Ok(());
+                        },
+                        Err(TrySendError::Disconnected(_)) => return Err(MarketDataError::Disconnected),
+                    }
+                }
+            },
+        }
+        
+        self.backpressure.record_queue_len(sender.len());
+        Ok(())
+    }
+    
+    pub fn get_backpressure_stats(&self) -> BackpressureStats {
+        self.backpressure.snapshot()
+    }
+    
+    /// Subscribes to processed updates on the given topics, optionally for a
+    /// single symbol. `capacity` bounds this subscriber's own queue.
+    pub fn subscribe(&self, topics: &[EventTopic], symbol: Option<&str>, capacity: usize) -> MarketDataResult<Subscription> {
+        self.context.events.subscribe(topics, symbol, capacity)
+    }
+    
+    pub fn unsubscribe(&self, subscription_id: u64) -> MarketDataResult<bool> {
+        self.context.events.unsubscribe(subscription_id)
+    }
+    
+    pub fn get_reorder_stats(&self) -> ReorderStats {
+        self.reorder.snapshot()
+    }
+    
+    pub fn get_sequence_stats(&self) -> MarketDataResult<SequenceStats> {
+        self.sequencing.stats()
+    }
+    
+    /// True while a symbol may have missed messages: it was seen on a channel
+    /// with an open gap, or its channel reset and it has not been recovered.
+    pub fn is_symbol_stale(&self, symbol: &str) -> MarketDataResult<bool> {
+        match self.context.symbols.get(symbol) {
+            Some(symbol) => self.sequencing.is_stale(symbol),
+            None => Ok(false),
         }
     }
     
-    pub fn submit_message(&self, message: MarketMessage) -> Result<(), String> {
-        self.sender.send(message).map_err(|e| e.to_string())
+    pub fn get_stale_symbols(&self) -> MarketDataResult<Vec<String>> {
+        let stale = self.sequencing.stale_symbols()?;
+        let mut names: Vec<String> = stale.into_iter()
+            .filter_map(|symbol| self.context.symbols.name(symbol))
+            .map(|name| name.to_string())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+    
+    pub fn mark_symbol_recovered(&self, symbol: &str) -> MarketDataResult<()> {
+        if let Some(symbol) = self.context.symbols.get(symbol) {
+            self.sequencing.mark_recovered(symbol)?;
+        }
+        Ok(())
+    }
+    
+    pub fn reset_sequence_channel(&self, channel_id: u32) -> MarketDataResult<()> {
+        self.sequencing.reset_channel(channel_id)?;
+        Ok(())
     }
     
     pub fn get_message_count(&self) -> usize {
         self.message_count.load(Ordering::Relaxed)
     }
     
-    pub fn start_processing(&self) -> Result<(), String> {
-        let receiver = self.receiver.clone();
-        let message_count = Arc::clone(&self.message_count);
-        let symbol_data = Arc::clone(&self.symbol_data);
+    /// Number of processed messages that could not be applied, e.g. a cancel
+    /// for an order that is not in the book.
+    pub fn get_error_count(&self) -> usize {
+        self.error_count.load(Ordering::Relaxed)
+    }
+    
+    pub fn is_running(&self) -> MarketDataResult<bool> {
+        Ok(self.intake.read()?.iter().all(|intake| intake.receiver.is_none()))
+    }
+    
+    pub fn start_processing(&self) -> MarketDataResult<ProcessingHandle> {
+        let receivers: Vec<Receiver<ShardMessage>> = {
+            let mut intake = self.intake.write()?;
+            if intake.iter().any(|shard| shard.receiver.is_none()) {
+                return Err(MarketDataError::AlreadyRunning);
+            }
+            intake.iter_mut().filter_map(|shard| shard.receiver.take()).collect()
+        };
         
-        std::thread::spawn(move || {
-            for message in receiver {
-                Self::process_message(&message, &symbol_data);
-                message_count.fetch_add(1, Ordering::Relaxed);
+        let mut workers = Vec::with_capacity(receivers.len());
+        for (index, receiver) in receivers.into_iter().enumerate() {
+            let worker = ShardWorker {
+                shard: Arc::clone(&self.shards[index]),
+                dirty: HashSet::new(),
+                last_publish: Instant::now(),
+                snapshot_interval: self.config.snapshot_interval,
+                snapshot_depth: self.config.snapshot_depth,
+                message_count: Arc::clone(&self.message_count),
+                error_count: Arc::clone(&self.error_count),
+                context: Arc::clone(&self.context),
+                conflation: match self.config.backpressure {
+                    BackpressurePolicy::ConflateLatest => Some(Arc::clone(&self.conflation[index])),
+                    _ => None,
+                },
+                reorder: match self.config.reorder_window_ns {
+                    0 => None,
+                    window_ns => Some(ReorderBuffer::new(window_ns, self.config.late_message_policy)),
+                },
+                reorder_counters: Arc::clone(&self.reorder),
+                // With a quiet feed the event-time window never advances, so
+                // held messages are also flushed after this much wall time
+                idle_flush: Duration::from_nanos(self.config.reorder_window_ns).max(Duration::from_millis(1)),
+            };
+            
+            let spawned = std::thread::Builder::new()
+                .name(format!("market-data-processor-{}", index))
+                .spawn(move || worker.run(receiver));
+            
+            match spawned {
+                Ok(handle) => workers.push(handle),
+                Err(e) => {
+                    // Unwind the shards that did start so the processor stays restartable
+                    let _ = self.stop_processing(ProcessingHandle { workers });
+                    return Err(MarketDataError::Spawn(e.to_string()));
+                }
             }
-        });
+        }
         
-        Ok(())
+        Ok(ProcessingHandle { workers })
     }
     
-    fn process_message(message: &MarketMessage, symbol_data: &Arc<Mutex<HashMap<String, SymbolData>>>) {
+    /// Closes intake, lets the workers drain everything already queued, joins
+    /// them and returns the final message count. Intake is reopened afterwards
+    /// so processing can be started again.
+    pub fn stop_processing(&self, handle: ProcessingHandle) -> MarketDataResult<usize> {
+        for shard in self.intake.write()?.iter_mut() {
+            shard.sender = None;
+        }
+        
+        let mut joined = Ok(());
+        for worker in handle.workers {
+            if worker.join().is_err() {
+                joined = Err(MarketDataError::WorkerPanicked);
+            }
+        }
+        
+        *self.intake.write()? = (0..self.shards.len()).map(|_| Intake::open(self.config.buffer_size)).collect();
+        
+        joined.map(|_| self.get_message_count())
+    }
+    
+    fn process_message(message: &ShardMessage, symbol_data: &Mutex<SymbolMap>, context: &ProcessingContext) -> MarketDataResult<()> {
+        let mut published = Vec::new();
+        let result = {
+            let mut data = symbol_data.lock()?;
+            Self::apply_message(message, &mut data, context, &mut published)
+        };
+        
+        // Publish outside the shard lock so readers are not held up by fan-out
+        for event in published {
+            context.events.publish(event);
+        }
+        result
+    }
+    
+    fn apply_message(message: &ShardMessage, data: &mut SymbolMap, context: &ProcessingContext, published: &mut Vec<MarketEvent>) -> MarketDataResult<()> {
         let timestamp = message.timestamp_ns;
+        let events = &context.events;
+        
+        // Every message counts towards activity, including modifies and
+        // cancels the book then rejects, so those create the symbol as well
+        let symbol_entry = data.entry(message.symbol)
+            .or_insert_with(|| SymbolData::new(context.symbol_name(message.symbol), context));
+        symbol_entry.activity.record(&message.message_type, timestamp);
+        
+        symbol_entry.version += 1;
+        if let Some(finished) = symbol_entry.session.roll(timestamp, &symbol_entry.calendar) {
+            symbol_entry.prior_session = Some(finished);
+        }
+        let mut closed_bars = Vec::new();
+        let mut vpin_points = Vec::new();
+        for bars in &mut symbol_entry.bars {
+            bars.advance(timestamp, &symbol_entry.calendar, &mut closed_bars);
+        }
+        let reference_price = symbol_entry.reference_price();
+        if let Some(kyle_lambda) = &mut symbol_entry.kyle_lambda {
+            kyle_lambda.advance(timestamp, reference_price);
+        }
+        let mut trade_spreads = Vec::new();
+        if let Some(spreads) = &mut symbol_entry.spreads {
+            spreads.advance(timestamp, symbol_entry.order_book.mid_price(), &mut trade_spreads);
+        }
+        
+        let bbo_before = (symbol_entry.order_book.best_bid(), symbol_entry.order_book.best_ask());
+        let precision = symbol_entry.precision;
+        let mut touched_levels: Vec<(Side, Price)> = Vec::new();
+        let mut trade_sign = None;
+        if let Some(order) = message.order_id.as_ref().and_then(|id| symbol_entry.order_book.get_order(id)) {
+            touched_levels.push((order.side, order.price));
+        }
         
         match message.message_type {
             MarketMessageType::Trade => {
-                if let (Some(price), Some(quantity)) = (message.price, message.quantity) {
-                    let mut data = symbol_data.lock().unwrap();
-                    
-                    let symbol_entry = data.entry(message.symbol.clone())
-                        .or_insert_with(|| SymbolData {
-                            last_price: 0.0,
-                            daily_volume: 0.0,
-                            last_update_time: 0,
-                            price_history: BTreeMap::new(),
-                            volume_history: BTreeMap::new(),
-                        });
-                    
+                let price = required(message.price, "price")?;
+                let quantity = required(message.quantity, "quantity")?;
+                
+                // A reported side wins, then the side of an executed resting
+                // order; only then is the configured algorithm consulted
+                let resting_side = message.order_id.as_ref()
+                    .and_then(|id| symbol_entry.order_book.get_order(id))
+                    .map(|order| order.side);
+                let classifier = context.trade_classifier;
+                let sign = match (message.is_buy, resting_side) {
+                    (Some(is_buy), _) => {
+                        symbol_entry.classifier.observe(price, classifier);
+                        TradeSign::discrete(is_buy, ClassificationMethod::Reported)
+                    },
+                    (None, Some(side)) => {
+                        symbol_entry.classifier.observe(price, classifier);
+                        TradeSign::discrete(side == Side::Ask, ClassificationMethod::RestingOrder)
+                    },
+                    (None, None) => symbol_entry.classifier.classify(classifier, price, bbo_before.0, bbo_before.1),
+                };
+                symbol_entry.classification_counts.record(sign.method);
+                symbol_entry.last_trade_sign = Some(sign);
+                trade_sign = Some(sign);
+                
+                symbol_entry.session.add_trade(timestamp, price, quantity);
+                for bars in &mut symbol_entry.bars {
+                    bars.add_trade(timestamp, price, quantity, sign.buy_fraction, &symbol_entry.calendar, &mut closed_bars);
+                }
+                if let Some(vpin) = &mut symbol_entry.vpin {
+                    vpin.add_trade(timestamp, price, precision.quantity_to_f64(quantity), sign.buy_fraction, &mut vpin_points);
+                }
+                if let Some(kyle_lambda) = &mut symbol_entry.kyle_lambda {
+                    kyle_lambda.add_trade(precision.quantity_to_f64(quantity), sign.buy_fraction);
+                }
+                if let Some(spreads) = &mut symbol_entry.spreads {
+                    let quote = match bbo_before {
+                        (Some(bid), Some(ask)) => Some((precision.price_to_f64(bid), precision.price_to_f64(ask))),
+                        _ => None,
+                    };
+                    spreads.add_trade(timestamp, precision.price_to_f64(price), quote, sign.buy_fraction, &mut trade_spreads);
+                }
+                
+                // A trade older than the latest one seen must not roll the
+                // last price back or overwrite a newer print in the same ms
+                let is_latest = timestamp >= symbol_entry.last_trade_time;
+                if is_latest {
                     symbol_entry.last_price = price;
-                    symbol_entry.daily_volume += quantity;
-                    symbol_entry.last_update_time = timestamp;
-                    
-                    let ms_timestamp = timestamp / 1_000_000;
-                    symbol_entry.price_history.insert(ms_timestamp, price);
-                    
-                    *symbol_entry.volume_history.entry(ms_timestamp).or_insert(0.0) += quantity;
+                    symbol_entry.last_trade_time = timestamp;
+                }
+                
+                let retention = &symbol_entry.retention;
+                symbol_entry.history.record(timestamp / 1_000_000, price, precision.quantity_total(quantity), is_latest, retention);
+                symbol_entry.history.enforce(retention);
+                symbol_entry.trades.record(timestamp, price, quantity);
+                symbol_entry.trades.enforce(retention);
+                
+                // Executions that reference a resting order consume it
+                if let Some(order_id) = &message.order_id {
+                    if symbol_entry.order_book.get_order(order_id).is_some() {
+                        symbol_entry.order_book.execute_order(order_id, quantity)?;
+                    }
+                }
+            },
+            MarketMessageType::Add => {
+                let order_id = required(message.order_id.as_ref(), "order_id")?;
+                let price = required(message.price, "price")?;
+                let quantity = required(message.quantity, "quantity")?;
+                let is_buy = required(message.is_buy, "is_buy")?;
+                
+                symbol_entry.order_book.add_order(order_id, Side::from_is_buy(is_buy), price, quantity, timestamp)?;
+            },
+            MarketMessageType::Modify => {
+                let order_id = required(message.order_id.as_ref(), "order_id")?;
+                symbol_entry.order_book.modify_order(order_id, message.price, message.quantity, timestamp)?;
+            },
+            MarketMessageType::Cancel => {
+                let order_id = required(message.order_id.as_ref(), "order_id")?;
+                symbol_entry.order_book.cancel_order(order_id)?;
+            },
+        }
+        symbol_entry.last_update_time = symbol_entry.last_update_time.max(timestamp);
+        if let Some(ofi) = &mut symbol_entry.ofi {
+            ofi.observe(timestamp, &symbol_entry.order_book);
+        }
+        let fair_value = symbol_entry.fair_value.as_mut().and_then(|fair_value| fair_value.update(timestamp, &symbol_entry.order_book));
+        
+        let mut toxicity = None;
+        if let Some(state) = &mut symbol_entry.toxicity {
+            let observation = match message.message_type {
+                MarketMessageType::Add => FlowObservation::Add {
+                    quantity: message.quantity.map_or(0.0, |quantity| precision.quantity_to_f64(quantity)),
+                    is_buy: message.is_buy.unwrap_or(true),
+                },
+                MarketMessageType::Modify => FlowObservation::Modify,
+                MarketMessageType::Cancel => FlowObservation::Cancel,
+                MarketMessageType::Trade => FlowObservation::Trade {
+                    price: message.price.map_or(0.0, |price| precision.price_to_f64(price)),
+                    mid_before: match bbo_before {
+                        (Some(bid), Some(ask)) => Some(precision.midpoint_to_f64(bid, ask)),
+                        _ => None,
+                    },
+                    mid_after: symbol_entry.order_book.mid_price(),
+                },
+            };
+            if let Some(score) = state.observe(observation, timestamp) {
+                symbol_entry.last_toxicity = Some(score.clone());
+                toxicity = Some(score);
+            }
+        }
+        
+        if !events.has_any_subscribers() {
+            return Ok(());
+        }
+        
+        if let Some(order) = message.order_id.as_ref().and_then(|id| symbol_entry.order_book.get_order(id)) {
+            if !touched_levels.contains(&(order.side, order.price)) {
+                touched_levels.push((order.side, order.price));
+            }
+        }
+        let book = &symbol_entry.order_book;
+        let bbo_after = (book.best_bid(), book.best_ask());
+        let is_trade = matches!(message.message_type, MarketMessageType::Trade);
+        
+        if let Some(aggressor) = trade_sign.filter(|_| events.has_subscribers(EventTopic::Trade)) {
+            published.push(MarketEvent::Trade {
+                symbol: symbol_entry.name.to_string(),
+                timestamp_ns: timestamp,
+                price: precision.price_to_f64(message.price.unwrap_or(symbol_entry.last_price)),
+                quantity: message.quantity.map_or(0.0, |quantity| precision.quantity_to_f64(quantity)),
+                aggressor,
+            });
+        }
+        if events.has_subscribers(EventTopic::BookUpdate) {
+            for (side, price) in touched_levels {
+                published.push(MarketEvent::BookUpdate {
+                    symbol: symbol_entry.name.to_string(),
+                    timestamp_ns: timestamp,
+                    side,
+                    price: precision.price_to_f64(price),
+                    quantity: precision.quantity_total_to_f64(book.level_quantity(side, price)),
+                });
+            }
+        }
+        if bbo_after != bbo_before && events.has_subscribers(EventTopic::BboChange) {
+            published.push(MarketEvent::BboChange {
+                symbol: symbol_entry.name.to_string(),
+                timestamp_ns: timestamp,
+                best_bid: bbo_after.0.map(|price| precision.price_to_f64(price)),
+                best_ask: bbo_after.1.map(|price| precision.price_to_f64(price)),
+            });
+        }
+        if (is_trade || bbo_after != bbo_before) && events.has_subscribers(EventTopic::Metrics) {
+            published.push(MarketEvent::Metrics {
+                symbol: symbol_entry.name.to_string(),
+                timestamp_ns: timestamp,
+                metrics: symbol_entry.metrics(),
+            });
+        }
+        if let Some(score) = toxicity.filter(|_| events.has_subscribers(EventTopic::Toxicity)) {
+            published.push(MarketEvent::Toxicity {
+                symbol: symbol_entry.name.to_string(),
+                timestamp_ns: timestamp,
+                score,
+            });
+        }
+        if events.has_subscribers(EventTopic::Vpin) {
+            for point in vpin_points {
+                published.push(MarketEvent::Vpin {
+                    symbol: symbol_entry.name.to_string(),
+                    timestamp_ns: timestamp,
+                    point,
+                });
+            }
+        }
+        if let Some(value) = fair_value.filter(|_| events.has_subscribers(EventTopic::FairValue)) {
+            published.push(MarketEvent::FairValue {
+                symbol: symbol_entry.name.to_string(),
+                timestamp_ns: timestamp,
+                value,
+            });
+        }
+        if events.has_subscribers(EventTopic::TradeSpread) {
+            for spread in trade_spreads {
+                published.push(MarketEvent::TradeSpread {
+                    symbol: symbol_entry.name.to_string(),
+                    timestamp_ns: timestamp,
+                    spread,
+                });
+            }
+        }
+        if events.has_subscribers(EventTopic::Bar) {
+            for (spec, bar) in closed_bars {
+                published.push(MarketEvent::Bar {
+                    symbol: symbol_entry.name.to_string(),
+                    timestamp_ns: timestamp,
+                    spec,
+                    bar,
+                });
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Latest published state of a symbol. Never waits on the processing
+    /// threads; see `ProcessorConfig::snapshot_interval` for staleness.
+    pub fn get_snapshot(&self, symbol: &str) -> MarketDataResult<Arc<SymbolSnapshot>> {
+        let id = self.symbol_id(symbol)?;
+        self.shards[self.shard_of(id)].snapshots.get(id)?
+            .ok_or_else(|| MarketDataError::UnknownSymbol(symbol.to_string()))
+    }
+    
+    pub fn get_snapshot_by_id(&self, symbol: SymbolId) -> MarketDataResult<Arc<SymbolSnapshot>> {
+        self.shards[self.shard_of(symbol)].snapshots.get(symbol)?
+            .ok_or_else(|| MarketDataError::UnknownSymbol(symbol.to_string()))
+    }
+    
+    pub fn get_last_price(&self, symbol: &str) -> MarketDataResult<f64> {
+        Ok(self.get_snapshot(symbol)?.last_price)
+    }
+    
+    pub fn get_daily_volume(&self, symbol: &str) -> MarketDataResult<f64> {
+        Ok(self.get_snapshot(symbol)?.daily_volume)
+    }
+    
+    /// Summary of the session in progress, if it has any trades.
+    pub fn get_session_summary(&self, symbol: &str) -> MarketDataResult<Option<SessionSummary>> {
+        Ok(self.get_snapshot(symbol)?.session)
+    }
+    
+    pub fn get_prior_session(&self, symbol: &str) -> MarketDataResult<Option<SessionSummary>> {
+        Ok(self.get_snapshot(symbol)?.prior_session)
+    }
+    
+    pub fn get_price_history(&self, symbol: &str, start_time: u64, end_time: u64) -> MarketDataResult<Vec<(u64, f64)>> {
+        Ok(self.get_snapshot(symbol)?.price_history_range(start_time, end_time))
+    }
+    
+    pub fn get_volume_history(&self, symbol: &str, start_time: u64, end_time: u64) -> MarketDataResult<Vec<(u64, f64)>> {
+        Ok(self.get_snapshot(symbol)?.volume_history_range(start_time, end_time))
+    }
+    
+    /// Approximate heap usage of a symbol's price and volume history,
+    /// trade index included.
+    pub fn get_history_memory_usage(&self, symbol: &str) -> MarketDataResult<usize> {
+        Ok(self.get_snapshot(symbol)?.history_bytes)
+    }
+    
+    /// Volume-weighted average trade price over `[start_time, end_time]`, in
+    /// nanoseconds; None if nothing traded.
+    pub fn get_vwap(&self, symbol: &str, start_time: u64, end_time: u64) -> MarketDataResult<Option<f64>> {
+        Ok(self.get_snapshot(symbol)?.trades.vwap(start_time, end_time))
+    }
+    
+    /// Time-weighted average of the last trade price over the range.
+    pub fn get_twap(&self, symbol: &str, start_time: u64, end_time: u64) -> MarketDataResult<Option<f64>> {
+        Ok(self.get_snapshot(symbol)?.trades.twap(start_time, end_time))
+    }
+    
+    pub fn get_traded_volume(&self, symbol: &str, start_time: u64, end_time: u64) -> MarketDataResult<f64> {
+        Ok(self.get_snapshot(symbol)?.trades.volume(start_time, end_time))
+    }
+    
+    /// Fraction of the range's traded volume made up by `quantity`, e.g. the
+    /// size of one's own fills.
+    pub fn get_participation_rate(&self, symbol: &str, start_time: u64, end_time: u64, quantity: f64) -> MarketDataResult<Option<f64>> {
+        Ok(self.get_snapshot(symbol)?.trades.participation_rate(start_time, end_time, quantity))
+    }
+    
+    /// VWAP of every trade from `anchor_time` on.
+    pub fn get_anchored_vwap(&self, symbol: &str, anchor_time: u64) -> MarketDataResult<Option<f64>> {
+        Ok(self.get_snapshot(symbol)?.trades.anchored_vwap(anchor_time))
+    }
+    
+    /// VWAP over the last `window_ns` before the symbol's latest update.
+    pub fn get_rolling_vwap(&self, symbol: &str, window_ns: u64) -> MarketDataResult<Option<f64>> {
+        let snapshot = self.get_snapshot(symbol)?;
+        Ok(snapshot.trades.rolling_vwap(snapshot.last_update_time, window_ns))
+    }
+    
+    pub fn get_rolling_twap(&self, symbol: &str, window_ns: u64) -> MarketDataResult<Option<f64>> {
+        let snapshot = self.get_snapshot(symbol)?;
+        Ok(snapshot.trades.rolling_twap(snapshot.last_update_time, window_ns))
+    }
+    
+    /// Messages of each type processed since the symbol was first seen.
+    pub fn get_message_counts(&self, symbol: &str) -> MarketDataResult<MessageCounts> {
+        Ok(self.get_snapshot(symbol)?.message_counts)
+    }
+    
+    /// Exact message counts over a window from `ProcessorConfig::activity_windows`,
+    /// ending at the symbol's latest message.
+    pub fn get_order_activity(&self, symbol: &str, window: Duration) -> MarketDataResult<OrderActivity> {
+        self.get_snapshot(symbol)?.order_activity.iter()
+            .find(|activity| activity.window == window)
+            .copied()
+            .ok_or_else(|| MarketDataError::Validation(format!("no {:?} activity window is configured", window)))
+    }
+    
+    /// Adds, modifies and cancels per trade over a configured window.
+    pub fn get_order_to_trade_ratio(&self, symbol: &str, window: Duration) -> MarketDataResult<Option<f64>> {
+        Ok(self.get_order_activity(symbol, window)?.counts.order_to_trade_ratio())
+    }
+    
+    pub fn get_trade_to_cancel_ratio(&self, symbol: &str, window: Duration) -> MarketDataResult<Option<f64>> {
+        Ok(self.get_order_activity(symbol, window)?.counts.trade_to_cancel_ratio())
+    }
+    
+    /// Latest flow toxicity score; None until scoring is configured and has
+    /// seen `ToxicityConfig::update_every` messages.
+    pub fn get_toxicity(&self, symbol: &str) -> MarketDataResult<Option<ToxicityScore>> {
+        Ok(self.get_snapshot(symbol)?.toxicity.clone())
+    }
+    
+    /// Latest VPIN point, or None before the first full window of buckets.
+    pub fn get_vpin(&self, symbol: &str) -> MarketDataResult<Option<VpinPoint>> {
+        Ok(self.vpin_series(symbol)?.latest().copied())
+    }
+    
+    /// VPIN points whose bucket completed within `[start_time, end_time]`.
+    pub fn get_vpin_series(&self, symbol: &str, start_time: u64, end_time: u64) -> MarketDataResult<Vec<VpinPoint>> {
+        Ok(self.vpin_series(symbol)?.range(start_time, end_time))
+    }
+    
+    fn vpin_series(&self, symbol: &str) -> MarketDataResult<VpinSeries> {
+        self.get_snapshot(symbol)?.vpin.clone()
+            .ok_or_else(|| MarketDataError::Validation(format!("VPIN is not configured for {}", symbol)))
+    }
+    
+    /// Latest Kyle's lambda estimate; None until enough intervals have closed.
+    pub fn get_kyle_lambda(&self, symbol: &str) -> MarketDataResult<Option<KyleLambdaPoint>> {
+        Ok(self.kyle_lambda_series(symbol)?.latest().copied())
+    }
+    
+    /// Kyle's lambda estimates whose last interval ended within
+    /// `[start_time, end_time]`.
+    pub fn get_kyle_lambda_series(&self, symbol: &str, start_time: u64, end_time: u64) -> MarketDataResult<Vec<KyleLambdaPoint>> {
+        Ok(self.kyle_lambda_series(symbol)?.range(start_time, end_time))
+    }
+    
+    fn kyle_lambda_series(&self, symbol: &str) -> MarketDataResult<KyleLambdaSeries> {
+        self.get_snapshot(symbol)?.kyle_lambda.clone()
+            .ok_or_else(|| MarketDataError::Validation("Kyle's lambda is not configured".to_string()))
+    }
+    
+    /// Spread averages of the intervals starting within
+    /// `[start_time, end_time]`.
+    pub fn get_spread_intervals(&self, symbol: &str, start_time: u64, end_time: u64) -> MarketDataResult<Vec<SpreadInterval>> {
+        Ok(self.spread_series(symbol)?.range(start_time, end_time))
+    }
+    
+    /// Latest trade whose spread decomposition is complete. Every trade's is
+    /// published on `EventTopic::TradeSpread`.
+    pub fn get_last_trade_spread(&self, symbol: &str) -> MarketDataResult<Option<TradeSpread>> {
+        Ok(self.spread_series(symbol)?.last_trade)
+    }
+    
+    fn spread_series(&self, symbol: &str) -> MarketDataResult<SpreadSeries> {
+        self.get_snapshot(symbol)?.spreads.clone()
+            .ok_or_else(|| MarketDataError::Validation("spread decomposition is not configured".to_string()))
+    }
+    
+    /// Microprice and weighted mids; None unless configured and the book is
+    /// two-sided. Every change is published on `EventTopic::FairValue`.
+    pub fn get_fair_value(&self, symbol: &str) -> MarketDataResult<Option<FairValue>> {
+        Ok(self.get_snapshot(symbol)?.fair_value.clone())
+    }
+    
+    /// Order flow imbalance over each configured window.
+    pub fn get_order_flow_imbalance(&self, symbol: &str) -> MarketDataResult<Vec<OrderFlowImbalance>> {
+        Ok(self.ofi_series(symbol)?.windows)
+    }
+    
+    /// Latest regression of mid changes on OFI; None until enough intervals
+    /// have closed.
+    pub fn get_ofi_regression(&self, symbol: &str) -> MarketDataResult<Option<OfiRegressionPoint>> {
+        Ok(self.ofi_series(symbol)?.latest().copied())
+    }
+    
+    pub fn get_ofi_regression_series(&self, symbol: &str, start_time: u64, end_time: u64) -> MarketDataResult<Vec<OfiRegressionPoint>> {
+        Ok(self.ofi_series(symbol)?.range(start_time, end_time))
+    }
+    
+    fn ofi_series(&self, symbol: &str) -> MarketDataResult<OfiSeries> {
+        self.get_snapshot(symbol)?.ofi.clone()
+            .ok_or_else(|| MarketDataError::Validation("order flow imbalance is not configured".to_string()))
+    }
+    
+    pub fn get_best_bid(&self, symbol: &str) -> MarketDataResult<Option<f64>> {
+        Ok(self.get_snapshot(symbol)?.best_bid)
+    }
+    
+    pub fn get_best_ask(&self, symbol: &str) -> MarketDataResult<Option<f64>> {
+        Ok(self.get_snapshot(symbol)?.best_ask)
+    }
+    
+    pub fn get_mid_price(&self, symbol: &str) -> MarketDataResult<Option<f64>> {
+        Ok(self.get_snapshot(symbol)?.mid_price)
+    }
+    
+    pub fn get_spread(&self, symbol: &str) -> MarketDataResult<Option<f64>> {
+        Ok(self.get_snapshot(symbol)?.spread)
+    }
+    
+    /// Up to `count` bid levels, limited by `ProcessorConfig::snapshot_depth`.
+    pub fn get_bid_levels(&self, symbol: &str, count: usize) -> MarketDataResult<Vec<BookLevel>> {
+        Ok(self.get_snapshot(symbol)?.bid_levels.iter().take(count).copied().collect())
+    }
+    
+    /// Up to `count` ask levels, limited by `ProcessorConfig::snapshot_depth`.
+    pub fn get_ask_levels(&self, symbol: &str, count: usize) -> MarketDataResult<Vec<BookLevel>> {
+        Ok(self.get_snapshot(symbol)?.ask_levels.iter().take(count).copied().collect())
+    }
+    
+    pub fn get_order_count(&self, symbol: &str) -> MarketDataResult<usize> {
+        Ok(self.get_snapshot(symbol)?.order_count)
+    }
+    
+    /// Completed bars of a configured series starting within
+    /// `[start_time, end_time]`, in nanoseconds.
+    pub fn get_bars(&self, symbol: &str, spec: BarSpec, start_time: u64, end_time: u64) -> MarketDataResult<Vec<Bar>> {
+        Ok(self.get_bar_series(symbol, spec)?.range(start_time, end_time))
+    }
+    
+    pub fn get_bar_series(&self, symbol: &str, spec: BarSpec) -> MarketDataResult<BarSeries> {
+        self.get_snapshot(symbol)?.bar_series(spec).cloned()
+    }
+    
+    /// Variance of log returns over `[start_time, end_time]` by the chosen
+    /// estimator; None if the window holds too few returns or bars.
+    /// Bar-based estimators need their series in `ProcessorConfig::bar_specs`.
+    pub fn get_volatility(&self, symbol: &str, estimator: VolatilityEstimator, start_time: u64, end_time: u64) -> MarketDataResult<Option<VolatilityEstimate>> {
+        let snapshot = self.get_snapshot(symbol)?;
+        volatility::estimate(&snapshot, estimator, start_time, end_time)
+    }
+}
+
+// Read-only state shared by all shard workers.
+struct ProcessingContext {
+    symbols: SymbolRegistry,
+    events: EventBus,
+    calendar: Arc<TradingCalendar>,
+    symbol_calendars: HashMap<String, Arc<TradingCalendar>>,
+    retention: RetentionPolicy,
+    symbol_retention: HashMap<String, RetentionPolicy>,
+    precision: Option<Precision>,
+    symbol_precision: HashMap<String, Precision>,
+    // Grid of each symbol by id, resolved from `symbol_precision` on first use
+    grids: RwLock<Vec<Option<Precision>>>,
+    trade_classifier: TradeClassifier,
+    bar_specs: Vec<BarSpec>,
+    symbol_bar_specs: HashMap<String, Vec<BarSpec>>,
+    empty_bars: EmptyBarPolicy,
+    max_bars: usize,
+    activity_windows: Vec<Duration>,
+    toxicity: Option<ToxicityConfig>,
+    symbol_toxicity: HashMap<String, ToxicityConfig>,
+    vpin: Option<VpinConfig>,
+    symbol_vpin: HashMap<String, VpinConfig>,
+    kyle_lambda: Option<KyleLambdaConfig>,
+    spreads: Option<SpreadConfig>,
+    ofi: Option<OfiConfig>,
+    fair_value: Option<FairValueConfig>,
+}
+
+impl ProcessingContext {
+    fn calendar_for(&self, symbol: &str) -> Arc<TradingCalendar> {
+        Arc::clone(self.symbol_calendars.get(symbol).unwrap_or(&self.calendar))
+    }
+    
+    fn retention_for(&self, symbol: &str) -> &RetentionPolicy {
+        self.symbol_retention.get(symbol).unwrap_or(&self.retention)
+    }
+    
+    fn precision_for(&self, symbol: &str) -> Precision {
+        match self.symbol_precision.get(symbol) {
+            Some(precision) => *precision,
+            None => self.default_precision(),
+        }
+    }
+    
+    fn default_precision(&self) -> Precision {
+        self.precision.unwrap_or_else(Precision::unscaled)
+    }
+    
+    // Same as `precision_for`, without hashing the ticker once it is cached
+    fn grid_for(&self, symbol: SymbolId) -> MarketDataResult<Precision> {
+        if self.symbol_precision.is_empty() {
+            return Ok(self.default_precision());
+        }
+        if let Some(Some(grid)) = self.grids.read()?.get(symbol.index()) {
+            return Ok(*grid);
+        }
+        let grid = self.precision_for(&self.symbol_name(symbol));
+        let mut grids = self.grids.write()?;
+        if grids.len() <= symbol.index() {
+            grids.resize(symbol.index() + 1, None);
+        }
+        grids[symbol.index()] = Some(grid);
+        Ok(grid)
+    }
+    
+    fn bar_specs_for(&self, symbol: &str) -> &[BarSpec] {
+        self.symbol_bar_specs.get(symbol).unwrap_or(&self.bar_specs)
+    }
+    
+    fn toxicity_for(&self, symbol: &str) -> Option<&ToxicityConfig> {
+        self.symbol_toxicity.get(symbol).or(self.toxicity.as_ref())
+    }
+    
+    fn vpin_for(&self, symbol: &str) -> Option<VpinConfig> {
+        self.symbol_vpin.get(symbol).copied().or(self.vpin)
+    }
+    
+    // Every id reaching a shard was interned at submission
+    fn symbol_name(&self, symbol: SymbolId) -> Arc<str> {
+        self.symbols.name(symbol).unwrap_or_else(|| Arc::from(symbol.to_string()))
+    }
+}
+
+// Everything a shard's worker thread needs, moved onto the thread at start.
+struct ShardWorker {
+    shard: Arc<Shard>,
+    // Symbols changed since snapshots were last published
+    dirty: HashSet<SymbolId>,
+    last_publish: Instant,
+    snapshot_interval: Duration,
+    snapshot_depth: usize,
+    message_count: Arc<AtomicUsize>,
+    error_count: Arc<AtomicUsize>,
+    context: Arc<ProcessingContext>,
+    conflation: Option<Arc<Mutex<ConflationState>>>,
+    reorder: Option<ReorderBuffer>,
+    reorder_counters: Arc<ReorderCounters>,
+    idle_flush: Duration,
+}
+
+impl ShardWorker {
+    fn run(mut self, receiver: Receiver<ShardMessage>) {
+        loop {
+            let holding = self.reorder.as_ref().is_some_and(|reorder| !reorder.is_empty());
+            let message = if holding {
+                match receiver.recv_timeout(self.idle_flush) {
+                    Ok(message) => message,
+                    Err(RecvTimeoutError::Timeout) => {
+                        self.flush_reorder();
+                        self.publish_snapshots(false);
+                        continue;
+                    },
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            } else {
+                match receiver.recv() {
+                    Ok(message) => message,
+                    Err(_) => break,
+                }
+            };
+            
+            let ready = match &self.conflation {
+                Some(conflation) => conflation.lock()
+                    .map(|mut state| state.record_processed(message.symbol))
+                    .unwrap_or_default(),
+                None => Vec::new(),
+            };
+            
+            self.accept(message);
+            for parked in ready {
+                self.accept(parked);
+            }
+            
+            if receiver.is_empty() || self.last_publish.elapsed() >= self.snapshot_interval {
+                self.publish_snapshots(false);
+            }
+        }
+        
+        if let Some(conflation) = &self.conflation {
+            let remaining = conflation.lock()
+                .map(|mut state| state.take_all_pending())
+                .unwrap_or_default();
+            for parked in remaining {
+                self.accept(parked);
+            }
+        }
+        self.flush_reorder();
+        self.publish_snapshots(true);
+    }
+    
+    fn accept(&mut self, message: ShardMessage) {
+        match self.reorder.as_mut() {
+            Some(reorder) => {
+                for released in reorder.push(message, &self.reorder_counters) {
+                    self.apply(&released);
                 }
             },
-            _ => {}
+            None => self.apply(&message),
         }
     }
     
-    pub fn get_last_price(&self, symbol: &str) -> Option<f64> {
-        let data = self.symbol_data.lock().unwrap();
-        data.get(symbol).map(|sd| sd.last_price)
+    fn flush_reorder(&mut self) {
+        let released = self.reorder.as_mut().map(|reorder| reorder.flush()).unwrap_or_default();
+        for message in released {
+            self.apply(&message);
+        }
     }
     
-    pub fn get_daily_volume(&self, symbol: &str) -> Option<f64> {
-        let data = self.symbol_data.lock().unwrap();
-        data.get(symbol).map(|sd| sd.daily_volume)
+    fn apply(&mut self, message: &ShardMessage) {
+        if MarketDataProcessor::process_message(message, &self.shard.symbols, &self.context).is_err() {
+            self.error_count.fetch_add(1, Ordering::Relaxed);
+        }
+        self.message_count.fetch_add(1, Ordering::Relaxed);
+        
+        self.dirty.insert(message.symbol);
     }
     
-    pub fn get_price_history(&self, symbol: &str, start_time: u64, end_time: u64) -> Vec<(u64, f64)> {
-        let data = self.symbol_data.lock().unwrap();
-        if let Some(sd) = data.get(symbol) {
-            return sd.price_history.range(start_time..=end_time)
-                .map(|(k, v)| (*k, *v))
-                .collect();
+    /// Publishes snapshots of the symbols changed since the last call. Unless
+    /// `wait` is set, gives up if readers hold the store and retries later.
+    fn publish_snapshots(&mut self, wait: bool) {
+        if self.dirty.is_empty() {
+            return;
+        }
+        
+        let mut snapshots = match self.shard.symbols.lock() {
+            Ok(mut symbols) => self.dirty.iter()
+                .filter_map(|symbol| symbols.get_mut(symbol).map(|sd| sd.snapshot(*symbol, self.snapshot_depth)))
+                .collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+        
+        let published = if wait {
+            self.shard.snapshots.publish(snapshots).is_ok()
+        } else {
+            self.shard.snapshots.try_publish(&mut snapshots)
+        };
+        if published {
+            self.dirty.clear();
+            self.last_publish = Instant::now();
         }
-        Vec::new()
     }
 }
 
-pub fn current_time_ns() -> u64 {
+pub fn current_time_ns() -> MarketDataResult<u64> {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos() as u64
-} 
\ No newline at end of file
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .map_err(|e| MarketDataError::Clock(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixed_point::FixedScale;
+    
+    fn message(symbol: &str, message_type: MarketMessageType, timestamp_ns: u64) -> MarketMessage {
+        MarketMessage {
+            timestamp_ns,
+            symbol: symbol.to_string(),
+            message_type,
+            order_id: None,
+            price: None,
+            quantity: None,
+            is_buy: None,
+            trade_id: None,
+            channel_id: None,
+            sequence: None,
+        }
+    }
+    
+    fn add(symbol: &str, order_id: &str, price: f64, quantity: f64, is_buy: bool, timestamp_ns: u64) -> MarketMessage {
+        MarketMessage {
+            order_id: Some(order_id.to_string()),
+            price: Some(price),
+            quantity: Some(quantity),
+            is_buy: Some(is_buy),
+            ..message(symbol, MarketMessageType::Add, timestamp_ns)
+        }
+    }
+    
+    fn trade(symbol: &str, price: f64, quantity: f64, timestamp_ns: u64) -> MarketMessage {
+        MarketMessage {
+            price: Some(price),
+            quantity: Some(quantity),
+            is_buy: Some(true),
+            ..message(symbol, MarketMessageType::Trade, timestamp_ns)
+        }
+    }
+    
+    fn with_backpressure(buffer_size: usize, backpressure: BackpressurePolicy) -> MarketDataProcessor {
+        MarketDataProcessor::with_config(ProcessorConfig { buffer_size, backpressure, ..ProcessorConfig::default() })
+    }
+    
+    #[test]
+    fn block_queues_every_message() {
+        let processor = with_backpressure(1, BackpressurePolicy::Block);
+        let handle = processor.start_processing().unwrap();
+        for i in 0..20 {
+            processor.submit_message(add("AAPL", &i.to_string(), 100.0, 1.0, true, i)).unwrap();
+        }
+        
+        assert_eq!(processor.stop_processing(handle), Ok(20));
+        assert_eq!(processor.get_order_count("AAPL"), Ok(20));
+        let stats = processor.get_backpressure_stats();
+        assert_eq!((stats.dropped, stats.rejected, stats.conflated), (0, 0, 0));
+    }
+    
+    #[test]
+    fn block_with_timeout_rejects_once_the_wait_expires() {
+        let processor = with_backpressure(1, BackpressurePolicy::BlockWithTimeout(Duration::from_millis(5)));
+        processor.submit_message(add("AAPL", "1", 100.0, 1.0, true, 1)).unwrap();
+        
+        assert_eq!(processor.submit_message(add("AAPL", "2", 100.0, 1.0, true, 2)), Err(MarketDataError::QueueFull));
+        let stats = processor.get_backpressure_stats();
+        assert_eq!(stats.rejected, 1);
+        assert_eq!(stats.queue_high_water_mark, 1);
+    }
+    
+    #[test]
+    fn fail_fast_rejects_when_full_and_the_message_can_be_resubmitted() {
+        let processor = with_backpressure(2, BackpressurePolicy::FailFast);
+        processor.submit_message(add("AAPL", "1", 100.0, 1.0, true, 1)).unwrap();
+        processor.submit_message(add("AAPL", "2", 100.0, 1.0, true, 2)).unwrap();
+        
+        assert_eq!(processor.submit_message(add("AAPL", "3", 100.0, 1.0, true, 3)), Err(MarketDataError::QueueFull));
+        assert_eq!(processor.get_backpressure_stats().rejected, 1);
+        
+        let handle = processor.start_processing().unwrap();
+        processor.stop_processing(handle).unwrap();
+        let handle = processor.start_processing().unwrap();
+        processor.submit_message(add("AAPL", "3", 100.0, 1.0, true, 3)).unwrap();
+        processor.stop_processing(handle).unwrap();
+        assert_eq!(processor.get_order_count("AAPL"), Ok(3));
+    }
+    
+    #[test]
+    fn drop_newest_reports_success_and_counts_drops() {
+        let processor = with_backpressure(2, BackpressurePolicy::DropNewest);
+        for i in 0..5 {
+            processor.submit_message(add("AAPL", &i.to_string(), 100.0, 1.0, true, i)).unwrap();
+        }
+        
+        let stats = processor.get_backpressure_stats();
+        assert_eq!((stats.dropped, stats.rejected), (3, 0));
+        assert_eq!(stats.queue_high_water_mark, 2);
+        let handle = processor.start_processing().unwrap();
+        assert_eq!(processor.stop_processing(handle), Ok(2));
+        assert_eq!(processor.get_order_count("AAPL"), Ok(2));
+    }
+    
+    #[test]
+    fn conflate_latest_never_merges_order_events() {
+        let processor = with_backpressure(1, BackpressurePolicy::ConflateLatest);
+        processor.submit_message(add("AAPL", "0", 100.0, 1.0, true, 0)).unwrap();
+        for i in 1..5 {
+            assert_eq!(processor.submit_message(add("AAPL", &i.to_string(), 100.0, 1.0, true, i)), Err(MarketDataError::QueueFull));
+        }
+        
+        let stats = processor.get_backpressure_stats();
+        assert_eq!((stats.conflated, stats.rejected, stats.dropped), (0, 4, 0));
+        let handle = processor.start_processing().unwrap();
+        assert_eq!(processor.stop_processing(handle), Ok(1));
+        assert_eq!(processor.get_order_count("AAPL"), Ok(1));
+    }
+    
+    #[test]
+    fn conflate_latest_rejects_trades_instead_of_replacing_them() {
+        let processor = with_backpressure(1, BackpressurePolicy::ConflateLatest);
+        processor.submit_message(trade("AAPL", 100.0, 10.0, 1)).unwrap();
+        assert_eq!(processor.submit_message(trade("AAPL", 101.0, 10.0, 2)), Err(MarketDataError::QueueFull));
+        
+        let handle = processor.start_processing().unwrap();
+        processor.stop_processing(handle).unwrap();
+        let handle = processor.start_processing().unwrap();
+        processor.submit_message(trade("AAPL", 101.0, 10.0, 2)).unwrap();
+        processor.stop_processing(handle).unwrap();
+        assert_eq!(processor.get_daily_volume("AAPL"), Ok(20.0));
+        assert_eq!(processor.get_last_price("AAPL"), Ok(101.0));
+    }
+    
+    #[test]
+    fn volumes_beyond_i64_units_accumulate_without_overflow() {
+        let processor = MarketDataProcessor::with_config(ProcessorConfig { precision: Some(Precision::default()), ..ProcessorConfig::default() });
+        let handle = processor.start_processing().unwrap();
+        for i in 0..3 {
+            processor.submit_message(trade("AAPL", 100.0, 9e10, i)).unwrap();
+        }
+        processor.submit_message(trade("AAPL", 100.0, 1.0, 3)).unwrap();
+        
+        assert_eq!(processor.stop_processing(handle), Ok(4));
+        assert_eq!(processor.get_error_count(), 0);
+        assert_eq!(processor.get_daily_volume("AAPL"), Ok(2.7e11 + 1.0));
+        assert_eq!(processor.get_vwap("AAPL", 0, 3), Ok(Some(100.0)));
+    }
+    
+    #[test]
+    fn symbols_without_precision_keep_their_values_unscaled() {
+        let processor = MarketDataProcessor::new(100);
+        let handle = processor.start_processing().unwrap();
+        processor.submit_message(trade("AAPL", 100.0 / 3.0, 2e11, 1)).unwrap();
+        processor.submit_message(trade("DUST", 1e-9, 1.0, 1)).unwrap();
+        processor.submit_message(add("DUST", "bid", 1e-9, 5.0, true, 2)).unwrap();
+        processor.submit_message(add("DUST", "ask", 3e-9, 5.0, false, 3)).unwrap();
+        processor.stop_processing(handle).unwrap();
+        
+        assert_eq!(processor.get_error_count(), 0);
+        assert_eq!(processor.get_daily_volume("AAPL"), Ok(2e11));
+        assert_eq!(processor.get_last_price("AAPL"), Ok(100.0 / 3.0));
+        assert_eq!(processor.get_last_price("DUST"), Ok(1e-9));
+        assert_eq!(processor.get_mid_price("DUST"), Ok(Some(2e-9)));
+        let spread = processor.get_spread("DUST").unwrap().unwrap();
+        assert!((spread - 2e-9).abs() < 1e-20);
+    }
+    
+    #[test]
+    fn values_that_round_to_zero_on_the_grid_are_rejected() {
+        let precision = Precision::new(FixedScale::decimals(2), FixedScale::decimals(0));
+        let processor = MarketDataProcessor::with_config(ProcessorConfig { precision: Some(precision), ..ProcessorConfig::default() });
+        
+        assert!(matches!(processor.submit_message(trade("AAPL", 0.001, 1.0, 1)), Err(MarketDataError::Validation(_))));
+        assert!(matches!(processor.submit_message(trade("AAPL", 1.0, 0.2, 1)), Err(MarketDataError::Validation(_))));
+    }
+    
+    #[test]
+    fn last_sequence_number_is_a_validation_error() {
+        let processor = MarketDataProcessor::new(100);
+        let sequenced = |sequence: u64| MarketMessage { sequence: Some(sequence), ..trade("AAPL", 100.0, 1.0, 1) };
+        processor.submit_message(sequenced(u64::MAX - 1)).unwrap();
+        
+        assert!(matches!(processor.submit_message(sequenced(u64::MAX)), Err(MarketDataError::Validation(_))));
+        assert!(processor.get_sequence_stats().is_ok());
+        assert!(processor.submit_message(sequenced(u64::MAX - 1)).is_ok());
+    }
+    
+    #[test]
+    fn rejected_cancels_and_modifies_still_count_as_activity() {
+        let processor = MarketDataProcessor::with_config(ProcessorConfig { activity_windows: vec![Duration::from_secs(1)], ..ProcessorConfig::default() });
+        let handle = processor.start_processing().unwrap();
+        processor.submit_message(add("AAPL", "1", 100.0, 1.0, true, 1)).unwrap();
+        processor.submit_message(MarketMessage { order_id: Some("missing".to_string()), ..message("AAPL", MarketMessageType::Cancel, 2) }).unwrap();
+        processor.submit_message(MarketMessage { order_id: Some("missing".to_string()), quantity: Some(5.0), ..message("AAPL", MarketMessageType::Modify, 3) }).unwrap();
+        processor.submit_message(MarketMessage { order_id: Some("1".to_string()), ..message("MSFT", MarketMessageType::Cancel, 4) }).unwrap();
+        processor.stop_processing(handle).unwrap();
+        
+        assert_eq!(processor.get_error_count(), 3);
+        let counts = processor.get_order_activity("AAPL", Duration::from_secs(1)).unwrap().counts;
+        assert_eq!((counts.adds, counts.modifies, counts.cancels), (1, 1, 1));
+        assert_eq!(processor.get_message_counts("MSFT").unwrap().cancels, 1);
+        assert_eq!(processor.get_order_count("AAPL"), Ok(1));
+    }
+    
+    #[test]
+    fn stop_drains_the_queue_and_returns_the_message_count() {
+        let processor = MarketDataProcessor::new(100);
+        for i in 0..50 {
+            processor.submit_message(add("AAPL", &i.to_string(), 100.0, 1.0, true, i)).unwrap();
+        }
+        let handle = processor.start_processing().unwrap();
+        
+        assert_eq!(processor.stop_processing(handle), Ok(50));
+        assert_eq!(processor.get_order_count("AAPL"), Ok(50));
+    }
+    
+    #[test]
+    fn processing_restarts_after_stop() {
+        let processor = MarketDataProcessor::new(100);
+        let handle = processor.start_processing().unwrap();
+        processor.submit_message(add("AAPL", "1", 100.0, 1.0, true, 1)).unwrap();
+        assert_eq!(processor.stop_processing(handle), Ok(1));
+        assert_eq!(processor.is_running(), Ok(false));
+        
+        let handle = processor.start_processing().unwrap();
+        assert_eq!(processor.is_running(), Ok(true));
+        processor.submit_message(add("AAPL", "2", 100.0, 1.0, true, 2)).unwrap();
+        assert_eq!(processor.stop_processing(handle), Ok(2));
+        assert_eq!(processor.get_order_count("AAPL"), Ok(2));
+    }
+    
+    #[test]
+    fn second_start_is_rejected() {
+        let processor = MarketDataProcessor::with_shards(100, 4);
+        let handle = processor.start_processing().unwrap();
+        
+        assert!(matches!(processor.start_processing(), Err(MarketDataError::AlreadyRunning)));
+        assert_eq!(processor.stop_processing(handle), Ok(0));
+    }
+    
+    #[test]
+    fn each_symbol_keeps_its_order_across_shards() {
+        let processor = MarketDataProcessor::with_shards(4, 4);
+        let symbols: Vec<String> = (0..8).map(|i| format!("SYM{}", i)).collect();
+        let handle = processor.start_processing().unwrap();
+        std::thread::scope(|scope| {
+            for symbol in &symbols {
+                let processor = &processor;
+                scope.spawn(move || {
+                    for i in 0..200u64 {
+                        let id = i.to_string();
+                        // Out of order, the modify or cancel would miss its order
+                        processor.submit_message(add(symbol, &id, 100.0, 1.0, true, 3 * i)).unwrap();
+                        processor.submit_message(MarketMessage { order_id: Some(id.clone()), quantity: Some(2.0), ..message(symbol, MarketMessageType::Modify, 3 * i + 1) }).unwrap();
+                        processor.submit_message(MarketMessage { order_id: Some(id), ..message(symbol, MarketMessageType::Cancel, 3 * i + 2) }).unwrap();
+                    }
+                    processor.submit_message(trade(symbol, 123.0, 1.0, 600)).unwrap();
+                });
+            }
+        });
+        
+        assert_eq!(processor.stop_processing(handle), Ok(8 * 601));
+        assert_eq!(processor.get_error_count(), 0);
+        let shards: HashSet<usize> = symbols.iter().map(|symbol| processor.shard_for_symbol(symbol).unwrap()).collect();
+        assert_eq!(shards.len(), 4);
+        for symbol in &symbols {
+            assert_eq!(processor.get_order_count(symbol), Ok(0));
+            assert_eq!(processor.get_last_price(symbol), Ok(123.0));
+        }
+    }
+    
+    #[test]
+    fn malformed_messages_are_validation_errors() {
+        let processor = MarketDataProcessor::new(100);
+        let invalid = [
+            MarketMessage { price: None, ..trade("AAPL", 100.0, 1.0, 1) },
+            MarketMessage { quantity: None, ..trade("AAPL", 100.0, 1.0, 1) },
+            trade("AAPL", f64::NAN, 1.0, 1),
+            trade("AAPL", f64::INFINITY, 1.0, 1),
+            trade("AAPL", 0.0, 1.0, 1),
+            trade("AAPL", -1.0, 1.0, 1),
+            trade("AAPL", 100.0, -1.0, 1),
+            trade("", 100.0, 1.0, 1),
+            MarketMessage { is_buy: None, ..add("AAPL", "1", 100.0, 1.0, true, 1) },
+            MarketMessage { order_id: None, ..add("AAPL", "1", 100.0, 1.0, true, 1) },
+            MarketMessage { order_id: Some("1".to_string()), ..message("AAPL", MarketMessageType::Modify, 1) },
+            message("AAPL", MarketMessageType::Cancel, 1),
+        ];
+        for message in invalid {
+            assert!(matches!(processor.submit_message(message), Err(MarketDataError::Validation(_))));
+        }
+    }
+    
+    #[test]
+    fn queries_for_unseen_symbols_are_errors() {
+        let processor = MarketDataProcessor::new(100);
+        processor.register_symbol("AAPL").unwrap();
+        
+        assert_eq!(processor.get_last_price("MSFT"), Err(MarketDataError::UnknownSymbol("MSFT".to_string())));
+        assert_eq!(processor.shard_for_symbol("MSFT"), Err(MarketDataError::UnknownSymbol("MSFT".to_string())));
+        assert_eq!(processor.get_best_bid("AAPL"), Err(MarketDataError::UnknownSymbol("AAPL".to_string())));
+    }
+    
+    #[test]
+    fn book_errors_are_counted_and_processing_continues() {
+        let processor = MarketDataProcessor::new(100);
+        let handle = processor.start_processing().unwrap();
+        processor.submit_message(add("AAPL", "1", 100.0, 1.0, true, 1)).unwrap();
+        processor.submit_message(add("AAPL", "1", 101.0, 1.0, true, 2)).unwrap();
+        processor.submit_message(MarketMessage { order_id: Some("2".to_string()), ..message("AAPL", MarketMessageType::Cancel, 3) }).unwrap();
+        processor.submit_message(add("AAPL", "3", 102.0, 1.0, false, 4)).unwrap();
+        
+        assert_eq!(processor.stop_processing(handle), Ok(4));
+        assert_eq!(processor.get_error_count(), 2);
+        assert_eq!(processor.get_order_count("AAPL"), Ok(2));
+        assert_eq!(processor.get_best_bid("AAPL"), Ok(Some(100.0)));
+    }
+    
+    #[test]
+    fn range_volatility_estimators_use_configured_bars() {
+        let bars = BarSpec::Ticks(4);
+        let processor = MarketDataProcessor::with_config(ProcessorConfig { bar_specs: vec![bars], ..ProcessorConfig::default() });
+        let handle = processor.start_processing().unwrap();
+        for (i, price) in [100.0, 102.0, 99.0, 101.0].into_iter().enumerate() {
+            processor.submit_message(trade("AAPL", price, 1.0, i as u64)).unwrap();
+        }
+        processor.stop_processing(handle).unwrap();
+        
+        // One bar, open 100, high 102, low 99, close 101; Python reference values
+        let variance = |estimator| processor.get_volatility("AAPL", estimator, 0, u64::MAX).unwrap().unwrap().variance;
+        assert!((variance(VolatilityEstimator::Parkinson { bars }) - 3.214_322_418_855_839_6e-4).abs() < 1e-15);
+        assert!((variance(VolatilityEstimator::GarmanKlass { bars }) - 4.073_530_535_254_599_4e-4).abs() < 1e-15);
+        assert!((variance(VolatilityEstimator::RogersSatchell { bars }) - 3.961_147_721_684_104e-4).abs() < 1e-15);
+        
+        let missing = VolatilityEstimator::Parkinson { bars: BarSpec::Ticks(5) };
+        assert!(processor.get_volatility("AAPL", missing, 0, u64::MAX).is_err());
+    }
+    
+    #[test]
+    fn time_bars_close_on_the_symbols_own_messages() {
+        let bars = BarSpec::Time(Duration::from_secs(1));
+        let processor = MarketDataProcessor::with_config(ProcessorConfig { bar_specs: vec![bars], ..ProcessorConfig::default() });
+        let handle = processor.start_processing().unwrap();
+        processor.submit_message(trade("AAPL", 100.0, 1.0, 100)).unwrap();
+        // Other symbols moving on leave AAPL's bar open
+        processor.submit_message(trade("MSFT", 200.0, 1.0, 5_000_000_000)).unwrap();
+        processor.stop_processing(handle).unwrap();
+        
+        let series = processor.get_bar_series("AAPL", bars).unwrap();
+        assert!(series.completed.is_empty());
+        assert_eq!(series.current.map(|bar| (bar.start_ns, bar.end_ns)), Some((0, 1_000_000_000)));
+        
+        // Any later message for the symbol closes it, even one that is not a trade
+        let handle = processor.start_processing().unwrap();
+        processor.submit_message(add("AAPL", "1", 99.0, 1.0, true, 3_000_000_000)).unwrap();
+        processor.stop_processing(handle).unwrap();
+        
+        let series = processor.get_bar_series("AAPL", bars).unwrap();
+        assert_eq!(series.completed.len(), 1);
+        assert_eq!(series.current, None);
+        assert_eq!(processor.get_bars("AAPL", bars, 0, u64::MAX).unwrap()[0].close, 100.0);
+    }
+    
+    #[test]
+    fn activity_windows_end_at_the_symbols_latest_message() {
+        let window = Duration::from_secs(1);
+        let processor = MarketDataProcessor::with_config(ProcessorConfig { activity_windows: vec![window], ..ProcessorConfig::default() });
+        let handle = processor.start_processing().unwrap();
+        processor.submit_message(add("AAPL", "1", 100.0, 1.0, true, 100)).unwrap();
+        processor.submit_message(trade("AAPL", 100.0, 1.0, 200)).unwrap();
+        // Other symbols moving on do not age AAPL's counts
+        processor.submit_message(trade("MSFT", 200.0, 1.0, 10_000_000_000)).unwrap();
+        processor.stop_processing(handle).unwrap();
+        
+        let activity = processor.get_order_activity("AAPL", window).unwrap();
+        assert_eq!(activity.end_ns, 200);
+        assert_eq!((activity.counts.adds, activity.counts.trades), (1, 1));
+        
+        // The symbol's own next message moves its window on
+        let handle = processor.start_processing().unwrap();
+        let cancel = MarketMessage { order_id: Some("1".to_string()), ..message("AAPL", MarketMessageType::Cancel, 5_000_000_000) };
+        processor.submit_message(cancel).unwrap();
+        processor.stop_processing(handle).unwrap();
+        
+        let activity = processor.get_order_activity("AAPL", window).unwrap();
+        assert_eq!(activity.end_ns, 5_000_000_000);
+        assert_eq!(activity.counts, MessageCounts { adds: 0, modifies: 0, cancels: 1, trades: 0 });
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+
+/// Slope of a least-squares line with an intercept.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LineFit {
+    pub(crate) slope: f64,
+    pub(crate) standard_error: f64,
+    pub(crate) r_squared: f64,
+}
+
+/// Ordinary least squares of y on x; needs at least three observations and
+/// some variation in x.
+pub(crate) fn fit_line(observations: &VecDeque<(f64, f64)>) -> Option<LineFit> {
+    let n = observations.len();
+    if n < 3 {
+        return None;
+    }
+    let count = n as f64;
+    let mean_x = observations.iter().map(|(x, _)| x).sum::<f64>() / count;
+    let mean_y = observations.iter().map(|(_, y)| y).sum::<f64>() / count;
+    let (mut sxx, mut sxy, mut syy) = (0.0, 0.0, 0.0);
+    for (x, y) in observations {
+        let (dx, dy) = (x - mean_x, y - mean_y);
+        sxx += dx * dx;
+        sxy += dx * dy;
+        syy += dy * dy;
+    }
+    if sxx <= 0.0 {
+        return None;
+    }
+
+    let slope = sxy / sxx;
+    let residual = (syy - slope * sxy).max(0.0);
+    Some(LineFit {
+        slope,
+        standard_error: (residual / (count - 2.0) / sxx).sqrt(),
+        r_squared: if syy > 0.0 { 1.0 - residual / syy } else { 0.0 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fit(points: &[(f64, f64)]) -> Option<LineFit> {
+        fit_line(&points.iter().copied().collect())
+    }
+
+    #[test]
+    fn exact_line() {
+        let line = fit(&[(0.0, 1.0), (1.0, 3.0), (2.0, 5.0)]).unwrap();
+        assert_eq!((line.slope, line.standard_error, line.r_squared), (2.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn noisy_line() {
+        let line = fit(&[(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (3.0, 1.0)]).unwrap();
+        assert!((line.slope - 0.2).abs() < 1e-12);
+        assert!((line.standard_error - 0.08f64.sqrt()).abs() < 1e-12);
+        assert!((line.r_squared - 0.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn degenerate_inputs() {
+        assert!(fit(&[(0.0, 0.0), (1.0, 1.0)]).is_none());
+        assert!(fit(&[(1.0, 0.0), (1.0, 1.0), (1.0, 2.0)]).is_none());
+        assert_eq!(fit(&[(0.0, 2.0), (1.0, 2.0), (2.0, 2.0)]).unwrap().r_squared, 0.0);
+    }
+}
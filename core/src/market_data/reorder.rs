@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::processor::ShardMessage;
+
+/// What to do with a message that arrives after newer messages have already
+/// been released from the reorder window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LateMessagePolicy {
+    /// Apply it immediately, out of order.
+    #[default]
+    Process,
+    Drop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReorderStats {
+    pub reordered: u64,
+    pub late: u64,
+    pub late_dropped: u64,
+    pub max_lateness_ns: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ReorderCounters {
+    reordered: AtomicU64,
+    late: AtomicU64,
+    late_dropped: AtomicU64,
+    max_lateness_ns: AtomicU64,
+}
+
+impl ReorderCounters {
+    pub(crate) fn snapshot(&self) -> ReorderStats {
+        ReorderStats {
+            reordered: self.reordered.load(Ordering::Relaxed),
+            late: self.late.load(Ordering::Relaxed),
+            late_dropped: self.late_dropped.load(Ordering::Relaxed),
+            max_lateness_ns: self.max_lateness_ns.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Holds messages for up to `window_ns` of event time and releases them in
+/// timestamp order. Messages with equal timestamps keep their arrival order.
+pub(crate) struct ReorderBuffer {
+    window_ns: u64,
+    late_policy: LateMessagePolicy,
+    pending: BTreeMap<(u64, u64), ShardMessage>,
+    arrival: u64,
+    max_seen_ns: u64,
+    released_ns: Option<u64>,
+}
+
+impl ReorderBuffer {
+    pub(crate) fn new(window_ns: u64, late_policy: LateMessagePolicy) -> Self {
+        ReorderBuffer {
+            window_ns,
+            late_policy,
+            pending: BTreeMap::new(),
+            arrival: 0,
+            max_seen_ns: 0,
+            released_ns: None,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Buffers a message and returns whatever has fallen out of the window.
+    pub(crate) fn push(&mut self, message: ShardMessage, counters: &ReorderCounters) -> Vec<ShardMessage> {
+        let timestamp = message.timestamp_ns;
+
+        if self.released_ns.is_some_and(|released| timestamp < released) {
+            counters.late.fetch_add(1, Ordering::Relaxed);
+            counters.max_lateness_ns.fetch_max(self.max_seen_ns - timestamp, Ordering::Relaxed);
+            return match self.late_policy {
+                LateMessagePolicy::Process => vec![message],
+                LateMessagePolicy::Drop => {
+                    counters.late_dropped.fetch_add(1, Ordering::Relaxed);
+                    Vec::new()
+                },
+            };
+        }
+
+        if timestamp < self.max_seen_ns {
+            counters.reordered.fetch_add(1, Ordering::Relaxed);
+        }
+        self.max_seen_ns = self.max_seen_ns.max(timestamp);
+        self.pending.insert((timestamp, self.arrival), message);
+        self.arrival += 1;
+
+        let watermark = self.max_seen_ns.saturating_sub(self.window_ns);
+        let retained = self.pending.split_off(&(watermark, 0));
+        let released = std::mem::replace(&mut self.pending, retained);
+        self.take_released(released)
+    }
+
+    pub(crate) fn flush(&mut self) -> Vec<ShardMessage> {
+        let released = std::mem::take(&mut self.pending);
+        self.take_released(released)
+    }
+
+    fn take_released(&mut self, released: BTreeMap<(u64, u64), ShardMessage>) -> Vec<ShardMessage> {
+        if let Some(((timestamp, _), _)) = released.last_key_value() {
+            self.released_ns = Some(self.released_ns.map_or(*timestamp, |released| released.max(*timestamp)));
+        }
+        released.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::processor::MarketMessageType;
+    use super::super::symbols::SymbolRegistry;
+
+    fn message(timestamp_ns: u64, order_id: &str) -> ShardMessage {
+        ShardMessage {
+            symbol: SymbolRegistry::new().intern("A").unwrap(),
+            timestamp_ns,
+            message_type: MarketMessageType::Add,
+            order_id: Some(order_id.to_string()),
+            price: None,
+            quantity: None,
+            is_buy: None,
+        }
+    }
+
+    fn ids(messages: Vec<ShardMessage>) -> Vec<String> {
+        messages.into_iter().filter_map(|message| message.order_id).collect()
+    }
+
+    #[test]
+    fn releases_in_timestamp_order_once_past_the_window() {
+        let counters = ReorderCounters::default();
+        let mut buffer = ReorderBuffer::new(10, LateMessagePolicy::Process);
+        assert!(ids(buffer.push(message(100, "a"), &counters)).is_empty());
+        assert!(ids(buffer.push(message(95, "b"), &counters)).is_empty());
+        assert!(ids(buffer.push(message(100, "c"), &counters)).is_empty());
+        // Watermark 104 releases everything before it, equal timestamps in arrival order
+        assert_eq!(ids(buffer.push(message(114, "d"), &counters)), ["b", "a", "c"]);
+        assert!(buffer.push(message(111, "e"), &counters).is_empty());
+        assert!(buffer.push(message(120, "f"), &counters).is_empty());
+        assert_eq!(ids(buffer.push(message(125, "g"), &counters)), ["e", "d"]);
+        assert_eq!(ids(buffer.flush()), ["f", "g"]);
+        assert!(buffer.is_empty());
+
+        let stats = counters.snapshot();
+        assert_eq!((stats.reordered, stats.late), (2, 0));
+    }
+
+    #[test]
+    fn late_messages_follow_the_policy() {
+        for (policy, processed) in [(LateMessagePolicy::Process, 1), (LateMessagePolicy::Drop, 0)] {
+            let counters = ReorderCounters::default();
+            let mut buffer = ReorderBuffer::new(10, policy);
+            buffer.push(message(100, "a"), &counters);
+            assert_eq!(ids(buffer.push(message(200, "b"), &counters)), ["a"]);
+
+            // Older than a released message
+            assert_eq!(buffer.push(message(90, "late"), &counters).len(), processed);
+            // Within the window of what is still held
+            assert!(buffer.push(message(195, "held"), &counters).is_empty());
+
+            let stats = counters.snapshot();
+            assert_eq!((stats.late, stats.late_dropped, stats.max_lateness_ns), (1, 1 - processed as u64, 110));
+            assert_eq!(ids(buffer.flush()), ["held", "b"]);
+        }
+    }
+
+    #[test]
+    fn zero_window_holds_only_the_newest_timestamp() {
+        let counters = ReorderCounters::default();
+        let mut buffer = ReorderBuffer::new(0, LateMessagePolicy::Process);
+        assert!(buffer.push(message(5, "a"), &counters).is_empty());
+        assert_eq!(ids(buffer.push(message(6, "b"), &counters)), ["a"]);
+        assert!(buffer.push(message(6, "c"), &counters).is_empty());
+        // Not older than anything released, so reordered rather than late
+        assert_eq!(ids(buffer.push(message(5, "d"), &counters)), ["d"]);
+        assert_eq!(ids(buffer.push(message(4, "e"), &counters)), ["e"]);
+
+        let stats = counters.snapshot();
+        assert_eq!((stats.reordered, stats.late), (1, 1));
+        assert_eq!(ids(buffer.flush()), ["b", "c"]);
+    }
+}
@@ -0,0 +1,226 @@
+use super::chunked::ChunkedVec;
+use super::fixed_point::{Price, Total};
+use super::trade_index::ENTRY_BYTES;
+
+// Heap cost of one point of each history
+const PRICE_POINT_BYTES: usize = std::mem::size_of::<(u64, Price)>();
+const VOLUME_POINT_BYTES: usize = std::mem::size_of::<(u64, Total)>();
+
+/// Folds points older than `after_ms` into buckets of `bucket_ms`, keeping
+/// the last price and the summed volume of each bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Downsampling {
+    pub after_ms: u64,
+    pub bucket_ms: u64,
+}
+
+/// Limits on how much trade history a symbol keeps, applied alike to the
+/// price and volume history and to the trade index. Downsampling is applied
+/// first; any limit still exceeded evicts the oldest points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RetentionPolicy {
+    pub max_age_ms: Option<u64>,
+    pub max_points: Option<usize>,
+    /// Approximate memory budget for both histories and the trade index of
+    /// a symbol together.
+    pub max_bytes: Option<usize>,
+    pub downsampling: Option<Downsampling>,
+}
+
+impl RetentionPolicy {
+    pub fn unbounded() -> Self {
+        Self::default()
+    }
+
+    // Points kept in each history and entries kept in the index. A trade
+    // adds at most one of each, so the budget is split evenly between them
+    pub(crate) fn point_limit(&self) -> Option<usize> {
+        let by_budget = self.max_bytes.map(|bytes| bytes / (PRICE_POINT_BYTES + VOLUME_POINT_BYTES + ENTRY_BYTES));
+        match (self.max_points, by_budget) {
+            (Some(points), Some(budget)) => Some(points.min(budget)),
+            (points, budget) => points.or(budget),
+        }
+    }
+}
+
+/// Millisecond-keyed price and volume history of a symbol, ordered by key.
+/// Kept in shared chunks so snapshots can take it without copying.
+#[derive(Debug, Default)]
+pub(crate) struct TradeHistory {
+    prices: ChunkedVec<(u64, Price)>,
+    volumes: ChunkedVec<(u64, Total)>,
+    // Everything before this key has already been folded into coarse buckets
+    downsampled_until_ms: u64,
+}
+
+impl TradeHistory {
+    /// Records a trade. `is_latest` is false for trades older than one
+    /// already seen, which must not overwrite a newer print in the same key.
+    pub(crate) fn record(&mut self, ms_timestamp: u64, price: Price, volume: Total, is_latest: bool, policy: &RetentionPolicy) {
+        let key = match policy.downsampling {
+            Some(downsampling) if ms_timestamp < self.downsampled_until_ms => bucket_start(ms_timestamp, downsampling.bucket_ms.max(1)),
+            _ => ms_timestamp,
+        };
+
+        upsert(&mut self.prices, key, price, |existing, price| if is_latest { price } else { existing });
+        upsert(&mut self.volumes, key, volume, |total, volume| total + volume);
+    }
+
+    /// Applies the retention policy relative to the newest recorded point.
+    pub(crate) fn enforce(&mut self, policy: &RetentionPolicy) {
+        let newest = match self.prices.last() {
+            Some((newest, _)) => *newest,
+            None => return,
+        };
+
+        if let Some(downsampling) = policy.downsampling {
+            self.downsample(newest, downsampling);
+        }
+
+        if let Some(max_age_ms) = policy.max_age_ms {
+            let cutoff = newest.saturating_sub(max_age_ms);
+            self.prices.remove_front(self.prices.partition_point(|(key, _)| *key < cutoff));
+            self.volumes.remove_front(self.volumes.partition_point(|(key, _)| *key < cutoff));
+        }
+
+        if let Some(limit) = policy.point_limit() {
+            self.prices.remove_front(self.prices.len().saturating_sub(limit));
+            self.volumes.remove_front(self.volumes.len().saturating_sub(limit));
+        }
+    }
+
+    fn downsample(&mut self, newest: u64, downsampling: Downsampling) {
+        let bucket_ms = downsampling.bucket_ms.max(1);
+        let cutoff = bucket_start(newest.saturating_sub(downsampling.after_ms), bucket_ms);
+        // Only fold whole buckets, and only once a new one has become eligible
+        if cutoff <= self.downsampled_until_ms {
+            return;
+        }
+
+        let start = bucket_start(self.downsampled_until_ms, bucket_ms);
+        fold_range(&mut self.prices, start, cutoff, bucket_ms, |_, price| price);
+        fold_range(&mut self.volumes, start, cutoff, bucket_ms, |total, volume| total + volume);
+        self.downsampled_until_ms = cutoff;
+    }
+
+    pub(crate) fn prices(&self) -> &ChunkedVec<(u64, Price)> {
+        &self.prices
+    }
+
+    pub(crate) fn volumes(&self) -> &ChunkedVec<(u64, Total)> {
+        &self.volumes
+    }
+
+    pub(crate) fn approximate_bytes(&self) -> usize {
+        self.prices.approximate_bytes() + self.volumes.approximate_bytes()
+    }
+}
+
+fn bucket_start(ms_timestamp: u64, bucket_ms: u64) -> u64 {
+    ms_timestamp - ms_timestamp % bucket_ms
+}
+
+// Adds a point at `key`, or combines it into the one already there. Keys
+// normally arrive in order, so the search is only paid for late trades
+fn upsert<T: Copy>(points: &mut ChunkedVec<(u64, T)>, key: u64, value: T, combine: impl Fn(T, T) -> T) {
+    match points.last().map(|(last, _)| *last) {
+        Some(last) if last == key => {
+            if let Some((_, existing)) = points.last_mut() {
+                *existing = combine(*existing, value);
+            }
+        },
+        Some(last) if last > key => {
+            let index = points.partition_point(|(existing, _)| *existing < key);
+            match points.get_mut(index) {
+                Some((existing_key, existing)) if *existing_key == key => *existing = combine(*existing, value),
+                _ => points.splice(index, index, vec![(key, value)]),
+            }
+        },
+        _ => points.push((key, value)),
+    }
+}
+
+// Replaces the points in [start, end) with one point per bucket, combining
+// values in key order.
+fn fold_range<T: Copy>(points: &mut ChunkedVec<(u64, T)>, start: u64, end: u64, bucket_ms: u64, combine: impl Fn(T, T) -> T) {
+    let from = points.partition_point(|(key, _)| *key < start);
+    let to = points.partition_point(|(key, _)| *key < end);
+
+    let mut folded: Vec<(u64, T)> = Vec::new();
+    for (key, value) in points.range(from, to) {
+        let bucket = bucket_start(*key, bucket_ms);
+        match folded.last_mut() {
+            Some((last_bucket, total)) if *last_bucket == bucket => *total = combine(*total, *value),
+            _ => folded.push((bucket, *value)),
+        }
+    }
+
+    points.splice(from, to, folded);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(keys: std::ops::Range<u64>, policy: &RetentionPolicy) -> TradeHistory {
+        let mut history = TradeHistory::default();
+        for key in keys {
+            history.record(key, Price(key as i64), Total::Units(1), true, policy);
+            history.enforce(policy);
+        }
+        history
+    }
+
+    fn keys(history: &TradeHistory) -> Vec<u64> {
+        history.prices().iter().map(|(key, _)| *key).collect()
+    }
+
+    #[test]
+    fn max_age_evicts_points_older_than_the_newest() {
+        let policy = RetentionPolicy { max_age_ms: Some(5), ..RetentionPolicy::unbounded() };
+        let history = history(0..10, &policy);
+
+        assert_eq!(keys(&history), vec![4, 5, 6, 7, 8, 9]);
+        assert_eq!(history.volumes().len(), 6);
+    }
+
+    #[test]
+    fn max_points_keeps_the_newest_points() {
+        let policy = RetentionPolicy { max_points: Some(3), ..RetentionPolicy::unbounded() };
+        let history = history(0..10, &policy);
+
+        assert_eq!(keys(&history), vec![7, 8, 9]);
+        assert_eq!(history.volumes().first(), Some(&(7, Total::Units(1))));
+    }
+
+    #[test]
+    fn max_bytes_is_split_between_histories_and_index() {
+        let per_trade = PRICE_POINT_BYTES + VOLUME_POINT_BYTES + ENTRY_BYTES;
+        let policy = RetentionPolicy { max_bytes: Some(4 * per_trade + 1), ..RetentionPolicy::unbounded() };
+        assert_eq!(policy.point_limit(), Some(4));
+
+        let policy = RetentionPolicy { max_points: Some(2), ..policy };
+        assert_eq!(policy.point_limit(), Some(2));
+        assert_eq!(keys(&history(0..10, &policy)), vec![8, 9]);
+    }
+
+    #[test]
+    fn old_points_are_folded_into_buckets() {
+        let policy = RetentionPolicy {
+            downsampling: Some(Downsampling { after_ms: 10, bucket_ms: 5 }),
+            ..RetentionPolicy::unbounded()
+        };
+        let mut history = history(0..21, &policy);
+
+        let mut expected: Vec<(u64, Price)> = vec![(0, Price(4)), (5, Price(9))];
+        expected.extend((10..21).map(|key| (key, Price(key as i64))));
+        assert_eq!(history.prices().iter().copied().collect::<Vec<_>>(), expected);
+        assert_eq!(history.volumes().first(), Some(&(0, Total::Units(5))));
+        assert_eq!(history.volumes().get(1), Some(&(5, Total::Units(5))));
+
+        // A late trade lands in its bucket without replacing the newer price
+        history.record(2, Price(100), Total::Units(3), false, &policy);
+        assert_eq!(history.prices().first(), Some(&(0, Price(4))));
+        assert_eq!(history.volumes().first(), Some(&(0, Total::Units(8))));
+    }
+}
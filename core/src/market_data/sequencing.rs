@@ -0,0 +1,372 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::error::{MarketDataError, MarketDataResult};
+use super::symbols::SymbolId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SequenceAnomaly {
+    Gap,
+    Duplicate,
+    Regression,
+    /// Every missing sequence number on the channel has since arrived.
+    Recovered,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SequenceStats {
+    pub gaps: u64,
+    pub missing_messages: u64,
+    pub duplicates: u64,
+    pub regressions: u64,
+    pub recoveries: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SequenceCheck {
+    InOrder,
+    /// A late message that fills part of an earlier gap.
+    Fill,
+    Gap { expected: u64 },
+    Duplicate { expected: u64 },
+    Regression { expected: u64 },
+}
+
+impl SequenceCheck {
+    pub(crate) fn is_duplicate(self) -> bool {
+        matches!(self, SequenceCheck::Duplicate { .. })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SequenceReport {
+    pub anomaly: SequenceAnomaly,
+    pub expected: u64,
+    pub received: u64,
+}
+
+/// Ordering state of one channel. Each channel has its own lock, held from
+/// classification until the message is queued.
+#[derive(Debug, Default)]
+pub(crate) struct ChannelState {
+    started: bool,
+    next_expected: u64,
+    // Open gaps as inclusive ranges, keyed by first missing sequence number
+    missing: BTreeMap<u64, u64>,
+    symbols: HashSet<SymbolId>,
+}
+
+impl ChannelState {
+    fn missing_range_containing(&self, sequence: u64) -> Option<(u64, u64)> {
+        self.missing.range(..=sequence).next_back()
+            .filter(|(_, end)| sequence <= **end)
+            .map(|(start, end)| (*start, *end))
+    }
+}
+
+// What readers need, kept apart from the channel locks so that queries never
+// wait behind a producer blocked on a full queue
+#[derive(Debug, Default)]
+struct SharedState {
+    symbols: HashMap<u32, HashSet<SymbolId>>,
+    // Channels with open gaps
+    gapped: HashSet<u32>,
+    // Symbols whose state is untrusted after a regression until explicitly recovered
+    reset_symbols: HashSet<SymbolId>,
+    stats: SequenceStats,
+}
+
+/// Tracks per-channel sequence numbers. Sequence numbers are checked at
+/// submission, before messages are split across shards, since a channel
+/// usually carries many symbols.
+#[derive(Debug)]
+pub(crate) struct SequenceTracker {
+    // How far below the expected number a message may be and still count as a
+    // duplicate rather than a sequence reset
+    reset_threshold: u64,
+    channels: RwLock<HashMap<u32, Arc<Mutex<ChannelState>>>>,
+    shared: Mutex<SharedState>,
+}
+
+impl SequenceTracker {
+    pub(crate) fn new(reset_threshold: u64) -> Self {
+        SequenceTracker {
+            reset_threshold,
+            channels: RwLock::new(HashMap::new()),
+            shared: Mutex::new(SharedState::default()),
+        }
+    }
+
+    /// State of a channel, created on first use. Lock it across
+    /// `classify`, the enqueue and `commit`.
+    pub(crate) fn channel(&self, channel_id: u32) -> MarketDataResult<Arc<Mutex<ChannelState>>> {
+        if let Some(channel) = self.channels.read()?.get(&channel_id) {
+            return Ok(Arc::clone(channel));
+        }
+        Ok(Arc::clone(self.channels.write()?.entry(channel_id).or_default()))
+    }
+
+    /// Rejects a sequence number with no successor, before the message is
+    /// enqueued.
+    pub(crate) fn classify(&self, channel: &ChannelState, sequence: u64) -> MarketDataResult<SequenceCheck> {
+        next_after(sequence)?;
+        if !channel.started {
+            return Ok(SequenceCheck::InOrder);
+        }
+        let expected = channel.next_expected;
+
+        let check = if sequence == expected {
+            SequenceCheck::InOrder
+        } else if sequence > expected {
+            SequenceCheck::Gap { expected }
+        } else if channel.missing_range_containing(sequence).is_some() {
+            SequenceCheck::Fill
+        } else if expected - sequence > self.reset_threshold {
+            SequenceCheck::Regression { expected }
+        } else {
+            SequenceCheck::Duplicate { expected }
+        };
+        Ok(check)
+    }
+
+    /// Applies a classification once the message has been accepted.
+    pub(crate) fn commit(&self, channel_id: u32, channel: &mut ChannelState, sequence: u64, symbol: SymbolId, check: SequenceCheck) -> MarketDataResult<Option<SequenceReport>> {
+        channel.started = true;
+        if channel.symbols.insert(symbol) {
+            self.shared.lock()?.symbols.entry(channel_id).or_default().insert(symbol);
+        }
+
+        let report = match check {
+            SequenceCheck::InOrder => {
+                channel.next_expected = next_after(sequence)?;
+                None
+            },
+            SequenceCheck::Gap { expected } => {
+                channel.missing.insert(expected, before(sequence)?);
+                channel.next_expected = next_after(sequence)?;
+                let mut shared = self.shared.lock()?;
+                shared.gapped.insert(channel_id);
+                shared.stats.gaps += 1;
+                shared.stats.missing_messages += sequence - expected;
+                Some(SequenceReport { anomaly: SequenceAnomaly::Gap, expected, received: sequence })
+            },
+            SequenceCheck::Fill => {
+                if let Some((start, end)) = channel.missing_range_containing(sequence) {
+                    channel.missing.remove(&start);
+                    if start < sequence {
+                        channel.missing.insert(start, before(sequence)?);
+                    }
+                    if sequence < end {
+                        channel.missing.insert(next_after(sequence)?, end);
+                    }
+                }
+                if channel.missing.is_empty() {
+                    let mut shared = self.shared.lock()?;
+                    shared.gapped.remove(&channel_id);
+                    shared.stats.recoveries += 1;
+                    Some(SequenceReport {
+                        anomaly: SequenceAnomaly::Recovered,
+                        expected: channel.next_expected,
+                        received: sequence,
+                    })
+                } else {
+                    None
+                }
+            },
+            SequenceCheck::Duplicate { expected } => {
+                self.shared.lock()?.stats.duplicates += 1;
+                Some(SequenceReport { anomaly: SequenceAnomaly::Duplicate, expected, received: sequence })
+            },
+            SequenceCheck::Regression { expected } => {
+                // The feed restarted; nothing before this point can be recovered
+                channel.missing.clear();
+                channel.next_expected = next_after(sequence)?;
+                let mut shared = self.shared.lock()?;
+                shared.gapped.remove(&channel_id);
+                shared.reset_symbols.extend(channel.symbols.iter().copied());
+                shared.stats.regressions += 1;
+                Some(SequenceReport { anomaly: SequenceAnomaly::Regression, expected, received: sequence })
+            },
+        };
+        Ok(report)
+    }
+
+    pub(crate) fn is_stale(&self, symbol: SymbolId) -> MarketDataResult<bool> {
+        let shared = self.shared.lock()?;
+        Ok(shared.reset_symbols.contains(&symbol)
+            || shared.gapped.iter().any(|channel_id| shared.symbols.get(channel_id).is_some_and(|symbols| symbols.contains(&symbol))))
+    }
+
+    pub(crate) fn stale_symbols(&self) -> MarketDataResult<HashSet<SymbolId>> {
+        let shared = self.shared.lock()?;
+        let mut stale = shared.reset_symbols.clone();
+        for channel_id in &shared.gapped {
+            if let Some(symbols) = shared.symbols.get(channel_id) {
+                stale.extend(symbols.iter().copied());
+            }
+        }
+        Ok(stale)
+    }
+
+    /// Clears the regression flag on a symbol, e.g. after it was rebuilt from
+    /// a snapshot. Symbols on channels with open gaps stay stale.
+    pub(crate) fn mark_recovered(&self, symbol: SymbolId) -> MarketDataResult<()> {
+        self.shared.lock()?.reset_symbols.remove(&symbol);
+        Ok(())
+    }
+
+    /// Gives up on the open gaps of a channel.
+    pub(crate) fn reset_channel(&self, channel_id: u32) -> MarketDataResult<()> {
+        let channel = match self.channels.read()?.get(&channel_id) {
+            Some(channel) => Arc::clone(channel),
+            None => return Ok(()),
+        };
+        channel.lock()?.missing.clear();
+        self.shared.lock()?.gapped.remove(&channel_id);
+        Ok(())
+    }
+
+    pub(crate) fn stats(&self) -> MarketDataResult<SequenceStats> {
+        Ok(self.shared.lock()?.stats)
+    }
+}
+
+// Checked so that a hostile sequence number fails validation instead of
+// panicking with the channel lock held
+fn next_after(sequence: u64) -> MarketDataResult<u64> {
+    sequence.checked_add(1)
+        .ok_or_else(|| MarketDataError::Validation(format!("sequence {} has no successor", sequence)))
+}
+
+fn before(sequence: u64) -> MarketDataResult<u64> {
+    sequence.checked_sub(1)
+        .ok_or_else(|| MarketDataError::Validation(format!("sequence {} has no predecessor", sequence)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::symbols::SymbolRegistry;
+
+    struct Feed {
+        tracker: SequenceTracker,
+        symbols: SymbolRegistry,
+    }
+
+    impl Feed {
+        fn new() -> Self {
+            Feed { tracker: SequenceTracker::new(100), symbols: SymbolRegistry::new() }
+        }
+
+        fn symbol(&self, name: &str) -> SymbolId {
+            self.symbols.intern(name).unwrap()
+        }
+
+        fn receive(&self, channel_id: u32, sequence: u64, symbol: &str) -> (SequenceCheck, Option<SequenceReport>) {
+            let channel = self.tracker.channel(channel_id).unwrap();
+            let mut state = channel.lock().unwrap();
+            let check = self.tracker.classify(&state, sequence).unwrap();
+            let report = self.tracker.commit(channel_id, &mut state, sequence, self.symbol(symbol), check).unwrap();
+            (check, report)
+        }
+    }
+
+    #[test]
+    fn in_order_messages_report_nothing() {
+        let feed = Feed::new();
+        for sequence in 10..20 {
+            assert_eq!(feed.receive(1, sequence, "A"), (SequenceCheck::InOrder, None));
+        }
+        assert_eq!(feed.tracker.stats().unwrap(), SequenceStats::default());
+    }
+
+    #[test]
+    fn gap_marks_channel_symbols_stale_until_filled() {
+        let feed = Feed::new();
+        feed.receive(1, 1, "A");
+        feed.receive(1, 2, "B");
+        feed.receive(2, 1, "C");
+
+        let (check, report) = feed.receive(1, 5, "A");
+        assert_eq!(check, SequenceCheck::Gap { expected: 3 });
+        assert_eq!(report, Some(SequenceReport { anomaly: SequenceAnomaly::Gap, expected: 3, received: 5 }));
+        assert!(feed.tracker.is_stale(feed.symbol("B")).unwrap());
+        assert!(!feed.tracker.is_stale(feed.symbol("C")).unwrap());
+
+        assert_eq!(feed.receive(1, 4, "A"), (SequenceCheck::Fill, None));
+        let (check, report) = feed.receive(1, 3, "A");
+        assert_eq!(check, SequenceCheck::Fill);
+        assert_eq!(report.map(|report| report.anomaly), Some(SequenceAnomaly::Recovered));
+        assert!(feed.tracker.stale_symbols().unwrap().is_empty());
+
+        let stats = feed.tracker.stats().unwrap();
+        assert_eq!((stats.gaps, stats.missing_messages, stats.recoveries), (1, 2, 1));
+    }
+
+    #[test]
+    fn fill_splits_a_gap() {
+        let feed = Feed::new();
+        feed.receive(1, 1, "A");
+        feed.receive(1, 10, "A");
+        assert_eq!(feed.receive(1, 5, "A"), (SequenceCheck::Fill, None));
+        // Both halves of the gap are still open
+        assert_eq!(feed.receive(1, 2, "A").0, SequenceCheck::Fill);
+        assert_eq!(feed.receive(1, 9, "A").0, SequenceCheck::Fill);
+        assert_eq!(feed.receive(1, 5, "A").0, SequenceCheck::Duplicate { expected: 11 });
+    }
+
+    #[test]
+    fn duplicates_and_regressions() {
+        let feed = Feed::new();
+        for sequence in 1..=200 {
+            feed.receive(1, sequence, "A");
+        }
+        let (check, report) = feed.receive(1, 150, "A");
+        assert_eq!(check, SequenceCheck::Duplicate { expected: 201 });
+        assert_eq!(report.map(|report| report.anomaly), Some(SequenceAnomaly::Duplicate));
+
+        // Further back than the reset threshold: the feed restarted
+        let (check, _) = feed.receive(1, 1, "B");
+        assert_eq!(check, SequenceCheck::Regression { expected: 201 });
+        assert!(feed.tracker.is_stale(feed.symbol("A")).unwrap());
+        assert_eq!(feed.receive(1, 2, "A").0, SequenceCheck::InOrder);
+
+        feed.tracker.mark_recovered(feed.symbol("A")).unwrap();
+        assert!(!feed.tracker.is_stale(feed.symbol("A")).unwrap());
+        assert!(feed.tracker.is_stale(feed.symbol("B")).unwrap());
+    }
+
+    #[test]
+    fn reset_channel_gives_up_on_gaps() {
+        let feed = Feed::new();
+        feed.receive(3, 1, "A");
+        feed.receive(3, 4, "A");
+        feed.tracker.reset_channel(3).unwrap();
+        assert!(!feed.tracker.is_stale(feed.symbol("A")).unwrap());
+        assert_eq!(feed.receive(3, 2, "A").0, SequenceCheck::Duplicate { expected: 5 });
+    }
+
+    #[test]
+    fn uncommitted_messages_can_be_resubmitted() {
+        let feed = Feed::new();
+        feed.receive(1, 1, "A");
+        let channel = feed.tracker.channel(1).unwrap();
+        // Classified, then rejected by backpressure and never committed
+        assert_eq!(feed.tracker.classify(&channel.lock().unwrap(), 2), Ok(SequenceCheck::InOrder));
+        assert_eq!(feed.receive(1, 2, "A"), (SequenceCheck::InOrder, None));
+    }
+
+    #[test]
+    fn last_sequence_number_is_rejected_without_poisoning_the_channel() {
+        let feed = Feed::new();
+        feed.receive(1, 1, "A");
+        let channel = feed.tracker.channel(1).unwrap();
+        assert!(matches!(feed.tracker.classify(&channel.lock().unwrap(), u64::MAX), Err(MarketDataError::Validation(_))));
+
+        let (check, _) = feed.receive(1, u64::MAX - 1, "A");
+        assert_eq!(check, SequenceCheck::Gap { expected: 2 });
+        assert_eq!(feed.receive(1, 2, "A").0, SequenceCheck::Fill);
+        assert!(feed.tracker.classify(&channel.lock().unwrap(), u64::MAX).is_err());
+        assert!(!channel.is_poisoned());
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use super::activity::{MessageCounts, OrderActivity};
+use super::bars::{BarSeries, BarSpec};
+use super::calendar::SessionSummary;
+use super::chunked::ChunkedVec;
+use super::classification::{ClassificationCounts, TradeSign};
+use super::error::{MarketDataError, MarketDataResult};
+use super::fixed_point::{Precision, Price, Total};
+use super::fair_value::FairValue;
+use super::kyle_lambda::KyleLambdaSeries;
+use super::ofi::OfiSeries;
+use super::order_book::BookLevel;
+use super::spreads::SpreadSeries;
+use super::symbols::SymbolId;
+use super::toxicity::ToxicityScore;
+use super::vpin::VpinSeries;
+use super::trade_index::TradeIndex;
+
+/// Immutable view of a symbol's state as of `last_update_time`, published by
+/// the shard worker between messages.
+#[derive(Debug, Clone)]
+pub struct SymbolSnapshot {
+    pub symbol: Arc<str>,
+    pub symbol_id: SymbolId,
+    /// Number of messages applied to the symbol when the snapshot was taken.
+    pub version: u64,
+    pub last_update_time: u64,
+    /// Grids the symbol's prices and quantities are kept on.
+    pub precision: Precision,
+    pub last_price: f64,
+    pub daily_volume: f64,
+    pub best_bid: Option<f64>,
+    pub best_ask: Option<f64>,
+    pub mid_price: Option<f64>,
+    pub spread: Option<f64>,
+    /// Top levels of each side, up to the configured snapshot depth.
+    pub bid_levels: Vec<BookLevel>,
+    pub ask_levels: Vec<BookLevel>,
+    pub order_count: usize,
+    pub last_trade_sign: Option<TradeSign>,
+    /// Trades signed by each classification method so far.
+    pub classification_counts: ClassificationCounts,
+    pub bars: Vec<BarSeries>,
+    pub message_counts: MessageCounts,
+    /// Message counts over each configured activity window.
+    pub order_activity: Vec<OrderActivity>,
+    /// Latest score, if toxicity scoring is configured and has run.
+    pub toxicity: Option<ToxicityScore>,
+    /// None unless VPIN is configured for the symbol.
+    pub vpin: Option<VpinSeries>,
+    /// None unless Kyle's lambda is configured.
+    pub kyle_lambda: Option<KyleLambdaSeries>,
+    /// None unless spread decomposition is configured.
+    pub spreads: Option<SpreadSeries>,
+    /// None unless order flow imbalance is configured.
+    pub ofi: Option<OfiSeries>,
+    /// Estimates as of the latest message, if fair values are configured and
+    /// the book is two-sided.
+    pub fair_value: Option<FairValue>,
+    pub session: Option<SessionSummary>,
+    pub prior_session: Option<SessionSummary>,
+    /// Last price per millisecond, on the price grid.
+    pub price_history: ChunkedVec<(u64, Price)>,
+    /// Volume per millisecond, on the quantity grid.
+    pub volume_history: ChunkedVec<(u64, Total)>,
+    pub history_bytes: usize,
+    /// Prefix sums for VWAP, TWAP and volume over arbitrary ranges.
+    pub trades: TradeIndex,
+}
+
+impl SymbolSnapshot {
+    pub fn price_history_range(&self, start_time: u64, end_time: u64) -> Vec<(u64, f64)> {
+        history_range(&self.price_history, start_time, end_time)
+            .map(|(ms, price)| (*ms, self.precision.price_to_f64(*price)))
+            .collect()
+    }
+
+    pub fn volume_history_range(&self, start_time: u64, end_time: u64) -> Vec<(u64, f64)> {
+        history_range(&self.volume_history, start_time, end_time)
+            .map(|(ms, volume)| (*ms, self.precision.quantity_total_to_f64(*volume)))
+            .collect()
+    }
+
+    /// Series built for `spec`; an error if the symbol has no such series.
+    pub fn bar_series(&self, spec: BarSpec) -> MarketDataResult<&BarSeries> {
+        self.bars.iter()
+            .find(|series| series.spec == spec)
+            .ok_or_else(|| MarketDataError::Validation(format!("{:?} bars are not configured for {}", spec, self.symbol)))
+    }
+}
+
+fn history_range<T: Copy>(points: &ChunkedVec<(u64, T)>, start_time: u64, end_time: u64) -> impl Iterator<Item = &(u64, T)> + '_ {
+    let from = points.partition_point(|(timestamp, _)| *timestamp < start_time);
+    let to = points.partition_point(|(timestamp, _)| *timestamp <= end_time);
+    points.range(from, to)
+}
+
+/// Published snapshots of one shard. The worker only ever tries to take the
+/// write lock, so readers can never hold up message processing; readers hold
+/// the read lock just long enough to clone an `Arc`.
+#[derive(Debug, Default)]
+pub(crate) struct SnapshotStore {
+    snapshots: RwLock<HashMap<SymbolId, Arc<SymbolSnapshot>>>,
+}
+
+impl SnapshotStore {
+    pub(crate) fn get(&self, symbol: SymbolId) -> MarketDataResult<Option<Arc<SymbolSnapshot>>> {
+        Ok(self.snapshots.read()?.get(&symbol).cloned())
+    }
+
+    /// Publishes without waiting; returns false if readers held the lock.
+    pub(crate) fn try_publish(&self, snapshots: &mut Vec<SymbolSnapshot>) -> bool {
+        match self.snapshots.try_write() {
+            Ok(mut published) => {
+                for snapshot in snapshots.drain(..) {
+                    published.insert(snapshot.symbol_id, Arc::new(snapshot));
+                }
+                true
+            },
+            Err(_) => false,
+        }
+    }
+
+    pub(crate) fn publish(&self, snapshots: Vec<SymbolSnapshot>) -> MarketDataResult<()> {
+        let mut published = self.snapshots.write()?;
+        for snapshot in snapshots {
+            published.insert(snapshot.symbol_id, Arc::new(snapshot));
+        }
+        Ok(())
+    }
+}
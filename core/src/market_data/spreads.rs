@@ -0,0 +1,372 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::chunked::ChunkedVec;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpreadConfig {
+    /// Delays after each trade at which the midquote is compared against it.
+    pub horizons: Vec<Duration>,
+    /// Length of the intervals averages are kept for, aligned to multiples
+    /// of it in event time.
+    pub interval: Duration,
+    /// Completed intervals kept; older ones are evicted.
+    pub max_intervals: usize,
+}
+
+impl Default for SpreadConfig {
+    fn default() -> Self {
+        SpreadConfig {
+            horizons: vec![Duration::from_secs(1), Duration::from_secs(5), Duration::from_secs(60)],
+            interval: Duration::from_secs(60),
+            max_intervals: 1_440,
+        }
+    }
+}
+
+/// Spread measures of one trade against the midquote prevailing before it,
+/// in price units. With `d` the aggressor direction, `m` the midquote and
+/// `m(h)` the midquote `h` later:
+/// effective = 2d(p - m), realized(h) = 2d(p - m(h)), impact(h) = 2d(m(h) - m),
+/// so effective = realized + impact at every horizon.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeSpread {
+    pub timestamp_ns: u64,
+    pub price: f64,
+    pub midquote: f64,
+    /// `2 * buy_fraction - 1`: 1 for a buy, -1 for a sell.
+    pub direction: f64,
+    pub quoted_spread: f64,
+    pub effective_spread: f64,
+    /// Per configured horizon; None where the book was one-sided then.
+    pub realized_spreads: Vec<Option<f64>>,
+    pub price_impacts: Vec<Option<f64>>,
+}
+
+/// Averages over the trades of one interval. Realized spread and impact
+/// only cover trades whose horizon has passed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpreadInterval {
+    pub start_ns: u64,
+    pub trade_count: u64,
+    horizons: Arc<[Duration]>,
+    quoted_sum: f64,
+    effective_sum: f64,
+    realized_sums: Vec<f64>,
+    impact_sums: Vec<f64>,
+    resolved: Vec<u64>,
+}
+
+impl SpreadInterval {
+    fn new(start_ns: u64, horizons: &Arc<[Duration]>) -> Self {
+        SpreadInterval {
+            start_ns,
+            trade_count: 0,
+            horizons: Arc::clone(horizons),
+            quoted_sum: 0.0,
+            effective_sum: 0.0,
+            realized_sums: vec![0.0; horizons.len()],
+            impact_sums: vec![0.0; horizons.len()],
+            resolved: vec![0; horizons.len()],
+        }
+    }
+
+    pub fn quoted_spread(&self) -> Option<f64> {
+        average(self.quoted_sum, self.trade_count)
+    }
+
+    pub fn effective_spread(&self) -> Option<f64> {
+        average(self.effective_sum, self.trade_count)
+    }
+
+    /// None for a horizon that is not configured or not yet resolved.
+    pub fn realized_spread(&self, horizon: Duration) -> Option<f64> {
+        let index = self.horizon_index(horizon)?;
+        average(self.realized_sums[index], self.resolved[index])
+    }
+
+    pub fn price_impact(&self, horizon: Duration) -> Option<f64> {
+        let index = self.horizon_index(horizon)?;
+        average(self.impact_sums[index], self.resolved[index])
+    }
+
+    fn horizon_index(&self, horizon: Duration) -> Option<usize> {
+        self.horizons.iter().position(|configured| *configured == horizon)
+    }
+}
+
+fn average(sum: f64, count: u64) -> Option<f64> {
+    if count == 0 {
+        return None;
+    }
+    Some(sum / count as f64)
+}
+
+/// Published spread decomposition of a symbol.
+#[derive(Debug, Clone)]
+pub struct SpreadSeries {
+    /// Intervals all of whose trades are resolved, oldest first.
+    pub settled: ChunkedVec<SpreadInterval>,
+    /// Later intervals, still collecting trades or waiting on horizons.
+    pub open: Vec<SpreadInterval>,
+    /// Most recent trade with every horizon resolved.
+    pub last_trade: Option<TradeSpread>,
+}
+
+impl SpreadSeries {
+    /// Intervals starting within `[start_ns, end_ns]`.
+    pub fn range(&self, start_ns: u64, end_ns: u64) -> Vec<SpreadInterval> {
+        let from = self.settled.partition_point(|interval| interval.start_ns < start_ns);
+        let to = self.settled.partition_point(|interval| interval.start_ns <= end_ns);
+        self.settled.range(from, to)
+            .chain(self.open.iter().filter(|interval| interval.start_ns >= start_ns && interval.start_ns <= end_ns))
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct PendingTrade {
+    spread: TradeSpread,
+    unresolved: usize,
+}
+
+/// Matches trades against later midquotes. Each horizon resolves trades in
+/// arrival order, at the first message more than the horizon after them.
+#[derive(Debug)]
+pub(crate) struct SpreadTracker {
+    horizons: Arc<[Duration]>,
+    horizons_ns: Vec<u64>,
+    interval_ns: u64,
+    max_intervals: usize,
+    pending: VecDeque<PendingTrade>,
+    // Trades popped from `pending`, so cursors can be absolute
+    popped: usize,
+    // Absolute index of the next trade each horizon has to resolve
+    cursors: Vec<usize>,
+    settled: ChunkedVec<SpreadInterval>,
+    open: VecDeque<SpreadInterval>,
+    last_trade: Option<TradeSpread>,
+}
+
+impl SpreadTracker {
+    pub(crate) fn new(config: &SpreadConfig) -> Self {
+        let horizons: Arc<[Duration]> = Arc::from(config.horizons.as_slice());
+        SpreadTracker {
+            horizons_ns: horizons.iter().map(|horizon| u64::try_from(horizon.as_nanos()).unwrap_or(u64::MAX)).collect(),
+            cursors: vec![0; horizons.len()],
+            horizons,
+            interval_ns: u64::try_from(config.interval.as_nanos()).unwrap_or(u64::MAX).max(1),
+            max_intervals: config.max_intervals,
+            pending: VecDeque::new(),
+            popped: 0,
+            settled: ChunkedVec::new(),
+            open: VecDeque::new(),
+            last_trade: None,
+        }
+    }
+
+    /// Called before a message at `timestamp_ns` is applied, with the
+    /// midquote prevailing until then. Appends trades it completes.
+    pub(crate) fn advance(&mut self, timestamp_ns: u64, midquote: Option<f64>, completed: &mut Vec<TradeSpread>) {
+        let interval_ns = self.interval_ns;
+        for (horizon, horizon_ns) in self.horizons_ns.iter().enumerate() {
+            while let Some(pending) = self.pending.get_mut(self.cursors[horizon] - self.popped) {
+                if pending.spread.timestamp_ns.saturating_add(*horizon_ns) >= timestamp_ns {
+                    break;
+                }
+                let spread = &mut pending.spread;
+                let realized = midquote.map(|midquote| 2.0 * spread.direction * (spread.price - midquote));
+                let impact = midquote.map(|midquote| 2.0 * spread.direction * (midquote - spread.midquote));
+                spread.realized_spreads[horizon] = realized;
+                spread.price_impacts[horizon] = impact;
+                pending.unresolved -= 1;
+                self.cursors[horizon] += 1;
+
+                if let (Some(realized), Some(impact)) = (realized, impact) {
+                    let start_ns = spread.timestamp_ns - spread.timestamp_ns % interval_ns;
+                    if let Some(interval) = self.open.iter_mut().find(|interval| interval.start_ns == start_ns) {
+                        interval.realized_sums[horizon] += realized;
+                        interval.impact_sums[horizon] += impact;
+                        interval.resolved[horizon] += 1;
+                    }
+                }
+            }
+        }
+
+        while self.pending.front().is_some_and(|pending| pending.unresolved == 0) {
+            if let Some(pending) = self.pending.pop_front() {
+                self.popped += 1;
+                self.last_trade = Some(pending.spread.clone());
+                completed.push(pending.spread);
+            }
+        }
+        self.settle(timestamp_ns);
+    }
+
+    /// Records a trade against the quotes prevailing before it. Trades
+    /// without a two-sided book are skipped.
+    pub(crate) fn add_trade(&mut self, timestamp_ns: u64, price: f64, quote: Option<(f64, f64)>, buy_fraction: f64, completed: &mut Vec<TradeSpread>) {
+        let (bid, ask) = match quote {
+            Some(quote) => quote,
+            None => return,
+        };
+        let midquote = (bid + ask) / 2.0;
+        let direction = 2.0 * buy_fraction - 1.0;
+        let spread = TradeSpread {
+            timestamp_ns,
+            price,
+            midquote,
+            direction,
+            quoted_spread: ask - bid,
+            effective_spread: 2.0 * direction * (price - midquote),
+            realized_spreads: vec![None; self.horizons.len()],
+            price_impacts: vec![None; self.horizons.len()],
+        };
+
+        let start_ns = self.interval_start(timestamp_ns);
+        let index = self.open.partition_point(|interval| interval.start_ns < start_ns);
+        if self.open.get(index).is_none_or(|interval| interval.start_ns != start_ns) {
+            // A late trade for an interval already settled is left out
+            if self.settled.last().is_some_and(|interval| interval.start_ns >= start_ns) {
+                return;
+            }
+            self.open.insert(index, SpreadInterval::new(start_ns, &self.horizons));
+        }
+        let interval = &mut self.open[index];
+        interval.trade_count += 1;
+        interval.quoted_sum += spread.quoted_spread;
+        interval.effective_sum += spread.effective_spread;
+
+        if self.horizons.is_empty() {
+            self.last_trade = Some(spread.clone());
+            completed.push(spread);
+            return;
+        }
+        self.pending.push_back(PendingTrade { spread, unresolved: self.horizons.len() });
+    }
+
+    pub(crate) fn series(&self) -> SpreadSeries {
+        SpreadSeries {
+            settled: self.settled.clone(),
+            open: self.open.iter().cloned().collect(),
+            last_trade: self.last_trade.clone(),
+        }
+    }
+
+    fn interval_start(&self, timestamp_ns: u64) -> u64 {
+        timestamp_ns - timestamp_ns % self.interval_ns
+    }
+
+    // Moves intervals that have ended and have no pending trades left. Trades
+    // are pending in arrival order, so a late one may find its interval gone
+    fn settle(&mut self, timestamp_ns: u64) {
+        let oldest_pending = self.pending.front().map(|pending| pending.spread.timestamp_ns);
+        while let Some(interval) = self.open.front() {
+            let end_ns = interval.start_ns.saturating_add(self.interval_ns);
+            if end_ns > timestamp_ns || oldest_pending.is_some_and(|oldest| oldest < end_ns) {
+                break;
+            }
+            if let Some(interval) = self.open.pop_front() {
+                self.settled.push(interval);
+                self.settled.remove_front(self.settled.len().saturating_sub(self.max_intervals.max(1)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HORIZONS: [Duration; 3] = [Duration::from_nanos(10), Duration::from_nanos(20), Duration::from_nanos(50)];
+
+    fn tracker() -> SpreadTracker {
+        SpreadTracker::new(&SpreadConfig { horizons: HORIZONS.to_vec(), interval: Duration::from_nanos(100), max_intervals: 10 })
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    // A buy and a sell against a 100 mid, then the mid at each later message
+    fn run(tracker: &mut SpreadTracker) -> Vec<TradeSpread> {
+        let mut completed = Vec::new();
+        tracker.add_trade(0, 100.05, Some((99.98, 100.02)), 1.0, &mut completed);
+        tracker.add_trade(5, 99.99, Some((99.98, 100.02)), 0.0, &mut completed);
+        let mids = [(11, Some(100.03)), (16, Some(100.01)), (21, None), (26, Some(99.97)), (51, Some(100.1)), (56, Some(100.2))];
+        for (timestamp_ns, midquote) in mids {
+            tracker.advance(timestamp_ns, midquote, &mut completed);
+        }
+        completed
+    }
+
+    #[test]
+    fn effective_is_realized_plus_impact_at_every_horizon() {
+        let completed = run(&mut tracker());
+        assert_eq!(completed.len(), 2);
+
+        for trade in &completed {
+            for (realized, impact) in trade.realized_spreads.iter().zip(&trade.price_impacts) {
+                if let (Some(realized), Some(impact)) = (realized, impact) {
+                    assert!((trade.effective_spread - (realized + impact)).abs() < 1e-9);
+                }
+            }
+        }
+
+        let buy = &completed[0];
+        assert_eq!((buy.midquote, buy.direction), (100.0, 1.0));
+        assert_close(Some(buy.quoted_spread), 0.04);
+        assert_close(Some(buy.effective_spread), 0.1);
+        assert_close(buy.realized_spreads[0], 0.04);
+        assert_close(buy.price_impacts[0], 0.06);
+        // The book was one-sided when the 20ns horizon passed
+        assert_eq!((buy.realized_spreads[1], buy.price_impacts[1]), (None, None));
+        assert_close(buy.realized_spreads[2], -0.1);
+        assert_close(buy.price_impacts[2], 0.2);
+
+        let sell = &completed[1];
+        assert_eq!(sell.direction, -1.0);
+        assert_close(Some(sell.effective_spread), 0.02);
+        assert_close(sell.realized_spreads[1], -0.04);
+        assert_close(sell.price_impacts[1], 0.06);
+        assert_close(sell.realized_spreads[2], 0.42);
+        assert_close(sell.price_impacts[2], -0.4);
+    }
+
+    #[test]
+    fn interval_averages_decompose_once_every_trade_is_resolved() {
+        let mut tracker = tracker();
+        run(&mut tracker);
+        assert!(tracker.series().settled.is_empty());
+        tracker.advance(100, Some(100.2), &mut Vec::new());
+
+        let series = tracker.series();
+        assert_eq!(series.settled.len(), 1);
+        assert_eq!(series.last_trade.map(|trade| trade.timestamp_ns), Some(5));
+        let interval = series.settled.first().unwrap();
+        assert_eq!(interval.trade_count, 2);
+        assert_close(interval.quoted_spread(), 0.04);
+        assert_close(interval.effective_spread(), 0.06);
+        for horizon in [HORIZONS[0], HORIZONS[2]] {
+            let sum = interval.realized_spread(horizon).unwrap() + interval.price_impact(horizon).unwrap();
+            assert_close(Some(sum), 0.06);
+        }
+        // Only the sell resolved against a two-sided book at 20ns
+        assert_close(interval.realized_spread(HORIZONS[1]), -0.04);
+        assert_eq!(interval.realized_spread(Duration::from_nanos(30)), None);
+    }
+
+    #[test]
+    fn trades_without_a_two_sided_book_are_skipped() {
+        let mut tracker = tracker();
+        let mut completed = Vec::new();
+        tracker.add_trade(0, 100.0, None, 1.0, &mut completed);
+        tracker.advance(100, Some(100.0), &mut completed);
+
+        assert!(completed.is_empty());
+        assert!(tracker.series().open.is_empty());
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use super::error::{MarketDataError, MarketDataResult};
+
+/// Compact handle for an interned ticker. Ids are dense, assigned in the
+/// order symbols are first seen, and never reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SymbolId(u32);
+
+impl SymbolId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for SymbolId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Interned {
+    ids: HashMap<Arc<str>, SymbolId>,
+    names: Vec<Arc<str>>,
+}
+
+/// Maps tickers to `SymbolId`s. Lookups of known symbols only take the read
+/// lock; the write lock is taken once per new symbol.
+#[derive(Debug, Default)]
+pub struct SymbolRegistry {
+    // Append-only, so a panic while it is held cannot leave it half-updated
+    // and poisoning is ignored
+    interned: RwLock<Interned>,
+    // Symbols interned so far, so ids can be checked without the lock
+    count: AtomicUsize,
+}
+
+impl SymbolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id of `symbol`, assigning one if it has not been seen.
+    pub fn intern(&self, symbol: &str) -> MarketDataResult<SymbolId> {
+        if let Some(id) = self.get(symbol) {
+            return Ok(id);
+        }
+
+        let mut interned = self.interned.write().unwrap_or_else(PoisonError::into_inner);
+        // Another thread may have interned it between the two locks
+        if let Some(id) = interned.ids.get(symbol) {
+            return Ok(*id);
+        }
+        let id = u32::try_from(interned.names.len())
+            .map(SymbolId)
+            .map_err(|_| MarketDataError::Validation("symbol registry is full".to_string()))?;
+        let name: Arc<str> = Arc::from(symbol);
+        interned.ids.insert(Arc::clone(&name), id);
+        interned.names.push(name);
+        self.count.store(interned.names.len(), Ordering::Release);
+        Ok(id)
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<SymbolId> {
+        self.interned.read().unwrap_or_else(PoisonError::into_inner).ids.get(symbol).copied()
+    }
+
+    /// True if `id` was assigned by this registry.
+    pub fn contains(&self, id: SymbolId) -> bool {
+        id.index() < self.count.load(Ordering::Acquire)
+    }
+
+    pub fn name(&self, id: SymbolId) -> Option<Arc<str>> {
+        self.interned.read().unwrap_or_else(PoisonError::into_inner).names.get(id.index()).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::VecDeque;
+
+/// Input to the toxicity score, each measured over the recent window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ToxicityFactor {
+    /// Cancels per trade among recent cancels and trades; with no trades,
+    /// the number of cancels.
+    CancelTradeRatio,
+    /// Absolute buy/sell imbalance of added order volume, from 0 to 1.
+    OrderImbalance,
+    /// Mean relative move of the mid across a trade.
+    PriceImpact,
+    /// Standard deviation of log returns between trades.
+    Volatility,
+    /// Share of added orders larger than `large_order_multiple` times the
+    /// window's average size.
+    LargeOrders,
+}
+
+/// A factor's raw value is divided by `normalizer` and capped at 1, then
+/// scaled by `weight`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FactorWeight {
+    pub factor: ToxicityFactor,
+    pub normalizer: f64,
+    pub weight: f64,
+}
+
+impl FactorWeight {
+    pub fn new(factor: ToxicityFactor, normalizer: f64, weight: f64) -> Self {
+        FactorWeight { factor, normalizer, weight }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToxicityConfig {
+    pub factors: Vec<FactorWeight>,
+    /// Flow is toxic when the summed contributions exceed this.
+    pub threshold: f64,
+    /// Added orders, trades, and cancels-or-trades each kept for the factors.
+    pub window: usize,
+    /// Messages between rescorings.
+    pub update_every: usize,
+    pub large_order_multiple: f64,
+}
+
+impl Default for ToxicityConfig {
+    /// The weights and normalizers of the original Python detector.
+    fn default() -> Self {
+        ToxicityConfig {
+            factors: vec![
+                FactorWeight::new(ToxicityFactor::CancelTradeRatio, 10.0, 0.25),
+                FactorWeight::new(ToxicityFactor::OrderImbalance, 1.0, 0.20),
+                FactorWeight::new(ToxicityFactor::PriceImpact, 0.0005, 0.20),
+                FactorWeight::new(ToxicityFactor::Volatility, 0.002, 0.15),
+                FactorWeight::new(ToxicityFactor::LargeOrders, 0.5, 0.20),
+            ],
+            threshold: 0.6,
+            window: 100,
+            update_every: 10,
+            large_order_multiple: 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FactorContribution {
+    pub factor: ToxicityFactor,
+    pub value: f64,
+    /// `value` normalized to at most 1.
+    pub score: f64,
+    /// `score` times the factor's weight.
+    pub contribution: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToxicityScore {
+    pub timestamp_ns: u64,
+    /// Sum of the factor contributions.
+    pub score: f64,
+    pub is_toxic: bool,
+    /// Distance of the score from the verdict it did not reach: the score
+    /// when toxic, one minus it otherwise.
+    pub confidence: f64,
+    pub factors: Vec<FactorContribution>,
+}
+
+/// What the processor tells the engine about each applied message.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FlowObservation {
+    Add { quantity: f64, is_buy: bool },
+    Modify,
+    Cancel,
+    Trade { price: f64, mid_before: Option<f64>, mid_after: Option<f64> },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TradeObservation {
+    price: f64,
+    impact: Option<f64>,
+}
+
+/// Per-symbol windows the score is computed from.
+#[derive(Debug)]
+pub(crate) struct ToxicityState {
+    config: ToxicityConfig,
+    adds: VecDeque<(f64, bool)>,
+    trades: VecDeque<TradeObservation>,
+    // Recent cancels and trades in arrival order, true for a cancel
+    cancels_and_trades: VecDeque<bool>,
+    cancels: usize,
+    since_update: usize,
+}
+
+impl ToxicityState {
+    pub(crate) fn new(config: ToxicityConfig) -> Self {
+        ToxicityState {
+            config,
+            adds: VecDeque::new(),
+            trades: VecDeque::new(),
+            cancels_and_trades: VecDeque::new(),
+            cancels: 0,
+            since_update: 0,
+        }
+    }
+
+    /// Records a message; returns a fresh score every `update_every` messages.
+    pub(crate) fn observe(&mut self, observation: FlowObservation, timestamp_ns: u64) -> Option<ToxicityScore> {
+        let window = self.config.window.max(1);
+        match observation {
+            FlowObservation::Add { quantity, is_buy } => {
+                self.adds.push_back((quantity, is_buy));
+                if self.adds.len() > window {
+                    self.adds.pop_front();
+                }
+            },
+            FlowObservation::Modify => {},
+            FlowObservation::Cancel => self.push_cancel_or_trade(true, window),
+            FlowObservation::Trade { price, mid_before, mid_after } => {
+                let impact = match (mid_before, mid_after) {
+                    (Some(before), Some(after)) if before > 0.0 => Some((after - before).abs() / before),
+                    _ => None,
+                };
+                self.trades.push_back(TradeObservation { price, impact });
+                if self.trades.len() > window {
+                    self.trades.pop_front();
+                }
+                self.push_cancel_or_trade(false, window);
+            },
+        }
+
+        self.since_update += 1;
+        if self.since_update < self.config.update_every.max(1) {
+            return None;
+        }
+        self.since_update = 0;
+        Some(self.score(timestamp_ns))
+    }
+
+    fn push_cancel_or_trade(&mut self, is_cancel: bool, window: usize) {
+        self.cancels_and_trades.push_back(is_cancel);
+        self.cancels += usize::from(is_cancel);
+        if self.cancels_and_trades.len() > window && self.cancels_and_trades.pop_front() == Some(true) {
+            self.cancels -= 1;
+        }
+    }
+
+    fn score(&self, timestamp_ns: u64) -> ToxicityScore {
+        let factors: Vec<FactorContribution> = self.config.factors.iter()
+            .map(|weight| {
+                let value = self.factor_value(weight.factor);
+                let score = if weight.normalizer > 0.0 { (value / weight.normalizer).min(1.0) } else { 0.0 };
+                FactorContribution {
+                    factor: weight.factor,
+                    value,
+                    score,
+                    contribution: score * weight.weight,
+                }
+            })
+            .collect();
+        let score: f64 = factors.iter().map(|factor| factor.contribution).sum();
+        let is_toxic = score > self.config.threshold;
+        ToxicityScore {
+            timestamp_ns,
+            score,
+            is_toxic,
+            confidence: if is_toxic { score } else { 1.0 - score },
+            factors,
+        }
+    }
+
+    fn factor_value(&self, factor: ToxicityFactor) -> f64 {
+        match factor {
+            ToxicityFactor::CancelTradeRatio => {
+                let trades = self.cancels_and_trades.len() - self.cancels;
+                self.cancels as f64 / trades.max(1) as f64
+            },
+            ToxicityFactor::OrderImbalance => {
+                let (buy, sell) = self.adds.iter().fold((0.0, 0.0), |(buy, sell), (quantity, is_buy)| {
+                    if *is_buy { (buy + quantity, sell) } else { (buy, sell + quantity) }
+                });
+                if buy + sell > 0.0 { (buy - sell).abs() / (buy + sell) } else { 0.0 }
+            },
+            ToxicityFactor::PriceImpact => {
+                let impacts: Vec<f64> = self.trades.iter().filter_map(|trade| trade.impact).collect();
+                if impacts.is_empty() { 0.0 } else { impacts.iter().sum::<f64>() / impacts.len() as f64 }
+            },
+            ToxicityFactor::Volatility => {
+                let returns: Vec<f64> = self.trades.iter()
+                    .zip(self.trades.iter().skip(1))
+                    .filter(|(previous, trade)| previous.price > 0.0 && trade.price > 0.0)
+                    .map(|(previous, trade)| (trade.price / previous.price).ln())
+                    .collect();
+                standard_deviation(&returns)
+            },
+            ToxicityFactor::LargeOrders => {
+                if self.adds.is_empty() {
+                    return 0.0;
+                }
+                let average = self.adds.iter().map(|(quantity, _)| quantity).sum::<f64>() / self.adds.len() as f64;
+                if average <= 0.0 {
+                    return 0.0;
+                }
+                let large = self.adds.iter()
+                    .filter(|(quantity, _)| *quantity > self.config.large_order_multiple * average)
+                    .count();
+                large as f64 / self.adds.len() as f64
+            },
+        }
+    }
+}
+
+fn standard_deviation(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    (values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Adds, cancels and trades with the mid before and after each trade
+    fn observe_flow(state: &mut ToxicityState) -> Vec<Option<ToxicityScore>> {
+        let mut observations = vec![
+            FlowObservation::Add { quantity: 10.0, is_buy: true },
+            FlowObservation::Add { quantity: 10.0, is_buy: true },
+            FlowObservation::Add { quantity: 10.0, is_buy: false },
+            FlowObservation::Add { quantity: 50.0, is_buy: true },
+        ];
+        observations.extend([FlowObservation::Cancel; 3]);
+        observations.extend([
+            FlowObservation::Trade { price: 100.0, mid_before: Some(100.0), mid_after: Some(100.02) },
+            FlowObservation::Trade { price: 100.1, mid_before: Some(100.02), mid_after: Some(100.05) },
+            FlowObservation::Trade { price: 100.0, mid_before: Some(100.05), mid_after: Some(100.01) },
+        ]);
+        observations.into_iter()
+            .enumerate()
+            .map(|(timestamp_ns, observation)| state.observe(observation, timestamp_ns as u64))
+            .collect()
+    }
+
+    #[test]
+    fn default_config_matches_the_python_detector() {
+        let mut state = ToxicityState::new(ToxicityConfig::default());
+        let scores = observe_flow(&mut state);
+        assert!(scores[..9].iter().all(Option::is_none));
+        let score = scores[9].clone().unwrap();
+
+        // From toxic_flow_detector.py fed the same flow, with its price
+        // impact and volatility inputs computed from these trades
+        let expected = [
+            (ToxicityFactor::CancelTradeRatio, 1.0, 0.025),
+            (ToxicityFactor::OrderImbalance, 0.75, 0.150_000_000_000_000_02),
+            (ToxicityFactor::PriceImpact, 0.000_299_913_370_649_172_5, 0.119_965_348_259_669_01),
+            (ToxicityFactor::Volatility, 0.001_413_506_926_643_057, 0.106_013_019_498_229_26),
+            (ToxicityFactor::LargeOrders, 0.25, 0.1),
+        ];
+        for (factor, (name, value, contribution)) in score.factors.iter().zip(expected) {
+            assert_eq!(factor.factor, name);
+            assert!((factor.value - value).abs() < 1e-12, "{:?}: {}", name, factor.value);
+            assert!((factor.contribution - contribution).abs() < 1e-12, "{:?}: {}", name, factor.contribution);
+        }
+        assert!((score.score - 0.500_978_367_757_898_3).abs() < 1e-12);
+        assert!(!score.is_toxic);
+        assert!((score.confidence - 0.499_021_632_242_101_7).abs() < 1e-12);
+        assert_eq!(score.timestamp_ns, 9);
+    }
+
+    #[test]
+    fn threshold_and_weights_are_configurable() {
+        let config = ToxicityConfig {
+            factors: vec![FactorWeight::new(ToxicityFactor::OrderImbalance, 0.5, 1.0)],
+            threshold: 0.9,
+            update_every: 5,
+            ..ToxicityConfig::default()
+        };
+        let mut state = ToxicityState::new(config);
+        let scores: Vec<ToxicityScore> = observe_flow(&mut state).into_iter().flatten().collect();
+
+        assert_eq!(scores.len(), 2);
+        // Imbalance of 0.75 over a normalizer of 0.5 is capped at 1
+        assert_eq!(scores[1].factors.len(), 1);
+        assert_eq!(scores[1].factors[0].score, 1.0);
+        assert_eq!(scores[1].score, 1.0);
+        assert!(scores[1].is_toxic);
+        assert_eq!(scores[1].confidence, 1.0);
+    }
+
+    #[test]
+    fn cancels_leave_the_window_with_old_trades() {
+        let config = ToxicityConfig {
+            factors: vec![FactorWeight::new(ToxicityFactor::CancelTradeRatio, 10.0, 1.0)],
+            window: 2,
+            update_every: 1,
+            ..ToxicityConfig::default()
+        };
+        let mut state = ToxicityState::new(config);
+        let trade = FlowObservation::Trade { price: 100.0, mid_before: None, mid_after: None };
+
+        let ratio = |score: Option<ToxicityScore>| score.unwrap().factors[0].value;
+        // With no trades the ratio is the number of cancels
+        assert_eq!(ratio(state.observe(FlowObservation::Cancel, 1)), 1.0);
+        assert_eq!(ratio(state.observe(FlowObservation::Cancel, 2)), 2.0);
+        assert_eq!(ratio(state.observe(trade, 3)), 1.0);
+        assert_eq!(ratio(state.observe(trade, 4)), 0.0);
+    }
+}
@@ -0,0 +1,430 @@
+use super::chunked::ChunkedVec;
+use super::fixed_point::{Precision, Price, Quantity, Total};
+use super::retention::{Downsampling, RetentionPolicy};
+
+/// Heap cost of one entry of the index, for retention budgets.
+pub(crate) const ENTRY_BYTES: usize = std::mem::size_of::<Entry>();
+
+// Trades at one timestamp with running totals. Queries only take differences
+// of totals, so evicting old entries leaves the rest valid.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    timestamp_ns: u64,
+    // Price of the last trade applied at this timestamp
+    price: Price,
+    volume: Total,
+    notional: Total,
+    cum_volume: Total,
+    cum_notional: Total,
+    // Integral of the last-trade price over time up to this timestamp
+    cum_price_time: Total,
+}
+
+/// Prefix sums over a symbol's trades for range queries in logarithmic
+/// time. Ranges are inclusive on both ends, in nanoseconds. Under a
+/// downsampling policy, older trades are folded into one entry per bucket,
+/// so only ranges aligned to buckets stay exact there.
+#[derive(Debug, Clone, Default)]
+pub struct TradeIndex {
+    precision: Precision,
+    entries: ChunkedVec<Entry>,
+    // Entries before this timestamp have been folded into buckets of `bucket_ns`
+    downsampled_until_ns: u64,
+    bucket_ns: u64,
+}
+
+impl TradeIndex {
+    pub(crate) fn new(precision: Precision) -> Self {
+        TradeIndex {
+            precision,
+            entries: ChunkedVec::new(),
+            downsampled_until_ns: 0,
+            bucket_ns: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn first_timestamp(&self) -> Option<u64> {
+        self.entries.first().map(|entry| entry.timestamp_ns)
+    }
+
+    pub fn last_timestamp(&self) -> Option<u64> {
+        self.entries.last().map(|entry| entry.timestamp_ns)
+    }
+
+    /// Records a trade. Trades may arrive out of order; a late one costs a
+    /// pass over the entries after it.
+    pub(crate) fn record(&mut self, timestamp_ns: u64, price: Price, quantity: Quantity) {
+        let volume = self.precision.quantity_total(quantity);
+        let notional = self.precision.notional(price, quantity);
+
+        if self.last_timestamp().is_none_or(|last| timestamp_ns > last) {
+            let entry = self.next_entry(self.entries.last().copied(), timestamp_ns, price, volume, notional);
+            self.entries.push(entry);
+            return;
+        }
+        if self.last_timestamp() == Some(timestamp_ns) {
+            // Same timestamp as the newest entry: still in order
+            if let Some(entry) = self.entries.last_mut() {
+                entry.price = price;
+                entry.volume += volume;
+                entry.notional += notional;
+                entry.cum_volume += volume;
+                entry.cum_notional += notional;
+            }
+            return;
+        }
+
+        let timestamp_ns = self.folded_timestamp(timestamp_ns);
+        let index = self.position(timestamp_ns);
+        match self.entries.get_mut(index).filter(|entry| entry.timestamp_ns == timestamp_ns) {
+            Some(entry) => {
+                // Late trade at a known timestamp keeps that timestamp's price
+                entry.volume += volume;
+                entry.notional += notional;
+            },
+            None => {
+                let entry = Entry {
+                    timestamp_ns,
+                    price,
+                    volume,
+                    notional,
+                    cum_volume: Total::ZERO,
+                    cum_notional: Total::ZERO,
+                    cum_price_time: Total::ZERO,
+                };
+                self.entries.splice(index, index, vec![entry]);
+            },
+        }
+        self.recompute_from(index);
+    }
+
+    /// Applies the policy's downsampling, then drops the oldest entries
+    /// beyond its age or point limits.
+    pub(crate) fn enforce(&mut self, policy: &RetentionPolicy) {
+        let newest = match self.last_timestamp() {
+            Some(newest) => newest,
+            None => return,
+        };
+
+        if let Some(downsampling) = policy.downsampling {
+            self.downsample(newest, downsampling);
+        }
+
+        let mut evict = policy.point_limit().map_or(0, |limit| self.entries.len().saturating_sub(limit));
+        if let Some(max_age_ms) = policy.max_age_ms {
+            let cutoff_ns = newest.saturating_sub(max_age_ms.saturating_mul(1_000_000));
+            evict = evict.max(self.position(cutoff_ns));
+        }
+        self.entries.remove_front(evict);
+    }
+
+    // Folds entries into one per bucket, with the same cutoff as the trade
+    // history. A folded entry keeps the timestamp, price and running totals
+    // of the last entry in its bucket, so totals stay exact across it
+    fn downsample(&mut self, newest: u64, downsampling: Downsampling) {
+        let bucket_ns = downsampling.bucket_ms.max(1).saturating_mul(1_000_000);
+        let cutoff_ns = bucket_start(newest.saturating_sub(downsampling.after_ms.saturating_mul(1_000_000)), bucket_ns);
+        if cutoff_ns <= self.downsampled_until_ns {
+            return;
+        }
+
+        let from = self.position(bucket_start(self.downsampled_until_ns, bucket_ns));
+        let to = self.position(cutoff_ns);
+        let mut folded: Vec<Entry> = Vec::new();
+        for entry in self.entries.range(from, to) {
+            match folded.last_mut() {
+                Some(last) if bucket_start(last.timestamp_ns, bucket_ns) == bucket_start(entry.timestamp_ns, bucket_ns) => {
+                    *last = Entry {
+                        volume: last.volume + entry.volume,
+                        notional: last.notional + entry.notional,
+                        ..*entry
+                    };
+                },
+                _ => folded.push(*entry),
+            }
+        }
+        self.entries.splice(from, to, folded);
+        self.downsampled_until_ns = cutoff_ns;
+        self.bucket_ns = bucket_ns;
+    }
+
+    // A late trade in a folded bucket joins the bucket's entry
+    fn folded_timestamp(&self, timestamp_ns: u64) -> u64 {
+        if timestamp_ns >= self.downsampled_until_ns || self.bucket_ns == 0 {
+            return timestamp_ns;
+        }
+        let bucket = bucket_start(timestamp_ns, self.bucket_ns);
+        self.entries.get(self.position(bucket))
+            .filter(|entry| bucket_start(entry.timestamp_ns, self.bucket_ns) == bucket)
+            .map_or(timestamp_ns, |entry| entry.timestamp_ns)
+    }
+
+    pub(crate) fn approximate_bytes(&self) -> usize {
+        self.entries.len() * ENTRY_BYTES
+    }
+
+    /// Total traded quantity.
+    pub fn volume(&self, start_ns: u64, end_ns: u64) -> f64 {
+        let (volume, _) = self.totals(start_ns, end_ns);
+        self.precision.quantity_total_to_f64(volume)
+    }
+
+    pub fn vwap(&self, start_ns: u64, end_ns: u64) -> Option<f64> {
+        let (volume, notional) = self.totals(start_ns, end_ns);
+        self.precision.average_price(notional, volume)
+    }
+
+    /// Average of the last trade price over time. Before the first recorded
+    /// trade there is no price, so the range starts there at the earliest.
+    pub fn twap(&self, start_ns: u64, end_ns: u64) -> Option<f64> {
+        let start_ns = start_ns.max(self.first_timestamp()?);
+        if end_ns < start_ns {
+            return None;
+        }
+        if end_ns == start_ns {
+            return self.price_at(start_ns);
+        }
+
+        let integral = self.price_integral(end_ns)? - self.price_integral(start_ns)?;
+        Some(self.precision.price_time_to_f64(integral) / (end_ns - start_ns) as f64)
+    }
+
+    /// Share of the range's traded volume that `quantity` represents.
+    pub fn participation_rate(&self, start_ns: u64, end_ns: u64, quantity: f64) -> Option<f64> {
+        let volume = self.volume(start_ns, end_ns);
+        if volume <= 0.0 {
+            return None;
+        }
+        Some(quantity / volume)
+    }
+
+    /// VWAP from `anchor_ns` through the last trade.
+    pub fn anchored_vwap(&self, anchor_ns: u64) -> Option<f64> {
+        self.vwap(anchor_ns, self.last_timestamp()?)
+    }
+
+    /// VWAP over the `window_ns` ending at `now_ns`.
+    pub fn rolling_vwap(&self, now_ns: u64, window_ns: u64) -> Option<f64> {
+        self.vwap(now_ns.saturating_sub(window_ns), now_ns)
+    }
+
+    pub fn rolling_twap(&self, now_ns: u64, window_ns: u64) -> Option<f64> {
+        self.twap(now_ns.saturating_sub(window_ns), now_ns)
+    }
+
+    /// Last trade price at or before `timestamp_ns`.
+    pub fn price_at(&self, timestamp_ns: u64) -> Option<f64> {
+        self.last_at_or_before(timestamp_ns).map(|entry| self.precision.price_to_f64(entry.price))
+    }
+
+    /// Last trade price at each traded timestamp within the range.
+    pub fn prices(&self, start_ns: u64, end_ns: u64) -> impl Iterator<Item = (u64, f64)> + '_ {
+        self.entries.range(self.position(start_ns), self.entries.len())
+            .take_while(move |entry| entry.timestamp_ns <= end_ns)
+            .map(|entry| (entry.timestamp_ns, self.precision.price_to_f64(entry.price)))
+    }
+
+    // Volume and notional of the trades within the range
+    fn totals(&self, start_ns: u64, end_ns: u64) -> (Total, Total) {
+        if end_ns < start_ns {
+            return (Total::ZERO, Total::ZERO);
+        }
+        let first = match self.entries.get(self.position(start_ns)).copied() {
+            Some(first) if first.timestamp_ns <= end_ns => first,
+            _ => return (Total::ZERO, Total::ZERO),
+        };
+        let last = match self.last_at_or_before(end_ns) {
+            Some(last) => last,
+            None => return (Total::ZERO, Total::ZERO),
+        };
+        (
+            last.cum_volume - (first.cum_volume - first.volume),
+            last.cum_notional - (first.cum_notional - first.notional),
+        )
+    }
+
+    fn price_integral(&self, timestamp_ns: u64) -> Option<Total> {
+        let entry = self.last_at_or_before(timestamp_ns)?;
+        Some(entry.cum_price_time + self.precision.price_time(entry.price, timestamp_ns - entry.timestamp_ns))
+    }
+
+    fn last_at_or_before(&self, timestamp_ns: u64) -> Option<Entry> {
+        self.position(timestamp_ns.saturating_add(1)).checked_sub(1)
+            .and_then(|index| self.entries.get(index))
+            .copied()
+    }
+
+    // Index of the first entry at or after `timestamp_ns`
+    fn position(&self, timestamp_ns: u64) -> usize {
+        self.entries.partition_point(|entry| entry.timestamp_ns < timestamp_ns)
+    }
+
+    fn next_entry(&self, previous: Option<Entry>, timestamp_ns: u64, price: Price, volume: Total, notional: Total) -> Entry {
+        let entry = Entry {
+            timestamp_ns,
+            price,
+            volume,
+            notional,
+            cum_volume: volume,
+            cum_notional: notional,
+            cum_price_time: Total::ZERO,
+        };
+        match previous {
+            Some(previous) => accumulate(&self.precision, &previous, &entry),
+            None => entry,
+        }
+    }
+
+    // Rebuilds running totals from an index to the end. Totals restart from
+    // zero when the first entry moves, which queries cannot tell apart
+    fn recompute_from(&mut self, index: usize) {
+        let precision = self.precision;
+        let mut previous = index.checked_sub(1).and_then(|previous| self.entries.get(previous)).copied();
+        for index in index..self.entries.len() {
+            let entry = match self.entries.get_mut(index) {
+                Some(entry) => entry,
+                None => break,
+            };
+            *entry = match previous {
+                Some(previous) => accumulate(&precision, &previous, entry),
+                None => Entry {
+                    cum_volume: entry.volume,
+                    cum_notional: entry.notional,
+                    cum_price_time: Total::ZERO,
+                    ..*entry
+                },
+            };
+            previous = Some(*entry);
+        }
+    }
+}
+
+fn bucket_start(timestamp_ns: u64, bucket_ns: u64) -> u64 {
+    timestamp_ns - timestamp_ns % bucket_ns
+}
+
+// Running totals of `entry` given those of the entry before it
+fn accumulate(precision: &Precision, previous: &Entry, entry: &Entry) -> Entry {
+    Entry {
+        cum_volume: previous.cum_volume + entry.volume,
+        cum_notional: previous.cum_notional + entry.notional,
+        cum_price_time: previous.cum_price_time + precision.price_time(previous.price, entry.timestamp_ns - previous.timestamp_ns),
+        ..*entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixed_point::FixedScale;
+
+    const MS: u64 = 1_000_000;
+
+    fn index(trades: &[(u64, i64, i64)]) -> TradeIndex {
+        let mut index = TradeIndex::new(Precision::new(FixedScale::decimals(2), FixedScale::decimals(0)));
+        for &(timestamp_ns, price, quantity) in trades {
+            index.record(timestamp_ns, Price(price), Quantity(quantity));
+        }
+        index
+    }
+
+    #[test]
+    fn vwap_and_volume_over_inclusive_ranges() {
+        let index = index(&[(10, 10_000, 1), (20, 11_000, 3), (30, 12_000, 4)]);
+        assert_eq!(index.volume(10, 30), 8.0);
+        assert_eq!(index.volume(11, 29), 3.0);
+        assert_eq!(index.volume(31, 40), 0.0);
+        assert_eq!(index.vwap(10, 20), Some(107.5));
+        assert_eq!(index.vwap(20, 30), Some(115.0 + 5.0 / 7.0));
+        assert_eq!(index.vwap(21, 29), None);
+        assert_eq!(index.anchored_vwap(15), index.vwap(20, 30));
+        assert_eq!(index.participation_rate(10, 30, 2.0), Some(0.25));
+    }
+
+    #[test]
+    fn twap_weights_prices_by_time_held() {
+        let index = index(&[(100, 10_000, 1), (110, 11_000, 1), (130, 12_000, 1)]);
+        assert!((index.twap(100, 130).unwrap() - 320.0 / 3.0).abs() < 1e-9);
+        assert_eq!(index.twap(105, 115), Some(105.0));
+        // Held past the last trade
+        assert_eq!(index.twap(130, 140), Some(120.0));
+        // No price before the first trade
+        assert_eq!(index.twap(0, 110), Some(100.0));
+        assert_eq!(index.twap(0, 50), None);
+        assert_eq!(index.twap(110, 110), Some(110.0));
+        assert_eq!(index.price_at(129), Some(110.0));
+        assert_eq!(index.price_at(99), None);
+    }
+
+    #[test]
+    fn late_trades_match_in_order_recording() {
+        let in_order = index(&[(10, 10_000, 1), (20, 10_500, 2), (30, 11_000, 3), (40, 11_500, 4)]);
+        let late = index(&[(10, 10_000, 1), (40, 11_500, 4), (20, 10_500, 2), (30, 11_000, 3)]);
+        for (start, end) in [(0, 50), (15, 35), (20, 20), (25, 45)] {
+            assert_eq!(late.volume(start, end), in_order.volume(start, end));
+            assert_eq!(late.vwap(start, end), in_order.vwap(start, end));
+            assert_eq!(late.twap(start, end), in_order.twap(start, end));
+        }
+        let before_first = index(&[(20, 10_500, 2), (10, 10_000, 1)]);
+        assert_eq!(before_first.first_timestamp(), Some(10));
+        assert_eq!(before_first.volume(0, 20), 3.0);
+    }
+
+    #[test]
+    fn trades_at_one_timestamp_share_an_entry() {
+        let mut index = index(&[(10, 10_000, 1), (10, 10_200, 1), (20, 11_000, 1)]);
+        assert_eq!(index.prices(0, 30).collect::<Vec<_>>(), [(10, 102.0), (20, 110.0)]);
+        assert_eq!(index.vwap(10, 10), Some(101.0));
+
+        // A late trade at a known timestamp keeps that timestamp's price
+        index.record(10, Price(9_000), Quantity(2));
+        assert_eq!(index.price_at(10), Some(102.0));
+        assert_eq!(index.volume(10, 10), 4.0);
+    }
+
+    #[test]
+    fn eviction_keeps_remaining_ranges_exact() {
+        let mut index = index(&[(MS, 10_000, 1), (2 * MS, 11_000, 2), (3 * MS, 12_000, 3), (4 * MS, 13_000, 4)]);
+        let vwap = index.vwap(3 * MS, 4 * MS);
+        let twap = index.twap(3 * MS, 4 * MS);
+
+        index.enforce(&RetentionPolicy { max_points: Some(3), ..RetentionPolicy::unbounded() });
+        assert_eq!(index.first_timestamp(), Some(2 * MS));
+        index.enforce(&RetentionPolicy { max_age_ms: Some(1), ..RetentionPolicy::unbounded() });
+        assert_eq!(index.first_timestamp(), Some(3 * MS));
+
+        assert_eq!(index.vwap(3 * MS, 4 * MS), vwap);
+        assert_eq!(index.twap(3 * MS, 4 * MS), twap);
+        assert_eq!(index.volume(0, 5 * MS), 7.0);
+    }
+
+    #[test]
+    fn downsampling_folds_old_trades_into_buckets() {
+        // A trade every quarter millisecond for 10ms
+        let trades: Vec<(u64, i64, i64)> = (0..=40).map(|step| (step * MS / 4, 10_000 + step as i64, 1)).collect();
+        let mut index = index(&trades);
+        let vwap = index.vwap(0, 10 * MS);
+        let recent_twap = index.twap(5 * MS, 10 * MS);
+
+        let policy = RetentionPolicy {
+            downsampling: Some(Downsampling { after_ms: 5, bucket_ms: 1 }),
+            ..RetentionPolicy::unbounded()
+        };
+        index.enforce(&policy);
+        // Five folded buckets, then every trade from 5ms on
+        assert_eq!(index.entries.len(), 5 + 21);
+        assert_eq!(index.prices(0, 5 * MS - 1).count(), 5);
+        assert_eq!(index.vwap(0, 10 * MS), vwap);
+        assert_eq!(index.twap(5 * MS, 10 * MS), recent_twap);
+        assert_eq!(index.volume(MS, 2 * MS - 1), 4.0);
+
+        // A late trade in a folded bucket joins it
+        index.record(MS + 1, Price(10_000), Quantity(2));
+        assert_eq!(index.entries.len(), 5 + 21);
+        assert_eq!(index.volume(MS, 2 * MS - 1), 6.0);
+    }
+}
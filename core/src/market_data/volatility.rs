@@ -0,0 +1,303 @@
+use std::f64::consts::{FRAC_PI_2, LN_2};
+use std::time::Duration;
+
+use super::bars::{Bar, BarSpec};
+use super::error::{MarketDataError, MarketDataResult};
+use super::snapshot::SymbolSnapshot;
+use super::trade_index::TradeIndex;
+
+// Most grid points a sampled estimator will visit in one query
+const MAX_SAMPLES: u64 = 1_000_000;
+
+/// How to estimate the variance of log returns over a window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VolatilityEstimator {
+    /// Sum of squared returns between last trade prices sampled every
+    /// `sampling`. Sparse sampling keeps microstructure noise down.
+    RealizedVariance { sampling: Duration },
+    /// Sum of products of adjacent absolute returns, scaled by pi/2. Robust
+    /// to jumps.
+    BipowerVariation { sampling: Duration },
+    /// High-low range of each bar of a configured series.
+    Parkinson { bars: BarSpec },
+    /// Range plus open-to-close move of each bar; assumes no drift.
+    GarmanKlass { bars: BarSpec },
+    /// Range estimator that stays unbiased under drift.
+    RogersSatchell { bars: BarSpec },
+    /// Two-scale realized variance over every traded price: the average of
+    /// `subsamples` sparse estimates, less the noise measured at tick scale.
+    TwoScale { subsamples: usize },
+    /// Realized kernel over tick returns with Parzen weights out to
+    /// `bandwidth` lags.
+    RealizedKernel { bandwidth: usize },
+}
+
+/// Variance of log returns over the whole window, not annualized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolatilityEstimate {
+    pub variance: f64,
+    /// Returns or bars the estimate was built from.
+    pub observations: usize,
+}
+
+impl VolatilityEstimate {
+    pub fn volatility(&self) -> f64 {
+        self.variance.max(0.0).sqrt()
+    }
+}
+
+/// Estimates over `[start_ns, end_ns]`; None if there is too little data.
+pub(crate) fn estimate(snapshot: &SymbolSnapshot, estimator: VolatilityEstimator, start_ns: u64, end_ns: u64) -> MarketDataResult<Option<VolatilityEstimate>> {
+    let estimate = match estimator {
+        VolatilityEstimator::RealizedVariance { sampling } => {
+            let returns = sampled_returns(&snapshot.trades, sampling, start_ns, end_ns)?;
+            realized_variance(&returns)
+        },
+        VolatilityEstimator::BipowerVariation { sampling } => {
+            let returns = sampled_returns(&snapshot.trades, sampling, start_ns, end_ns)?;
+            bipower_variation(&returns)
+        },
+        VolatilityEstimator::Parkinson { bars } => {
+            let bars = snapshot.bar_series(bars)?.range(start_ns, end_ns);
+            sum_over_bars(&bars, |high_low, _, _, _| high_low * high_low / (4.0 * LN_2))
+        },
+        VolatilityEstimator::GarmanKlass { bars } => {
+            let bars = snapshot.bar_series(bars)?.range(start_ns, end_ns);
+            sum_over_bars(&bars, |high_low, close_open, _, _| {
+                0.5 * high_low * high_low - (2.0 * LN_2 - 1.0) * close_open * close_open
+            })
+        },
+        VolatilityEstimator::RogersSatchell { bars } => {
+            let bars = snapshot.bar_series(bars)?.range(start_ns, end_ns);
+            sum_over_bars(&bars, |_, _, (high_close, high_open), (low_close, low_open)| {
+                high_close * high_open + low_close * low_open
+            })
+        },
+        VolatilityEstimator::TwoScale { subsamples } => {
+            let log_prices = log_prices(&snapshot.trades, start_ns, end_ns);
+            two_scale(&log_prices, subsamples)
+        },
+        VolatilityEstimator::RealizedKernel { bandwidth } => {
+            let log_prices = log_prices(&snapshot.trades, start_ns, end_ns);
+            realized_kernel(&differences(&log_prices), bandwidth)
+        },
+    };
+    Ok(estimate)
+}
+
+// Log returns between last trade prices on a grid from `start_ns`, or from
+// the first trade if later, to `end_ns`, or to the last trade if earlier.
+// Past the last trade the price cannot move, so those steps would only
+// add zero returns
+fn sampled_returns(trades: &TradeIndex, sampling: Duration, start_ns: u64, end_ns: u64) -> MarketDataResult<Vec<f64>> {
+    let step = u64::try_from(sampling.as_nanos()).unwrap_or(u64::MAX);
+    if step == 0 {
+        return Err(MarketDataError::Validation("volatility sampling interval must be positive".to_string()));
+    }
+    let (first, last) = match (trades.first_timestamp(), trades.last_timestamp()) {
+        (Some(first), Some(last)) => (start_ns.max(first), end_ns.min(last)),
+        _ => return Ok(Vec::new()),
+    };
+    if last < first {
+        return Ok(Vec::new());
+    }
+    let samples = (last - first) / step + 1;
+    if samples > MAX_SAMPLES {
+        return Err(MarketDataError::Validation(format!(
+            "{} samples of {:?} exceed the limit of {}; use a longer sampling interval or a shorter range",
+            samples, sampling, MAX_SAMPLES,
+        )));
+    }
+
+    let mut log_prices = Vec::with_capacity(samples as usize);
+    let mut timestamp = first;
+    while timestamp <= last {
+        if let Some(price) = trades.price_at(timestamp).filter(|price| *price > 0.0) {
+            log_prices.push(price.ln());
+        }
+        timestamp = match timestamp.checked_add(step) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    Ok(differences(&log_prices))
+}
+
+fn log_prices(trades: &TradeIndex, start_ns: u64, end_ns: u64) -> Vec<f64> {
+    trades.prices(start_ns, end_ns)
+        .filter(|(_, price)| *price > 0.0)
+        .map(|(_, price)| price.ln())
+        .collect()
+}
+
+fn differences(values: &[f64]) -> Vec<f64> {
+    values.windows(2).map(|pair| pair[1] - pair[0]).collect()
+}
+
+fn realized_variance(returns: &[f64]) -> Option<VolatilityEstimate> {
+    if returns.is_empty() {
+        return None;
+    }
+    Some(VolatilityEstimate {
+        variance: returns.iter().map(|r| r * r).sum(),
+        observations: returns.len(),
+    })
+}
+
+fn bipower_variation(returns: &[f64]) -> Option<VolatilityEstimate> {
+    if returns.len() < 2 {
+        return None;
+    }
+    let products: f64 = returns.windows(2).map(|pair| pair[0].abs() * pair[1].abs()).sum();
+    Some(VolatilityEstimate {
+        variance: FRAC_PI_2 * products,
+        observations: returns.len(),
+    })
+}
+
+// Applies a per-bar estimator to the log high/low, close/open, and the high
+// and low against close and open
+fn sum_over_bars(bars: &[Bar], per_bar: impl Fn(f64, f64, (f64, f64), (f64, f64)) -> f64) -> Option<VolatilityEstimate> {
+    let mut variance = 0.0;
+    let mut observations = 0;
+    for bar in bars {
+        if bar.low <= 0.0 || bar.open <= 0.0 {
+            continue;
+        }
+        let high_low = (bar.high / bar.low).ln();
+        let close_open = (bar.close / bar.open).ln();
+        let high = ((bar.high / bar.close).ln(), (bar.high / bar.open).ln());
+        let low = ((bar.low / bar.close).ln(), (bar.low / bar.open).ln());
+        variance += per_bar(high_low, close_open, high, low);
+        observations += 1;
+    }
+    if observations == 0 {
+        return None;
+    }
+    Some(VolatilityEstimate { variance, observations })
+}
+
+// Zhang, Mykland and Ait-Sahalia (2005), with the small-sample adjustment
+fn two_scale(log_prices: &[f64], subsamples: usize) -> Option<VolatilityEstimate> {
+    let n = log_prices.len().checked_sub(1)?;
+    let k = subsamples.max(1);
+    if n < 2 * k {
+        return None;
+    }
+
+    let all: f64 = differences(log_prices).iter().map(|r| r * r).sum();
+    let sparse: f64 = (0..k)
+        .map(|offset| {
+            let grid: Vec<f64> = log_prices[offset..].iter().step_by(k).copied().collect();
+            differences(&grid).iter().map(|r| r * r).sum::<f64>()
+        })
+        .sum::<f64>() / k as f64;
+    let n_bar = (n - k + 1) as f64 / k as f64;
+    let ratio = n_bar / n as f64;
+    Some(VolatilityEstimate {
+        variance: (sparse - ratio * all) / (1.0 - ratio),
+        observations: n,
+    })
+}
+
+// Barndorff-Nielsen, Hansen, Lunde and Shephard (2008)
+fn realized_kernel(returns: &[f64], bandwidth: usize) -> Option<VolatilityEstimate> {
+    if returns.len() <= bandwidth {
+        return None;
+    }
+    let autocovariance = |lag: usize| -> f64 {
+        returns[lag..].iter().zip(returns).map(|(r, lagged)| r * lagged).sum()
+    };
+
+    let mut variance = autocovariance(0);
+    for lag in 1..=bandwidth {
+        let weight = parzen((lag - 1) as f64 / bandwidth as f64);
+        variance += 2.0 * weight * autocovariance(lag);
+    }
+    Some(VolatilityEstimate {
+        variance,
+        observations: returns.len(),
+    })
+}
+
+fn parzen(x: f64) -> f64 {
+    if x <= 0.5 {
+        1.0 - 6.0 * x * x + 6.0 * x * x * x
+    } else if x <= 1.0 {
+        2.0 * (1.0 - x).powi(3)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixed_point::{FixedScale, Precision};
+
+    // Reference values computed independently in Python
+    const PRICES: [f64; 9] = [100.0, 100.3, 100.5, 100.4, 100.8, 101.0, 101.3, 101.2, 101.6];
+
+    fn assert_close(actual: Option<VolatilityEstimate>, variance: f64, observations: usize) {
+        let actual = actual.unwrap();
+        assert!((actual.variance - variance).abs() < 1e-15, "{} != {}", actual.variance, variance);
+        assert_eq!(actual.observations, observations);
+    }
+
+    fn log_prices() -> Vec<f64> {
+        PRICES.iter().map(|price| price.ln()).collect()
+    }
+
+    fn trades(prices: &[(u64, f64)]) -> TradeIndex {
+        let precision = Precision::new(FixedScale::decimals(2), FixedScale::decimals(0));
+        let mut trades = TradeIndex::new(precision);
+        for &(timestamp_ns, price) in prices {
+            trades.record(timestamp_ns, precision.price(price).unwrap(), precision.quantity(1.0).unwrap());
+        }
+        trades
+    }
+
+    #[test]
+    fn sampled_estimators_use_the_last_price_at_each_step() {
+        let trades = trades(&[(0, 100.0), (10, 110.0), (20, 99.0), (30, 99.0)]);
+        let (up, down) = (1.1_f64.ln(), 0.9_f64.ln());
+
+        let returns = sampled_returns(&trades, Duration::from_nanos(10), 0, 100).unwrap();
+        assert_eq!(returns.len(), 3);
+        assert_close(realized_variance(&returns), up * up + down * down, 3);
+        assert_close(bipower_variation(&returns), FRAC_PI_2 * up.abs() * down.abs(), 3);
+
+        // Finer sampling only adds zero returns between trades
+        let returns = sampled_returns(&trades, Duration::from_nanos(5), 0, 100).unwrap();
+        assert_close(realized_variance(&returns), up * up + down * down, 6);
+
+        assert!(sampled_returns(&trades, Duration::ZERO, 0, 100).is_err());
+        assert_eq!(realized_variance(&[]), None);
+        assert_eq!(bipower_variation(&[up]), None);
+    }
+
+    #[test]
+    fn realized_variance_of_tick_returns() {
+        assert_close(realized_variance(&differences(&log_prices())), 5.900_431_383_325_45e-5, 8);
+    }
+
+    #[test]
+    fn two_scale_matches_reference() {
+        assert_close(two_scale(&log_prices(), 2), 4.957_039_937_195_26e-5, 8);
+        assert_eq!(two_scale(&log_prices(), 5), None);
+    }
+
+    #[test]
+    fn realized_kernel_matches_reference() {
+        assert_close(realized_kernel(&differences(&log_prices()), 2), 8.517_560_358_857_357e-5, 8);
+        assert_eq!(realized_kernel(&differences(&log_prices()), 8), None);
+    }
+
+    #[test]
+    fn parzen_weights() {
+        assert_eq!(parzen(0.0), 1.0);
+        assert_eq!(parzen(0.5), 0.25);
+        assert_eq!(parzen(1.0), 0.0);
+        assert_eq!(parzen(1.5), 0.0);
+    }
+}
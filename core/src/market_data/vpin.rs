@@ -0,0 +1,334 @@
+use std::collections::VecDeque;
+
+use super::chunked::ChunkedVec;
+use super::classification::{normal_cdf, TradeClassifier, TradeClassifierState};
+use super::fixed_point::{Precision, Price};
+
+/// Where each trade's buy volume comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VpinClassification {
+    /// The trade's aggressor sign: `is_buy` when reported, otherwise the
+    /// processor's configured classifier.
+    TradeSign,
+    /// Bulk volume classification on the standardized price change, with
+    /// the deviation taken over the last `window` changes.
+    BulkVolume { window: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VpinConfig {
+    /// Volume in each bucket.
+    pub bucket_volume: f64,
+    /// Buckets VPIN is averaged over.
+    pub window: usize,
+    pub classification: VpinClassification,
+    /// CDF levels at which VPIN counts as elevated and critical.
+    pub elevated_cdf: f64,
+    pub critical_cdf: f64,
+    /// Points kept in the series and used to fit the CDF.
+    pub max_points: usize,
+}
+
+impl Default for VpinConfig {
+    fn default() -> Self {
+        VpinConfig {
+            bucket_volume: 10_000.0,
+            window: 50,
+            classification: VpinClassification::TradeSign,
+            elevated_cdf: 0.9,
+            critical_cdf: 0.99,
+            max_points: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VpinAlertLevel {
+    Normal,
+    Elevated,
+    Critical,
+}
+
+/// VPIN as of a completed bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VpinPoint {
+    /// Trade that completed the bucket.
+    pub timestamp_ns: u64,
+    pub vpin: f64,
+    /// Where `vpin` falls in a lognormal fitted to the retained series; None
+    /// until there are enough points to fit.
+    pub cdf: Option<f64>,
+    pub alert: VpinAlertLevel,
+}
+
+/// Published VPIN series of a symbol.
+#[derive(Debug, Clone)]
+pub struct VpinSeries {
+    pub config: VpinConfig,
+    pub points: ChunkedVec<VpinPoint>,
+}
+
+impl VpinSeries {
+    pub fn latest(&self) -> Option<&VpinPoint> {
+        self.points.last()
+    }
+
+    /// Points whose bucket completed within `[start_ns, end_ns]`.
+    pub fn range(&self, start_ns: u64, end_ns: u64) -> Vec<VpinPoint> {
+        let from = self.points.partition_point(|point| point.timestamp_ns < start_ns);
+        let to = self.points.partition_point(|point| point.timestamp_ns <= end_ns);
+        self.points.range(from, to).copied().collect()
+    }
+}
+
+/// Fills volume buckets from the trade stream in arrival order. A trade that
+/// overflows a bucket is split, keeping its buy fraction.
+#[derive(Debug)]
+pub(crate) struct VpinCalculator {
+    config: VpinConfig,
+    classifier: TradeClassifierState,
+    bucket_filled: f64,
+    bucket_buy: f64,
+    // Order imbalance of the last `window` buckets and their sum
+    imbalances: VecDeque<f64>,
+    imbalance_sum: f64,
+    points: ChunkedVec<VpinPoint>,
+    // Sums of ln VPIN over the retained points, for the lognormal fit
+    log_sum: f64,
+    log_sum_sq: f64,
+    log_count: usize,
+}
+
+impl VpinCalculator {
+    pub(crate) fn new(config: VpinConfig, precision: Precision) -> Self {
+        VpinCalculator {
+            config,
+            classifier: TradeClassifierState::new(precision),
+            bucket_filled: 0.0,
+            bucket_buy: 0.0,
+            imbalances: VecDeque::new(),
+            imbalance_sum: 0.0,
+            points: ChunkedVec::new(),
+            log_sum: 0.0,
+            log_sum_sq: 0.0,
+            log_count: 0,
+        }
+    }
+
+    /// Adds a trade; appends to `completed` a point for each bucket it
+    /// completes once the window is full. A trade completing more buckets
+    /// than the window and the retained series hold only reports the last of
+    /// them, since the earlier ones would be evicted by the same trade.
+    pub(crate) fn add_trade(&mut self, timestamp_ns: u64, price: Price, quantity: f64, buy_fraction: f64, completed: &mut Vec<VpinPoint>) {
+        let buy_fraction = match self.config.classification {
+            VpinClassification::TradeSign => buy_fraction,
+            VpinClassification::BulkVolume { window } => self.classifier
+                .classify(TradeClassifier::BulkVolume { window }, price, None, None)
+                .buy_fraction,
+        };
+        if self.config.bucket_volume <= 0.0 {
+            return;
+        }
+
+        let bucket_volume = self.config.bucket_volume;
+        let mut remaining = quantity;
+        if self.bucket_filled > 0.0 {
+            remaining -= self.fill(remaining, buy_fraction, timestamp_ns, completed);
+        }
+
+        // Whole buckets are counted rather than filled one at a time, so a
+        // bucket volume far below the trade size cannot stall the worker
+        let whole = (remaining / bucket_volume).floor();
+        let kept = whole.min((self.config.window.max(1) - 1 + self.config.max_points.max(1)) as f64);
+        for _ in 0..kept as u64 {
+            self.fill(bucket_volume, buy_fraction, timestamp_ns, completed);
+        }
+
+        let rest = (remaining - whole * bucket_volume).clamp(0.0, bucket_volume);
+        if rest > 0.0 {
+            self.fill(rest, buy_fraction, timestamp_ns, completed);
+        }
+    }
+
+    pub(crate) fn series(&self) -> VpinSeries {
+        VpinSeries {
+            config: self.config,
+            points: self.points.clone(),
+        }
+    }
+
+    // Adds up to the rest of the open bucket, closing it once full; returns
+    // the quantity taken
+    fn fill(&mut self, quantity: f64, buy_fraction: f64, timestamp_ns: u64, completed: &mut Vec<VpinPoint>) -> f64 {
+        let taken = quantity.min(self.config.bucket_volume - self.bucket_filled);
+        self.bucket_filled += taken;
+        self.bucket_buy += taken * buy_fraction;
+
+        // Tolerate float dust so a bucket filled by many trades closes
+        if self.bucket_filled >= self.config.bucket_volume * (1.0 - 1e-12) {
+            if let Some(point) = self.close_bucket(timestamp_ns) {
+                completed.push(point);
+            }
+        }
+        taken
+    }
+
+    fn close_bucket(&mut self, timestamp_ns: u64) -> Option<VpinPoint> {
+        let sell = self.bucket_filled - self.bucket_buy;
+        let imbalance = (self.bucket_buy - sell).abs();
+        self.bucket_filled = 0.0;
+        self.bucket_buy = 0.0;
+
+        let window = self.config.window.max(1);
+        self.imbalances.push_back(imbalance);
+        self.imbalance_sum += imbalance;
+        if self.imbalances.len() > window {
+            if let Some(old) = self.imbalances.pop_front() {
+                self.imbalance_sum -= old;
+            }
+        }
+        if self.imbalances.len() < window {
+            return None;
+        }
+
+        let vpin = (self.imbalance_sum / (window as f64 * self.config.bucket_volume)).clamp(0.0, 1.0);
+        let cdf = self.fit_cdf(vpin);
+        let alert = match cdf {
+            Some(cdf) if cdf >= self.config.critical_cdf => VpinAlertLevel::Critical,
+            Some(cdf) if cdf >= self.config.elevated_cdf => VpinAlertLevel::Elevated,
+            _ => VpinAlertLevel::Normal,
+        };
+        let point = VpinPoint { timestamp_ns, vpin, cdf, alert };
+        self.push_point(point);
+        Some(point)
+    }
+
+    // Lognormal CDF of `vpin` fitted to the points before it
+    fn fit_cdf(&self, vpin: f64) -> Option<f64> {
+        if self.log_count < 2 {
+            return None;
+        }
+        if vpin <= 0.0 {
+            return Some(0.0);
+        }
+        let n = self.log_count as f64;
+        let mean = self.log_sum / n;
+        let variance = (self.log_sum_sq - self.log_sum * self.log_sum / n) / (n - 1.0);
+        if variance <= 0.0 {
+            return Some(if vpin.ln() >= mean { 1.0 } else { 0.0 });
+        }
+        Some(normal_cdf((vpin.ln() - mean) / variance.sqrt()))
+    }
+
+    fn push_point(&mut self, point: VpinPoint) {
+        self.points.push(point);
+        self.add_log(point.vpin, 1.0);
+        if self.points.len() > self.config.max_points.max(1) {
+            if let Some(evicted) = self.points.first().copied() {
+                self.points.remove_front(1);
+                self.add_log(evicted.vpin, -1.0);
+            }
+        }
+    }
+
+    // Zero VPIN has no log and is left out of the fit
+    fn add_log(&mut self, vpin: f64, sign: f64) {
+        if vpin <= 0.0 {
+            return;
+        }
+        let log = vpin.ln();
+        self.log_sum += sign * log;
+        self.log_sum_sq += sign * log * log;
+        if sign > 0.0 {
+            self.log_count += 1;
+        } else {
+            self.log_count -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fixed_point::FixedScale;
+
+    fn calculator(bucket_volume: f64, window: usize) -> VpinCalculator {
+        let config = VpinConfig { bucket_volume, window, ..VpinConfig::default() };
+        VpinCalculator::new(config, Precision::new(FixedScale::decimals(2), FixedScale::decimals(0)))
+    }
+
+    fn add(calculator: &mut VpinCalculator, timestamp_ns: u64, quantity: f64, buy_fraction: f64) -> Vec<f64> {
+        let mut completed = Vec::new();
+        calculator.add_trade(timestamp_ns, Price(10_000), quantity, buy_fraction, &mut completed);
+        assert!(completed.iter().all(|point| point.timestamp_ns == timestamp_ns));
+        completed.iter().map(|point| point.vpin).collect()
+    }
+
+    #[test]
+    fn overflowing_trades_are_split_across_buckets() {
+        let mut calculator = calculator(10.0, 1);
+
+        // 10 buys close the first bucket; the other 5 carry over
+        assert_eq!(add(&mut calculator, 1, 15.0, 1.0), vec![1.0]);
+        assert_eq!(add(&mut calculator, 2, 5.0, 0.0), vec![0.0]);
+        // One trade filling three buckets closes each of them
+        assert_eq!(add(&mut calculator, 3, 32.0, 0.75), vec![0.5, 0.5, 0.5]);
+        // Bucket with 2 at 0.75 and 8 sold: buy 1.5, sell 8.5
+        assert_eq!(add(&mut calculator, 4, 8.0, 0.0), vec![0.7]);
+        assert_eq!(calculator.series().points.len(), 6);
+    }
+
+    #[test]
+    fn vpin_averages_over_the_window_once_full() {
+        let mut calculator = calculator(10.0, 2);
+
+        assert!(add(&mut calculator, 1, 10.0, 1.0).is_empty());
+        assert_eq!(add(&mut calculator, 2, 10.0, 0.5), vec![0.5]);
+        assert_eq!(add(&mut calculator, 3, 10.0, 0.0), vec![0.5]);
+        assert_eq!(add(&mut calculator, 4, 10.0, 0.5), vec![0.5]);
+        assert_eq!(add(&mut calculator, 5, 10.0, 0.5), vec![0.0]);
+    }
+
+    #[test]
+    fn buckets_filled_by_many_small_trades_close_despite_rounding() {
+        let mut calculator = calculator(1.0, 1);
+        let closed: usize = (0..10).map(|i| add(&mut calculator, i, 0.1, 1.0).len()).sum();
+        assert_eq!(closed, 1);
+    }
+
+    #[test]
+    fn alerts_follow_the_fitted_cdf() {
+        let config = VpinConfig { bucket_volume: 10.0, window: 1, ..VpinConfig::default() };
+        let mut calculator = VpinCalculator::new(config, Precision::new(FixedScale::decimals(2), FixedScale::decimals(0)));
+        let mut completed = Vec::new();
+        for buy_fraction in [0.6, 0.55, 0.6, 0.55, 1.0] {
+            calculator.add_trade(0, Price(10_000), 10.0, buy_fraction, &mut completed);
+        }
+
+        assert_eq!(completed[0].cdf, None);
+        assert_eq!(completed[1].cdf, None);
+        let last = completed[4];
+        assert_eq!(last.vpin, 1.0);
+        assert!(last.cdf.unwrap() > 0.99);
+        assert_eq!(last.alert, VpinAlertLevel::Critical);
+    }
+
+    #[test]
+    fn tiny_buckets_are_counted_rather_than_filled_one_by_one() {
+        let bucket_volume = 2f64.powi(-30);
+        let mut calculator = calculator(bucket_volume, 3);
+        calculator.config.max_points = 5;
+
+        // 2^60 buckets in one trade
+        let vpins = add(&mut calculator, 1, 2f64.powi(30), 0.75);
+
+        // Only the buckets the series can hold are reported
+        assert_eq!(vpins, vec![0.5; 5]);
+        assert_eq!(calculator.series().points.len(), 5);
+
+        // The open bucket still carries over between trades
+        assert!(add(&mut calculator, 2, bucket_volume / 2.0, 1.0).is_empty());
+        assert_eq!(add(&mut calculator, 3, bucket_volume, 1.0).len(), 1);
+        assert_eq!(calculator.bucket_filled, bucket_volume / 2.0);
+    }
+}